@@ -0,0 +1,241 @@
+use ppppp_blob::{BlobError, BlobHasher, BlobId, Decoder, Encoder, Outboard, GROUP_LENGTH};
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobStoreError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("blob error: {0}")]
+    Blob(#[from] BlobError),
+}
+
+/// A store of blobs, as files in a directory, at paths from their ids.
+///
+/// Blobs are written to a temporary file, then moved into place once complete, so a blob which
+/// is in the store is always whole.
+#[derive(Debug)]
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, BlobStoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("tmp"))?;
+        Ok(Self { dir })
+    }
+
+    /// The path of a blob, in a directory from the first two characters of its id, to keep
+    /// directories small.
+    fn path(&self, blob_id: &BlobId) -> PathBuf {
+        let blob_id = blob_id.to_string();
+        self.dir.join(&blob_id[..2]).join(blob_id)
+    }
+
+    fn tmp_file(&self) -> Result<NamedTempFile, BlobStoreError> {
+        Ok(NamedTempFile::new_in(self.dir.join("tmp"))?)
+    }
+
+    fn persist(&self, file: NamedTempFile, blob_id: &BlobId) -> Result<(), BlobStoreError> {
+        let path = self.path(blob_id);
+        fs::create_dir_all(path.parent().unwrap())?;
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    /// Add a blob from a reader, returning its id.
+    pub fn add<R: Read>(&self, mut reader: R) -> Result<BlobId, BlobStoreError> {
+        let mut file = self.tmp_file()?;
+        let mut hasher = BlobHasher::new();
+        let mut buffer = vec![0; GROUP_LENGTH];
+        loop {
+            let length = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => length,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            hasher.update(&buffer[..length]);
+            file.write_all(&buffer[..length])?;
+        }
+        let blob_id = hasher.finalize().id().clone();
+        if !self.has(&blob_id) {
+            self.persist(file, &blob_id)?;
+        }
+        Ok(blob_id)
+    }
+
+    /// Add a blob from a verified stream, as sent by [`BlobStore::encode`].
+    ///
+    /// Each group is checked as it arrives, so a peer which sends a corrupt blob is caught early,
+    /// and nothing is stored unless the whole blob arrives.
+    pub fn add_verified<R: Read>(
+        &self,
+        blob_id: &BlobId,
+        mut reader: R,
+    ) -> Result<(), BlobStoreError> {
+        let mut file = self.tmp_file()?;
+        let mut decoder = Decoder::new(blob_id.clone());
+        let mut buffer = vec![0; GROUP_LENGTH];
+        loop {
+            let length = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => length,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            decoder.push(&buffer[..length]);
+            while let Some(group) = decoder.decode()? {
+                file.write_all(&group)?;
+            }
+        }
+        decoder.finish()?;
+        if !self.has(blob_id) {
+            self.persist(file, blob_id)?;
+        }
+        Ok(())
+    }
+
+    pub fn has(&self, blob_id: &BlobId) -> bool {
+        self.path(blob_id).is_file()
+    }
+
+    pub fn size(&self, blob_id: &BlobId) -> Result<Option<u64>, BlobStoreError> {
+        match fs::metadata(self.path(blob_id)) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn open_blob(&self, blob_id: &BlobId) -> Result<Option<File>, BlobStoreError> {
+        match File::open(self.path(blob_id)) {
+            Ok(file) => Ok(Some(file)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Get a blob, to read or to iterate as a stream of chunks.
+    pub fn get(&self, blob_id: &BlobId) -> Result<Option<BlobReader>, BlobStoreError> {
+        Ok(self.open_blob(blob_id)?.map(|file| BlobReader { file }))
+    }
+
+    /// Get a blob as a verified stream, which a peer can check as it arrives.
+    pub fn encode(&self, blob_id: &BlobId) -> Result<Option<Encoder<File>>, BlobStoreError> {
+        let Some(file) = self.open_blob(blob_id)? else {
+            return Ok(None);
+        };
+        let outboard = Outboard::compute(&file)?;
+        Ok(Some(outboard.encode(file)))
+    }
+
+    /// Remove a blob, returning whether it was stored.
+    pub fn rm(&self, blob_id: &BlobId) -> Result<bool, BlobStoreError> {
+        match fs::remove_file(self.path(blob_id)) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// A stored blob, which is a reader, or an iterator of chunks.
+#[derive(Debug)]
+pub struct BlobReader {
+    file: File,
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Iterator for BlobReader {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(GROUP_LENGTH);
+        match (&mut self.file)
+            .take(GROUP_LENGTH as u64)
+            .read_to_end(&mut chunk)
+        {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(chunk)),
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn add_get_rm() -> Result<(), BlobStoreError> {
+        let dir = tempdir()?;
+        let store = BlobStore::open(dir.path())?;
+        let blob = vec![7; 2 * GROUP_LENGTH + 3];
+
+        let blob_id = store.add(blob.as_slice())?;
+        assert_eq!(blob_id, BlobId::of(&blob));
+        assert!(store.has(&blob_id));
+        assert_eq!(store.size(&blob_id)?, Some(blob.len() as u64));
+
+        let chunks = store
+            .get(&blob_id)?
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.concat(), blob);
+
+        assert!(store.rm(&blob_id)?);
+        assert!(!store.has(&blob_id));
+        assert!(store.get(&blob_id)?.is_none());
+        assert!(!store.rm(&blob_id)?);
+        Ok(())
+    }
+
+    #[test]
+    fn add_verified() -> Result<(), BlobStoreError> {
+        let dir = tempdir()?;
+        let alice = BlobStore::open(dir.path().join("alice"))?;
+        let bob = BlobStore::open(dir.path().join("bob"))?;
+        let blob: Vec<u8> = (0..3 * GROUP_LENGTH).map(|i| i as u8).collect();
+        let blob_id = alice.add(blob.as_slice())?;
+
+        let encoded = alice
+            .encode(&blob_id)?
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()?
+            .concat();
+
+        let mut corrupt = encoded.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(matches!(
+            bob.add_verified(&blob_id, Cursor::new(corrupt)),
+            Err(BlobStoreError::Blob(BlobError::Corrupt { .. }))
+        ));
+        assert!(matches!(
+            bob.add_verified(&blob_id, &encoded[..encoded.len() - 1]),
+            Err(BlobStoreError::Blob(BlobError::Truncated))
+        ));
+        assert!(!bob.has(&blob_id));
+
+        bob.add_verified(&blob_id, Cursor::new(encoded))?;
+        let mut stored = Vec::new();
+        bob.get(&blob_id)?.unwrap().read_to_end(&mut stored)?;
+        assert_eq!(stored, blob);
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use ppppp_bytes::{impl_as_bytes_outputs, impl_from_bytes_inputs, AsBytes, FromBytes};
+use ppppp_crypto::Hash;
+use std::convert::Infallible;
+
+/// A blob id, the blake3 hash of the blob
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlobId(Hash);
+
+impl FromBytes<32> for BlobId {
+    type Error = Infallible;
+
+    fn from_bytes(bytes: &[u8; 32]) -> Result<Self, Self::Error> {
+        Ok(Self(Hash::from_bytes(bytes)?))
+    }
+}
+
+impl AsBytes<32> for BlobId {
+    fn as_bytes(&self) -> &[u8; 32] {
+        self.0.as_bytes()
+    }
+}
+
+impl_from_bytes_inputs!(BlobId, 32_usize);
+impl_as_bytes_outputs!(BlobId, 32_usize);
+
+impl BlobId {
+    pub fn from_hash(hash: Hash) -> Self {
+        Self(hash)
+    }
+
+    pub fn hash(&self) -> &Hash {
+        &self.0
+    }
+
+    /// Hash a blob which is already in memory.
+    pub fn of(blob: &[u8]) -> Self {
+        Self(Hash::from_bytes(blake3::hash(blob).as_bytes()).unwrap())
+    }
+}
@@ -0,0 +1,17 @@
+//! Blobs, the images and files which msgs refer to by id.
+//!
+//! - [ssbc/ssb-blobs](https://github.com/ssbc/ssb-blobs)
+//!
+//! A blob id is the blake3 hash of the blob. Blake3 hashes are the root of a tree of chunks, so
+//! a blob can be streamed with the parents of the tree (like [bao](https://github.com/oconnor663/bao)),
+//! which lets the receiver verify each group of chunks as it arrives, rather than only once the
+//! whole blob has arrived.
+
+mod id;
+mod verified;
+
+pub use crate::id::BlobId;
+pub use crate::verified::{BlobError, BlobHasher, Decoder, Encoder, Outboard};
+
+/// The length of a group of chunks, the unit of verified streaming
+pub const GROUP_LENGTH: usize = 16 * 1024;
@@ -0,0 +1,421 @@
+use blake3::hazmat::{
+    merge_subtrees_non_root, merge_subtrees_root, ChainingValue, HasherExt, Mode,
+};
+use ppppp_bytes::FromBytes;
+use ppppp_crypto::Hash;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{BlobId, GROUP_LENGTH};
+
+/// The length of the header of a verified stream, the blob size as a little-endian u64
+const HEADER_LENGTH: usize = 8;
+
+/// The length of a parent node, the chaining values of its left and right subtrees
+const PARENT_LENGTH: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("blob does not match its id, at group starting from byte {offset}")]
+    Corrupt { offset: u64 },
+    #[error("verified stream ended before the blob")]
+    Truncated,
+    #[error("verified stream has bytes after the blob")]
+    TrailingBytes,
+}
+
+fn blob_id(hash: blake3::Hash) -> BlobId {
+    BlobId::from_hash(Hash::from_bytes(hash.as_bytes()).unwrap())
+}
+
+/// The number of groups in a blob, where an empty blob is one empty group.
+fn group_count(size: u64) -> u64 {
+    size.div_ceil(GROUP_LENGTH as u64).max(1)
+}
+
+/// The number of groups in the left subtree of a subtree, the largest power of two less than
+/// `groups`.
+fn left_groups(groups: u64) -> u64 {
+    groups.div_ceil(2).next_power_of_two()
+}
+
+fn subtree_cv(group_cvs: &[ChainingValue]) -> ChainingValue {
+    if group_cvs.len() == 1 {
+        return group_cvs[0];
+    }
+    let (left, right) = group_cvs.split_at(left_groups(group_cvs.len() as u64) as usize);
+    merge_subtrees_non_root(&subtree_cv(left), &subtree_cv(right), Mode::Hash)
+}
+
+fn parent(group_cvs: &[ChainingValue]) -> (ChainingValue, ChainingValue) {
+    let (left, right) = group_cvs.split_at(left_groups(group_cvs.len() as u64) as usize);
+    (subtree_cv(left), subtree_cv(right))
+}
+
+/// Hashes a blob as it's written, keeping the chaining value of each group.
+pub struct BlobHasher {
+    group: blake3::Hasher,
+    group_length: usize,
+    size: u64,
+    group_cvs: Vec<ChainingValue>,
+}
+
+impl BlobHasher {
+    pub fn new() -> Self {
+        Self {
+            group: blake3::Hasher::new(),
+            group_length: 0,
+            size: 0,
+            group_cvs: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, mut bytes: &[u8]) -> &mut Self {
+        while !bytes.is_empty() {
+            // only finish a full group once there's more, as a single group is hashed as the root
+            if self.group_length == GROUP_LENGTH {
+                self.group_cvs.push(self.group.finalize_non_root());
+                self.group = blake3::Hasher::new();
+                self.group.set_input_offset(self.size);
+                self.group_length = 0;
+            }
+            let length = bytes.len().min(GROUP_LENGTH - self.group_length);
+            self.group.update(&bytes[..length]);
+            self.group_length += length;
+            self.size += length as u64;
+            bytes = &bytes[length..];
+        }
+        self
+    }
+
+    pub fn finalize(mut self) -> Outboard {
+        let id = if self.group_cvs.is_empty() {
+            blob_id(self.group.finalize())
+        } else {
+            self.group_cvs.push(self.group.finalize_non_root());
+            let (left, right) = parent(&self.group_cvs);
+            blob_id(merge_subtrees_root(&left, &right, Mode::Hash))
+        };
+        Outboard {
+            id,
+            size: self.size,
+            group_cvs: self.group_cvs,
+        }
+    }
+}
+
+impl Default for BlobHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for BlobHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The tree of a blob, without the blob: what's needed to stream it verified.
+#[derive(Clone, Debug)]
+pub struct Outboard {
+    id: BlobId,
+    size: u64,
+    /// The chaining value of each group, or none if there is only one group
+    group_cvs: Vec<ChainingValue>,
+}
+
+impl Outboard {
+    /// Hash a blob from a reader.
+    pub fn compute<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut hasher = BlobHasher::new();
+        io::copy(&mut reader, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+
+    pub fn id(&self) -> &BlobId {
+        &self.id
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Stream the blob from a reader, with the parents which verify it.
+    pub fn encode<R: Read + Seek>(&self, reader: R) -> Encoder<R> {
+        let mut pieces = Vec::new();
+        if self.group_cvs.is_empty() {
+            pieces.push(Piece::Group(0));
+        } else {
+            push_pieces(&self.group_cvs, 0, &mut pieces);
+        }
+        pieces.reverse();
+        Encoder {
+            reader,
+            size: self.size,
+            header: true,
+            pieces,
+        }
+    }
+}
+
+/// A piece of a verified stream
+enum Piece {
+    Parent(ChainingValue, ChainingValue),
+    /// A group, by index
+    Group(u64),
+}
+
+/// Push the pieces of a subtree in pre-order, parents before their children.
+fn push_pieces(group_cvs: &[ChainingValue], start: u64, pieces: &mut Vec<Piece>) {
+    if group_cvs.len() == 1 {
+        pieces.push(Piece::Group(start));
+        return;
+    }
+    let (left, right) = parent(group_cvs);
+    pieces.push(Piece::Parent(left, right));
+    let left_length = left_groups(group_cvs.len() as u64);
+    let (left, right) = group_cvs.split_at(left_length as usize);
+    push_pieces(left, start, pieces);
+    push_pieces(right, start + left_length, pieces);
+}
+
+/// The bytes of a group, from its index
+fn group_range(size: u64, group: u64) -> (u64, usize) {
+    let start = group * GROUP_LENGTH as u64;
+    let length = (size - start).min(GROUP_LENGTH as u64);
+    (start, length as usize)
+}
+
+/// A verified stream of a blob: the blob size, then the parents and groups of its tree, where
+/// each parent comes before its children.
+pub struct Encoder<R> {
+    reader: R,
+    size: u64,
+    header: bool,
+    /// The pieces left to send, in reverse
+    pieces: Vec<Piece>,
+}
+
+impl<R: Read + Seek> Iterator for Encoder<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.header {
+            self.header = false;
+            return Some(Ok(self.size.to_le_bytes().to_vec()));
+        }
+        match self.pieces.pop()? {
+            Piece::Parent(left, right) => Some(Ok([left, right].concat())),
+            Piece::Group(group) => {
+                let (start, length) = group_range(self.size, group);
+                let mut bytes = vec![0; length];
+                let result = self
+                    .reader
+                    .seek(SeekFrom::Start(start))
+                    .and_then(|_| self.reader.read_exact(&mut bytes));
+                Some(result.map(|_| bytes))
+            }
+        }
+    }
+}
+
+/// What a node of the tree must hash to
+#[derive(Clone, Copy)]
+enum Expected {
+    Root,
+    Cv(ChainingValue),
+}
+
+/// A subtree still to be received
+struct Node {
+    start: u64,
+    groups: u64,
+    expected: Expected,
+}
+
+/// Verifies a stream from an [`Encoder`], group by group.
+pub struct Decoder {
+    id: BlobId,
+    buffer: Vec<u8>,
+    size: Option<u64>,
+    /// The subtrees still to be received, the next on top
+    nodes: Vec<Node>,
+}
+
+impl Decoder {
+    pub fn new(id: BlobId) -> Self {
+        Self {
+            id,
+            buffer: Vec::new(),
+            size: None,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// The blob size, once the header has been received
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.size.is_some() && self.nodes.is_empty()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Check the stream has ended with the whole blob.
+    pub fn finish(&self) -> Result<(), BlobError> {
+        if !self.is_done() {
+            return Err(BlobError::Truncated);
+        }
+        if !self.buffer.is_empty() {
+            return Err(BlobError::TrailingBytes);
+        }
+        Ok(())
+    }
+
+    /// Decode the next verified group of the blob, if enough bytes have been pushed.
+    pub fn decode(&mut self) -> Result<Option<Vec<u8>>, BlobError> {
+        if self.size.is_none() {
+            if self.buffer.len() < HEADER_LENGTH {
+                return Ok(None);
+            }
+            let header: Vec<u8> = self.buffer.drain(..HEADER_LENGTH).collect();
+            let size = u64::from_le_bytes(header.try_into().unwrap());
+            self.size = Some(size);
+            self.nodes.push(Node {
+                start: 0,
+                groups: group_count(size),
+                expected: Expected::Root,
+            });
+        }
+        let size = self.size.unwrap();
+
+        loop {
+            let Some(node) = self.nodes.last() else {
+                if !self.buffer.is_empty() {
+                    return Err(BlobError::TrailingBytes);
+                }
+                return Ok(None);
+            };
+            let offset = node.start * GROUP_LENGTH as u64;
+
+            if node.groups == 1 {
+                let (start, length) = group_range(size, node.start);
+                if self.buffer.len() < length {
+                    return Ok(None);
+                }
+                let group: Vec<u8> = self.buffer.drain(..length).collect();
+                let is_valid = match node.expected {
+                    Expected::Root => blob_id(blake3::hash(&group)) == self.id,
+                    Expected::Cv(cv) => {
+                        let mut hasher = blake3::Hasher::new();
+                        hasher.set_input_offset(start).update(&group);
+                        hasher.finalize_non_root() == cv
+                    }
+                };
+                if !is_valid {
+                    return Err(BlobError::Corrupt { offset });
+                }
+                self.nodes.pop();
+                return Ok(Some(group));
+            }
+
+            if self.buffer.len() < PARENT_LENGTH {
+                return Ok(None);
+            }
+            let left: ChainingValue = self.buffer[..32].try_into().unwrap();
+            let right: ChainingValue = self.buffer[32..PARENT_LENGTH].try_into().unwrap();
+            let is_valid = match node.expected {
+                Expected::Root => {
+                    blob_id(merge_subtrees_root(&left, &right, Mode::Hash)) == self.id
+                }
+                Expected::Cv(cv) => merge_subtrees_non_root(&left, &right, Mode::Hash) == cv,
+            };
+            if !is_valid {
+                return Err(BlobError::Corrupt { offset });
+            }
+            self.buffer.drain(..PARENT_LENGTH);
+            let node = self.nodes.pop().unwrap();
+            let left_length = left_groups(node.groups);
+            self.nodes.push(Node {
+                start: node.start + left_length,
+                groups: node.groups - left_length,
+                expected: Expected::Cv(right),
+            });
+            self.nodes.push(Node {
+                start: node.start,
+                groups: left_length,
+                expected: Expected::Cv(left),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn blob(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn encode(blob: &[u8]) -> (BlobId, Vec<u8>) {
+        let outboard = Outboard::compute(blob).unwrap();
+        let encoded = outboard
+            .encode(Cursor::new(blob))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+            .concat();
+        (outboard.id().clone(), encoded)
+    }
+
+    #[test]
+    fn roundtrip() -> Result<(), BlobError> {
+        for size in [0, 1, GROUP_LENGTH, GROUP_LENGTH + 1, 5 * GROUP_LENGTH - 7] {
+            let blob = blob(size);
+            let (id, encoded) = encode(&blob);
+            assert_eq!(id, BlobId::of(&blob));
+
+            let mut decoder = Decoder::new(id);
+            let mut decoded = Vec::new();
+            // push in uneven pieces, as they would arrive from a peer
+            for piece in encoded.chunks(1000) {
+                decoder.push(piece);
+                while let Some(group) = decoder.decode()? {
+                    decoded.extend(group);
+                }
+            }
+            assert!(decoder.is_done());
+            assert_eq!(decoded, blob);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_group() -> Result<(), BlobError> {
+        let blob = blob(3 * GROUP_LENGTH);
+        let (id, mut encoded) = encode(&blob);
+        // header, root parent, left parent, group 0, group 1
+        let group_1 = HEADER_LENGTH + 2 * PARENT_LENGTH + GROUP_LENGTH;
+        encoded[group_1 + 10] ^= 1;
+
+        let mut decoder = Decoder::new(id);
+        decoder.push(&encoded);
+        assert_eq!(decoder.decode()?, Some(blob[..GROUP_LENGTH].to_vec()));
+        assert!(matches!(
+            decoder.decode(),
+            Err(BlobError::Corrupt { offset }) if offset == GROUP_LENGTH as u64
+        ));
+        Ok(())
+    }
+}
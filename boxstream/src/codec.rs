@@ -0,0 +1,200 @@
+use crypto_secretbox::{
+    aead::{generic_array::GenericArray, AeadInPlace},
+    KeyInit, Tag, XSalsa20Poly1305,
+};
+
+use crate::BoxStreamError;
+
+/// The most bytes of plaintext in one body box
+pub const MAX_BODY_LENGTH: usize = 4096;
+/// The length of a header box: a tag, then the body length and the body's tag
+pub const HEADER_LENGTH: usize = 16 + 2 + 16;
+
+const TAG_LENGTH: usize = 16;
+
+/// A 24 byte nonce, incremented as a big-endian number after each box
+#[derive(Clone, Debug)]
+struct Nonce([u8; 24]);
+
+impl Nonce {
+    fn next(&mut self) -> [u8; 24] {
+        let current = self.0;
+        for byte in self.0.iter_mut().rev() {
+            let (incremented, overflow) = byte.overflowing_add(1);
+            *byte = incremented;
+            if !overflow {
+                break;
+            }
+        }
+        current
+    }
+}
+
+/// The plaintext of a header box
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub body_length: usize,
+    pub body_tag: [u8; TAG_LENGTH],
+}
+
+/// Encrypts plaintext into box stream frames.
+pub struct BoxEncoder {
+    cipher: XSalsa20Poly1305,
+    nonce: Nonce,
+}
+
+impl BoxEncoder {
+    pub fn new(key: &[u8; 32], nonce: &[u8; 24]) -> Self {
+        Self {
+            cipher: XSalsa20Poly1305::new(key.into()),
+            nonce: Nonce(*nonce),
+        }
+    }
+
+    /// Encrypt a body of at most [`MAX_BODY_LENGTH`] bytes into a header box then a body box.
+    pub fn encrypt(&mut self, body: &[u8]) -> Vec<u8> {
+        assert!(body.len() <= MAX_BODY_LENGTH, "body is too large to box");
+        let header_nonce = self.nonce.next();
+        let body_nonce = self.nonce.next();
+
+        let mut frame = vec![0; HEADER_LENGTH + body.len()];
+        let (header, body_box) = frame.split_at_mut(HEADER_LENGTH);
+        body_box.copy_from_slice(body);
+        let body_tag = self.seal(&body_nonce, body_box);
+
+        let (header_tag, header) = header.split_at_mut(TAG_LENGTH);
+        header[..2].copy_from_slice(&(body.len() as u16).to_be_bytes());
+        header[2..].copy_from_slice(&body_tag);
+        let tag = self.seal(&header_nonce, header);
+        header_tag.copy_from_slice(&tag);
+
+        frame
+    }
+
+    /// Encrypt the goodbye header, which says the stream is over.
+    pub fn goodbye(&mut self) -> [u8; HEADER_LENGTH] {
+        let header_nonce = self.nonce.next();
+        let mut frame = [0; HEADER_LENGTH];
+        let (header_tag, header) = frame.split_at_mut(TAG_LENGTH);
+        let tag = self.seal(&header_nonce, header);
+        header_tag.copy_from_slice(&tag);
+        frame
+    }
+
+    fn seal(&self, nonce: &[u8; 24], buffer: &mut [u8]) -> [u8; TAG_LENGTH] {
+        self.cipher
+            .encrypt_in_place_detached(GenericArray::from_slice(nonce), &[], buffer)
+            .unwrap()
+            .into()
+    }
+}
+
+/// Decrypts box stream frames into plaintext.
+pub struct BoxDecoder {
+    cipher: XSalsa20Poly1305,
+    nonce: Nonce,
+}
+
+impl BoxDecoder {
+    pub fn new(key: &[u8; 32], nonce: &[u8; 24]) -> Self {
+        Self {
+            cipher: XSalsa20Poly1305::new(key.into()),
+            nonce: Nonce(*nonce),
+        }
+    }
+
+    /// Decrypt a header box, returning `None` if it is the goodbye.
+    pub fn decrypt_header(
+        &mut self,
+        header_box: &[u8; HEADER_LENGTH],
+    ) -> Result<Option<Header>, BoxStreamError> {
+        let (tag, header) = header_box.split_at(TAG_LENGTH);
+        let mut header: [u8; HEADER_LENGTH - TAG_LENGTH] = header.try_into().unwrap();
+        let nonce = self.nonce.next();
+        self.open(&nonce, &mut header, tag)
+            .map_err(|()| BoxStreamError::HeaderDecrypt)?;
+
+        if header.iter().all(|byte| *byte == 0) {
+            return Ok(None);
+        }
+        let body_length = u16::from_be_bytes([header[0], header[1]]) as usize;
+        if body_length > MAX_BODY_LENGTH {
+            return Err(BoxStreamError::BodyTooLarge { body_length });
+        }
+        Ok(Some(Header {
+            body_length,
+            body_tag: header[2..].try_into().unwrap(),
+        }))
+    }
+
+    /// Decrypt a body box in place, given its header.
+    pub fn decrypt_body(&mut self, header: &Header, body: &mut [u8]) -> Result<(), BoxStreamError> {
+        let nonce = self.nonce.next();
+        self.open(&nonce, body, &header.body_tag)
+            .map_err(|()| BoxStreamError::BodyDecrypt)
+    }
+
+    fn open(&self, nonce: &[u8; 24], buffer: &mut [u8], tag: &[u8]) -> Result<(), ()> {
+        self.cipher
+            .decrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                &[],
+                buffer,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonce_increments_big_endian() {
+        let mut nonce = Nonce([0xff; 24]);
+        nonce.0[0] = 0;
+        nonce.next();
+        let mut expected = [0; 24];
+        expected[0] = 1;
+        assert_eq!(nonce.0, expected);
+    }
+
+    #[test]
+    fn codec_roundtrip_and_tampering() -> Result<(), BoxStreamError> {
+        let (key, nonce) = ([1; 32], [2; 24]);
+        let mut encoder = BoxEncoder::new(&key, &nonce);
+        let mut decoder = BoxDecoder::new(&key, &nonce);
+
+        for body in [&b"hello"[..], &[7; MAX_BODY_LENGTH]] {
+            let mut frame = encoder.encrypt(body);
+            let (header_box, body_box) = frame.split_at_mut(HEADER_LENGTH);
+            let header = decoder
+                .decrypt_header((&*header_box).try_into().unwrap())?
+                .unwrap();
+            assert_eq!(header.body_length, body.len());
+            decoder.decrypt_body(&header, body_box)?;
+            assert_eq!(body_box, body);
+        }
+        assert_eq!(decoder.decrypt_header(&encoder.goodbye())?, None);
+
+        let mut frame = encoder.encrypt(b"tampered");
+        frame[HEADER_LENGTH] ^= 1;
+        let (header_box, body_box) = frame.split_at_mut(HEADER_LENGTH);
+        let header = decoder
+            .decrypt_header((&*header_box).try_into().unwrap())?
+            .unwrap();
+        assert!(matches!(
+            decoder.decrypt_body(&header, body_box),
+            Err(BoxStreamError::BodyDecrypt)
+        ));
+
+        let mut frame = encoder.encrypt(b"tampered");
+        frame[0] ^= 1;
+        assert!(matches!(
+            decoder.decrypt_header(frame[..HEADER_LENGTH].try_into().unwrap()),
+            Err(BoxStreamError::HeaderDecrypt)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+//! Box stream, the encrypted and authenticated framing used after a secret handshake.
+//!
+//! - [Scuttlebutt Protocol Guide: Box stream](https://ssbc.github.io/scuttlebutt-protocol-guide/#box-stream)
+//!
+//! Each frame is a header box, holding the length and tag of the body, then the body box. A
+//! header box of all zeros is the goodbye, which ends the stream.
+
+use std::io;
+
+mod codec;
+mod stream;
+
+pub use crate::codec::{BoxDecoder, BoxEncoder, Header, HEADER_LENGTH, MAX_BODY_LENGTH};
+pub use crate::stream::{BoxReader, BoxStream, BoxWriter};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BoxStreamError {
+    #[error("failed to decrypt header box, it was tampered with or is out of order")]
+    HeaderDecrypt,
+    #[error("failed to decrypt body box, it was tampered with or is out of order")]
+    BodyDecrypt,
+    #[error("body length {body_length} is over the limit of {MAX_BODY_LENGTH}")]
+    BodyTooLarge { body_length: usize },
+    #[error("stream ended in the middle of a box")]
+    Truncated,
+    #[error("stream ended without a goodbye")]
+    MissingGoodbye,
+}
+
+impl From<BoxStreamError> for io::Error {
+    fn from(err: BoxStreamError) -> Self {
+        let kind = match err {
+            BoxStreamError::Truncated | BoxStreamError::MissingGoodbye => {
+                io::ErrorKind::UnexpectedEof
+            }
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -0,0 +1,416 @@
+use futures::{ready, AsyncRead, AsyncWrite};
+use ppppp_shse::HandshakeOutcome;
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{BoxDecoder, BoxEncoder, BoxStreamError, Header, HEADER_LENGTH, MAX_BODY_LENGTH};
+
+enum Reading {
+    Header {
+        header_box: [u8; HEADER_LENGTH],
+        filled: usize,
+    },
+    Body {
+        header: Header,
+        body: Vec<u8>,
+        filled: usize,
+    },
+    Ready {
+        body: Vec<u8>,
+        position: usize,
+    },
+    Goodbye,
+}
+
+impl Reading {
+    fn header() -> Self {
+        Reading::Header {
+            header_box: [0; HEADER_LENGTH],
+            filled: 0,
+        }
+    }
+}
+
+struct ReadState {
+    decoder: BoxDecoder,
+    reading: Reading,
+}
+
+impl ReadState {
+    fn new(key: &[u8; 32], nonce: &[u8; 24]) -> Self {
+        Self {
+            decoder: BoxDecoder::new(key, nonce),
+            reading: Reading::header(),
+        }
+    }
+
+    fn poll_read<R: AsyncRead + Unpin>(
+        &mut self,
+        inner: &mut R,
+        cx: &mut Context<'_>,
+        out: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if out.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        loop {
+            match &mut self.reading {
+                Reading::Header { header_box, filled } => {
+                    let n =
+                        ready!(Pin::new(&mut *inner).poll_read(cx, &mut header_box[*filled..]))?;
+                    if n == 0 {
+                        let err = if *filled == 0 {
+                            BoxStreamError::MissingGoodbye
+                        } else {
+                            BoxStreamError::Truncated
+                        };
+                        return Poll::Ready(Err(err.into()));
+                    }
+                    *filled += n;
+                    if *filled == HEADER_LENGTH {
+                        self.reading = match self.decoder.decrypt_header(header_box)? {
+                            None => Reading::Goodbye,
+                            Some(header) => Reading::Body {
+                                body: vec![0; header.body_length],
+                                header,
+                                filled: 0,
+                            },
+                        };
+                    }
+                }
+                Reading::Body {
+                    header,
+                    body,
+                    filled,
+                } => {
+                    if *filled < body.len() {
+                        let n = ready!(Pin::new(&mut *inner).poll_read(cx, &mut body[*filled..]))?;
+                        if n == 0 {
+                            return Poll::Ready(Err(BoxStreamError::Truncated.into()));
+                        }
+                        *filled += n;
+                    }
+                    if *filled == body.len() {
+                        self.decoder.decrypt_body(header, body)?;
+                        self.reading = Reading::Ready {
+                            body: std::mem::take(body),
+                            position: 0,
+                        };
+                    }
+                }
+                Reading::Ready { body, position } => {
+                    let n = out.len().min(body.len() - *position);
+                    out[..n].copy_from_slice(&body[*position..*position + n]);
+                    *position += n;
+                    if *position == body.len() {
+                        self.reading = Reading::header();
+                    }
+                    if n > 0 {
+                        return Poll::Ready(Ok(n));
+                    }
+                }
+                Reading::Goodbye => return Poll::Ready(Ok(0)),
+            }
+        }
+    }
+}
+
+struct WriteState {
+    encoder: BoxEncoder,
+    pending: Vec<u8>,
+    written: usize,
+    goodbye: bool,
+}
+
+impl WriteState {
+    fn new(key: &[u8; 32], nonce: &[u8; 24]) -> Self {
+        Self {
+            encoder: BoxEncoder::new(key, nonce),
+            pending: Vec::new(),
+            written: 0,
+            goodbye: false,
+        }
+    }
+
+    fn poll_pending<W: AsyncWrite + Unpin>(
+        &mut self,
+        inner: &mut W,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.written < self.pending.len() {
+            let n = ready!(Pin::new(&mut *inner).poll_write(cx, &self.pending[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        self.pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write<W: AsyncWrite + Unpin>(
+        &mut self,
+        inner: &mut W,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.goodbye {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        ready!(self.poll_pending(inner, cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = buf.len().min(MAX_BODY_LENGTH);
+        self.pending = self.encoder.encrypt(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush<W: AsyncWrite + Unpin>(
+        &mut self,
+        inner: &mut W,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        ready!(self.poll_pending(inner, cx))?;
+        Pin::new(inner).poll_flush(cx)
+    }
+
+    fn poll_close<W: AsyncWrite + Unpin>(
+        &mut self,
+        inner: &mut W,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        ready!(self.poll_pending(inner, cx))?;
+        if !self.goodbye {
+            self.pending = self.encoder.goodbye().to_vec();
+            self.goodbye = true;
+            ready!(self.poll_pending(inner, cx))?;
+        }
+        Pin::new(inner).poll_close(cx)
+    }
+}
+
+/// Decrypts a box stream read from an inner reader.
+pub struct BoxReader<R> {
+    inner: R,
+    state: ReadState,
+}
+
+impl<R: AsyncRead + Unpin> BoxReader<R> {
+    pub fn new(inner: R, key: &[u8; 32], nonce: &[u8; 24]) -> Self {
+        Self {
+            inner,
+            state: ReadState::new(key, nonce),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BoxReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.state.poll_read(&mut this.inner, cx, buf)
+    }
+}
+
+/// Encrypts a box stream written to an inner writer.
+///
+/// Closing the writer sends the goodbye.
+pub struct BoxWriter<W> {
+    inner: W,
+    state: WriteState,
+}
+
+impl<W: AsyncWrite + Unpin> BoxWriter<W> {
+    pub fn new(inner: W, key: &[u8; 32], nonce: &[u8; 24]) -> Self {
+        Self {
+            inner,
+            state: WriteState::new(key, nonce),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BoxWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.state.poll_write(&mut this.inner, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.state.poll_flush(&mut this.inner, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.state.poll_close(&mut this.inner, cx)
+    }
+}
+
+/// A duplex box stream over an inner stream, with the keys from a handshake.
+pub struct BoxStream<S> {
+    inner: S,
+    read: ReadState,
+    write: WriteState,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> BoxStream<S> {
+    pub fn new(inner: S, outcome: &HandshakeOutcome) -> Self {
+        Self {
+            inner,
+            read: ReadState::new(&outcome.decrypt_key, &outcome.decrypt_nonce),
+            write: WriteState::new(&outcome.encrypt_key, &outcome.encrypt_nonce),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for BoxStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.read.poll_read(&mut this.inner, cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for BoxStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.write.poll_write(&mut this.inner, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.write.poll_flush(&mut this.inner, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.write.poll_close(&mut this.inner, cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, future::join, io::Cursor, AsyncReadExt, AsyncWriteExt};
+    use futures_ringbuf::Endpoint;
+    use ppppp_crypto::{OsRng, SignKeypair};
+    use ppppp_shse::{client_handshake, server_handshake, NetworkKey, NO_EXTRA};
+
+    use super::*;
+
+    #[test]
+    fn boxstream_after_handshake() {
+        let network_key = NetworkKey::generate(&mut OsRng);
+        let client_keypair = SignKeypair::generate(&mut OsRng);
+        let server_keypair = SignKeypair::generate(&mut OsRng);
+        let (mut client_stream, mut server_stream) = Endpoint::pair(64, 64);
+
+        let (client_outcome, server_outcome) = block_on(join(
+            client_handshake(
+                &mut client_stream,
+                &mut OsRng,
+                network_key.clone(),
+                client_keypair,
+                server_keypair.verifying_key().clone(),
+                NO_EXTRA,
+            ),
+            server_handshake(
+                &mut server_stream,
+                &mut OsRng,
+                network_key,
+                server_keypair,
+                |_, _| true,
+            ),
+        ));
+        let mut client = BoxStream::new(client_stream, &client_outcome.unwrap());
+        let mut server = BoxStream::new(server_stream, &server_outcome.unwrap());
+
+        let data: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        let (written, received) = block_on(join(
+            async {
+                client.write_all(&data).await?;
+                client.close().await
+            },
+            async {
+                let mut received = Vec::new();
+                server.read_to_end(&mut received).await?;
+                Ok::<_, io::Error>(received)
+            },
+        ));
+        written.unwrap();
+        assert_eq!(received.unwrap(), data);
+    }
+
+    #[test]
+    fn truncated_and_tampered_streams() {
+        let (key, nonce) = ([1; 32], [2; 24]);
+        let mut encoder = BoxEncoder::new(&key, &nonce);
+        let frame = encoder.encrypt(b"hello");
+
+        let read_all = |bytes: Vec<u8>| {
+            let mut reader = BoxReader::new(Cursor::new(bytes), &key, &nonce);
+            let mut out = Vec::new();
+            block_on(reader.read_to_end(&mut out)).map(|_| out)
+        };
+        let box_stream_error = |err: io::Error| {
+            err.into_inner()
+                .and_then(|err| err.downcast::<BoxStreamError>().ok())
+                .map(|err| *err)
+        };
+
+        let err = read_all(frame[..frame.len() - 1].to_vec()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(matches!(
+            box_stream_error(err),
+            Some(BoxStreamError::Truncated)
+        ));
+
+        let err = read_all(frame.clone()).unwrap_err();
+        assert!(matches!(
+            box_stream_error(err),
+            Some(BoxStreamError::MissingGoodbye)
+        ));
+
+        let mut tampered = frame.clone();
+        tampered[HEADER_LENGTH + 1] ^= 1;
+        let err = read_all(tampered).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            box_stream_error(err),
+            Some(BoxStreamError::BodyDecrypt)
+        ));
+
+        let stream = [frame, encoder.goodbye().to_vec()].concat();
+        assert_eq!(read_all(stream).unwrap(), b"hello");
+    }
+}
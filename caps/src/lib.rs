@@ -0,0 +1,85 @@
+//! Capabilities, the secrets shared by every peer of a network, which keep networks apart.
+//!
+//! - [staltz/ppppp-caps](https://github.com/staltz/ppppp-caps)
+//!
+//! Peers only connect if they have the same caps. The main network uses the caps of
+//! `ppppp-caps`, and a staging network can be kept apart from it by deriving its caps from a name.
+
+use ppppp_crypto::CryptoRngCore;
+
+mod network_key;
+
+pub use crate::network_key::NetworkKey;
+
+/// The network key of the main ppppp network, as in `ppppp-caps`:
+///
+/// ```js
+/// shse: crypto.createHash('sha256').update('ppppp').digest()
+/// ```
+pub const DEFAULT_SHSE: [u8; 32] = [
+    119, 41, 114, 126, 254, 78, 160, 121, 99, 200, 191, 137, 118, 164, 186, 98, 37, 17, 12, 152,
+    17, 194, 143, 145, 129, 214, 255, 129, 20, 233, 182, 155,
+];
+
+const SHSE_CONTEXT: &str = "ppppp-caps 2023-12-01 shse network key";
+
+/// The caps of a network
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Caps {
+    shse: NetworkKey,
+}
+
+impl Default for Caps {
+    fn default() -> Self {
+        Self::from_shse(NetworkKey::from(DEFAULT_SHSE))
+    }
+}
+
+impl Caps {
+    pub fn from_shse(shse: NetworkKey) -> Self {
+        Self { shse }
+    }
+
+    /// Derive the caps of a custom network from its name, like `ppppp-staging`.
+    ///
+    /// The main network doesn't use derived caps, see [`Caps::default`].
+    pub fn derive(network: &str) -> Self {
+        let shse = blake3::derive_key(SHSE_CONTEXT, network.as_bytes());
+        Self::from_shse(NetworkKey::from(shse))
+    }
+
+    /// Generate the caps of a private network, which only peers given the caps can join.
+    pub fn generate<R: CryptoRngCore + ?Sized>(rng: &mut R) -> Self {
+        Self::from_shse(NetworkKey::generate(rng))
+    }
+
+    /// The network key for the secret handshake
+    pub fn shse(&self) -> &NetworkKey {
+        &self.shse
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_bytes::AsBytes;
+    use ppppp_crypto::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn default_and_derived_caps() {
+        let shse: String = Caps::default()
+            .shse()
+            .as_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            shse,
+            "7729727efe4ea07963c8bf8976a4ba6225110c9811c28f9181d6ff8114e9b69b"
+        );
+        assert_ne!(Caps::derive("ppppp"), Caps::default());
+        assert_ne!(Caps::derive("ppppp-staging"), Caps::default());
+        assert_ne!(Caps::generate(&mut OsRng), Caps::generate(&mut OsRng));
+    }
+}
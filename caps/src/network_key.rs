@@ -0,0 +1,44 @@
+use ppppp_bytes::{impl_as_bytes_outputs, impl_from_bytes_inputs, AsBytes, FromBytes};
+use ppppp_crypto::CryptoRngCore;
+use std::convert::Infallible;
+
+/// A secret key shared by every peer of a network, so peers of other networks can't connect
+#[derive(Clone, PartialEq, Eq)]
+pub struct NetworkKey([u8; 32]);
+
+impl std::fmt::Debug for NetworkKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NetworkKey").finish_non_exhaustive()
+    }
+}
+
+impl FromBytes<32> for NetworkKey {
+    type Error = Infallible;
+
+    fn from_bytes(bytes: &[u8; 32]) -> Result<Self, Self::Error> {
+        Ok(Self(*bytes))
+    }
+}
+
+impl AsBytes<32> for NetworkKey {
+    fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for NetworkKey {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl_from_bytes_inputs!(NetworkKey, 32_usize);
+impl_as_bytes_outputs!(NetworkKey, 32_usize);
+
+impl NetworkKey {
+    pub fn generate<R: CryptoRngCore + ?Sized>(rng: &mut R) -> Self {
+        let mut bytes = [0; 32];
+        rng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+}
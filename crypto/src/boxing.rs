@@ -0,0 +1,287 @@
+use crypto_box::{
+    aead::{Aead, AeadCore},
+    Nonce as CryptoBoxNonce, PublicKey as CryptoPublicKey, SalsaBox, SecretKey as CryptoSecretKey,
+};
+use crypto_secretbox::{KeyInit, XSalsa20Poly1305};
+use ppppp_bytes::{
+    impl_as_bytes_outputs, impl_from_bytes_inputs, impl_to_bytes_outputs, AsBytes, FromBytes,
+    ToBytes,
+};
+use rand_core::CryptoRngCore;
+use std::convert::Infallible;
+
+/// The size of the nonce prepended to an authenticated box or secret box
+const BOX_NONCE_LENGTH: usize = 24;
+
+#[derive(Copy, Clone, Debug, thiserror::Error)]
+#[error("failed to encrypt or decrypt box")]
+pub struct BoxError;
+
+/// An x25519 public key to encrypt messages to
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BoxingKey(CryptoPublicKey);
+
+impl FromBytes<32> for BoxingKey {
+    type Error = Infallible;
+
+    fn from_bytes(bytes: &[u8; 32]) -> Result<Self, Self::Error> {
+        Ok(BoxingKey(CryptoPublicKey::from_bytes(*bytes)))
+    }
+}
+
+impl AsBytes<32> for BoxingKey {
+    fn as_bytes(&self) -> &[u8; 32] {
+        self.0.as_bytes()
+    }
+}
+
+impl_from_bytes_inputs!(BoxingKey, 32_usize);
+impl_as_bytes_outputs!(BoxingKey, 32_usize);
+
+impl BoxingKey {
+    /// Anonymously encrypt a message to this key, as a libsodium sealed box.
+    pub fn seal<R: CryptoRngCore>(
+        &self,
+        rng: &mut R,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, BoxError> {
+        self.0.seal(rng, plaintext).map_err(|_| BoxError)
+    }
+}
+
+/// An x25519 secret key to decrypt messages
+#[derive(Clone)]
+pub struct UnboxingKey(CryptoSecretKey);
+
+impl FromBytes<32> for UnboxingKey {
+    type Error = Infallible;
+
+    fn from_bytes(bytes: &[u8; 32]) -> Result<Self, Self::Error> {
+        Ok(UnboxingKey(CryptoSecretKey::from_bytes(*bytes)))
+    }
+}
+
+impl ToBytes<32> for UnboxingKey {
+    fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+}
+
+impl_from_bytes_inputs!(UnboxingKey, 32_usize);
+impl_to_bytes_outputs!(UnboxingKey, 32_usize);
+
+impl UnboxingKey {
+    pub fn generate<R: CryptoRngCore>(rng: &mut R) -> Self {
+        UnboxingKey(CryptoSecretKey::generate(rng))
+    }
+
+    pub fn boxing_key(&self) -> BoxingKey {
+        BoxingKey(self.0.public_key())
+    }
+
+    /// Decrypt a libsodium sealed box encrypted to this key.
+    pub fn unseal(&self, ciphertext: &[u8]) -> Result<Vec<u8>, BoxError> {
+        self.0.unseal(ciphertext).map_err(|_| BoxError)
+    }
+
+    /// Encrypt and authenticate a message from this key to the recipient.
+    ///
+    /// The random nonce is prepended to the returned ciphertext.
+    pub fn encrypt_to<R: CryptoRngCore>(
+        &self,
+        recipient: &BoxingKey,
+        rng: &mut R,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, BoxError> {
+        let salsa_box = SalsaBox::new(&recipient.0, &self.0);
+        let nonce = SalsaBox::generate_nonce(rng);
+        let ciphertext = salsa_box.encrypt(&nonce, plaintext).map_err(|_| BoxError)?;
+        Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    /// Decrypt and authenticate a message from the sender to this key.
+    pub fn decrypt_from(&self, sender: &BoxingKey, ciphertext: &[u8]) -> Result<Vec<u8>, BoxError> {
+        if ciphertext.len() < BOX_NONCE_LENGTH {
+            return Err(BoxError);
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(BOX_NONCE_LENGTH);
+        let salsa_box = SalsaBox::new(&sender.0, &self.0);
+        salsa_box
+            .decrypt(CryptoBoxNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| BoxError)
+    }
+}
+
+impl PartialEq for UnboxingKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+}
+
+impl Eq for UnboxingKey {}
+
+impl std::fmt::Debug for UnboxingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UnboxingKey").finish_non_exhaustive()
+    }
+}
+
+/// A secret and public key pair to encrypt and decrypt messages
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoxKeypair {
+    unboxing_key: UnboxingKey,
+    boxing_key: BoxingKey,
+}
+
+impl BoxKeypair {
+    pub fn generate<R: CryptoRngCore>(rng: &mut R) -> Self {
+        Self::from_unboxing_key(UnboxingKey::generate(rng))
+    }
+
+    pub fn from_unboxing_key(unboxing_key: UnboxingKey) -> Self {
+        let boxing_key = unboxing_key.boxing_key();
+        Self {
+            unboxing_key,
+            boxing_key,
+        }
+    }
+
+    pub fn unboxing_key(&self) -> &UnboxingKey {
+        &self.unboxing_key
+    }
+
+    pub fn boxing_key(&self) -> &BoxingKey {
+        &self.boxing_key
+    }
+}
+
+/// A symmetric key to encrypt and decrypt messages, as a libsodium secret box
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretBoxKey([u8; 32]);
+
+impl FromBytes<32> for SecretBoxKey {
+    type Error = Infallible;
+
+    fn from_bytes(bytes: &[u8; 32]) -> Result<Self, Self::Error> {
+        Ok(SecretBoxKey(*bytes))
+    }
+}
+
+impl ToBytes<32> for SecretBoxKey {
+    fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl_from_bytes_inputs!(SecretBoxKey, 32_usize);
+impl_to_bytes_outputs!(SecretBoxKey, 32_usize);
+
+impl SecretBoxKey {
+    pub fn generate<R: CryptoRngCore>(rng: &mut R) -> Self {
+        let mut bytes = [0; 32];
+        rng.fill_bytes(&mut bytes);
+        SecretBoxKey(bytes)
+    }
+
+    /// Encrypt and authenticate a message with this key.
+    ///
+    /// The random nonce is prepended to the returned ciphertext.
+    pub fn encrypt<R: CryptoRngCore>(
+        &self,
+        rng: &mut R,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, BoxError> {
+        let secret_box = XSalsa20Poly1305::new(&self.0.into());
+        let nonce = XSalsa20Poly1305::generate_nonce(rng);
+        let ciphertext = secret_box
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| BoxError)?;
+        Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    /// Decrypt and authenticate a message encrypted with this key.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, BoxError> {
+        if ciphertext.len() < BOX_NONCE_LENGTH {
+            return Err(BoxError);
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(BOX_NONCE_LENGTH);
+        let secret_box = XSalsa20Poly1305::new(&self.0.into());
+        secret_box
+            .decrypt(CryptoBoxNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| BoxError)
+    }
+}
+
+impl std::fmt::Debug for SecretBoxKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SecretBoxKey").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn sealed_box_roundtrip() -> Result<(), BoxError> {
+        let keypair = BoxKeypair::generate(&mut OsRng);
+        let ciphertext = keypair.boxing_key().seal(&mut OsRng, b"hello world")?;
+        let plaintext = keypair.unboxing_key().unseal(&ciphertext)?;
+        assert_eq!(plaintext, b"hello world");
+
+        let other = BoxKeypair::generate(&mut OsRng);
+        assert!(other.unboxing_key().unseal(&ciphertext).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn box_roundtrip() -> Result<(), BoxError> {
+        let alice = BoxKeypair::generate(&mut OsRng);
+        let bob = BoxKeypair::generate(&mut OsRng);
+        let ciphertext =
+            alice
+                .unboxing_key()
+                .encrypt_to(bob.boxing_key(), &mut OsRng, b"hello bob")?;
+        let plaintext = bob
+            .unboxing_key()
+            .decrypt_from(alice.boxing_key(), &ciphertext)?;
+        assert_eq!(plaintext, b"hello bob");
+
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(bob
+            .unboxing_key()
+            .decrypt_from(alice.boxing_key(), &tampered)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn secret_box_roundtrip() -> Result<(), BoxError> {
+        let key = SecretBoxKey::generate(&mut OsRng);
+        let ciphertext = key.encrypt(&mut OsRng, b"hello")?;
+        assert_eq!(key.decrypt(&ciphertext)?, b"hello");
+
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(key.decrypt(&tampered).is_err());
+        let other = SecretBoxKey::generate(&mut OsRng);
+        assert!(other.decrypt(&ciphertext).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn boxing_key_base58_roundtrip() -> Result<(), Box<dyn Error>> {
+        let keypair = BoxKeypair::generate(&mut OsRng);
+        let json = serde_json::to_string(keypair.boxing_key())?;
+        let boxing_key: BoxingKey = serde_json::from_str(&json)?;
+        assert_eq!(&boxing_key, keypair.boxing_key());
+
+        let unboxing_key = UnboxingKey::from_base58(&keypair.unboxing_key().to_base58())?;
+        assert_eq!(&unboxing_key, keypair.unboxing_key());
+        Ok(())
+    }
+}
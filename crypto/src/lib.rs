@@ -1,7 +1,11 @@
+mod boxing;
 mod hash;
 mod nonce;
 mod sign;
 
+pub use rand_core::{CryptoRng, CryptoRngCore, OsRng, RngCore};
+
+pub use crate::boxing::{BoxError, BoxKeypair, BoxingKey, SecretBoxKey, UnboxingKey};
 pub use crate::hash::{Hash, Hasher};
 pub use crate::nonce::Nonce;
 pub use crate::sign::{
@@ -7,10 +7,16 @@ use ppppp_bytes::{
     impl_as_bytes_outputs, impl_from_bytes_inputs, impl_to_bytes_outputs, AsBytes,
     DeserializeBytesError, FromBytes, ToBytes,
 };
-use std::convert::Infallible;
+use rand_core::CryptoRngCore;
+use std::{
+    convert::Infallible,
+    hash::{Hash, Hasher},
+};
 
 pub use ed25519_dalek::SignatureError;
 
+use crate::{BoxingKey, UnboxingKey};
+
 pub type SignDeserializeBytesError = DeserializeBytesError<SignatureError>;
 
 /// A secret key to sign messages
@@ -35,6 +41,14 @@ impl_from_bytes_inputs!(SigningKey, 32_usize);
 impl_as_bytes_outputs!(SigningKey, 32_usize);
 
 impl SigningKey {
+    pub fn generate<R: CryptoRngCore + ?Sized>(rng: &mut R) -> Self {
+        SigningKey(CryptoSigningKey::generate(rng))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+
     pub fn sign(&self, message: &[u8]) -> Signature {
         Signature(self.0.sign(message))
     }
@@ -42,6 +56,12 @@ impl SigningKey {
     pub fn try_sign(&self, message: &[u8]) -> Result<Signature, SignatureError> {
         Ok(Signature(self.0.try_sign(message)?))
     }
+
+    /// Convert to the x25519 secret key of the same keypair, like libsodium's
+    /// `crypto_sign_ed25519_sk_to_curve25519`.
+    pub fn to_unboxing_key(&self) -> UnboxingKey {
+        UnboxingKey::from_bytes(&self.0.to_scalar_bytes()).unwrap()
+    }
 }
 
 /// A public key to verify signatures
@@ -75,6 +95,12 @@ impl VerifyingKey {
     pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), SignatureError> {
         self.0.verify_strict(message, &signature.0)
     }
+
+    /// Convert to the x25519 public key of the same keypair, like libsodium's
+    /// `crypto_sign_ed25519_pk_to_curve25519`.
+    pub fn to_boxing_key(&self) -> BoxingKey {
+        BoxingKey::from_bytes(&self.0.to_montgomery().to_bytes()).unwrap()
+    }
 }
 
 impl PartialEq for VerifyingKey {
@@ -83,6 +109,12 @@ impl PartialEq for VerifyingKey {
     }
 }
 
+impl Hash for VerifyingKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
+
 /// A private and public key pair to sign and verify signatures
 #[derive(Clone, Debug, GetterMethods)]
 pub struct SignKeypair {
@@ -90,6 +122,45 @@ pub struct SignKeypair {
     verifying_key: VerifyingKey,
 }
 
+impl SignKeypair {
+    pub fn generate<R: CryptoRngCore + ?Sized>(rng: &mut R) -> Self {
+        Self::from_signing_key(SigningKey::generate(rng))
+    }
+
+    pub fn from_signing_key(signing_key: SigningKey) -> Self {
+        let verifying_key = signing_key.verifying_key();
+        Self {
+            signing_key,
+            verifying_key,
+        }
+    }
+}
+
+impl From<SigningKey> for SignKeypair {
+    fn from(signing_key: SigningKey) -> Self {
+        Self::from_signing_key(signing_key)
+    }
+}
+
+/// 64 bytes: the 32 byte secret key followed by the 32 byte public key
+impl FromBytes<64> for SignKeypair {
+    type Error = SignatureError;
+
+    fn from_bytes(bytes: &[u8; 64]) -> Result<Self, Self::Error> {
+        let signing_key = CryptoSigningKey::from_keypair_bytes(bytes)?;
+        Ok(Self::from_signing_key(SigningKey(signing_key)))
+    }
+}
+
+impl ToBytes<64> for SignKeypair {
+    fn to_bytes(&self) -> [u8; 64] {
+        self.signing_key.0.to_keypair_bytes()
+    }
+}
+
+impl_from_bytes_inputs!(SignKeypair, 64_usize);
+impl_to_bytes_outputs!(SignKeypair, 64_usize);
+
 /// An Ed25519 signature
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Signature(CryptoSignature);
@@ -110,3 +181,56 @@ impl ToBytes<64> for Signature {
 
 impl_from_bytes_inputs!(Signature, 64_usize);
 impl_to_bytes_outputs!(Signature, 64_usize);
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn keypair_from_signing_key() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let keypair = SignKeypair::from_signing_key(signing_key.clone());
+        assert_eq!(keypair.signing_key(), &signing_key);
+        assert_eq!(keypair.verifying_key(), &signing_key.verifying_key());
+
+        let signature = keypair.signing_key().sign(b"hello world");
+        assert!(keypair
+            .verifying_key()
+            .verify(b"hello world", &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn keypair_base58_roundtrip() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let keypair_str = keypair.to_string();
+        let decoded = SignKeypair::from_base58(&keypair_str)?;
+        assert_eq!(decoded.signing_key(), keypair.signing_key());
+        assert_eq!(decoded.verifying_key(), keypair.verifying_key());
+
+        let json = serde_json::to_string(&keypair)?;
+        assert_eq!(json, format!("\"{}\"", keypair_str));
+        let decoded: SignKeypair = serde_json::from_str(&json)?;
+        assert_eq!(decoded.signing_key(), keypair.signing_key());
+        Ok(())
+    }
+
+    #[test]
+    fn signing_key_to_unboxing_key() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        assert_eq!(
+            signing_key.to_unboxing_key().boxing_key(),
+            signing_key.verifying_key().to_boxing_key()
+        );
+    }
+
+    #[test]
+    fn keypair_rejects_mismatched_public_key() {
+        let mut bytes = SignKeypair::generate(&mut OsRng).to_bytes();
+        bytes[32..].copy_from_slice(SigningKey::generate(&mut OsRng).verifying_key().as_bytes());
+        assert!(SignKeypair::from_bytes(&bytes).is_err());
+    }
+}
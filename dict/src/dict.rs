@@ -0,0 +1,288 @@
+// https://github.com/staltz/ppppp-dict/blob/master/protospec.md
+
+use ppppp_crypto::SignKeypair;
+use ppppp_msg::{AccountId, Msg, MsgCreateOpts, MsgData, MsgDomain, MsgError, MsgId, Tangle};
+use serde::{Deserialize, Serialize};
+use serde_json::{Error as JsonError, Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use typed_builder::TypedBuilder;
+
+use crate::DOMAIN_PREFIX;
+
+/// The data of a dict msg
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DictMsgData {
+    /// The fields this msg sets, and their values
+    pub update: Map<String, Value>,
+    /// The field roots of the updated fields, which this msg replaces
+    #[serde(default)]
+    pub supersedes: Vec<MsgId>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DictError {
+    #[error("msg error: {0}")]
+    Msg(#[from] MsgError),
+    #[error("tangle {root_msg_id} is not a dict feed")]
+    NotADictTangle { root_msg_id: MsgId },
+    #[error("invalid dict msg data in {msg_id}: {source}")]
+    Data {
+        msg_id: MsgId,
+        #[source]
+        source: JsonError,
+    },
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct DictUpdateOpts {
+    /// The fields to set, and their new values
+    #[builder(setter(into))]
+    pub update: Map<String, Value>,
+    #[builder(default, setter(into))]
+    pub account_tips: Option<Vec<MsgId>>,
+    #[builder(setter(into))]
+    pub sign_keypair: SignKeypair,
+}
+
+/// The dict of an account for a subdomain, and the field roots of each field.
+#[derive(Clone, Debug)]
+pub struct Dict {
+    account_id: MsgId,
+    domain: MsgDomain,
+    tangle: Tangle,
+    fields: BTreeMap<String, Value>,
+    field_roots: BTreeMap<String, HashSet<MsgId>>,
+}
+
+impl Dict {
+    /// Read a dict from its feed tangle.
+    ///
+    /// Msgs which are missing or erased are skipped, as they were pruned.
+    pub fn from_tangle<GetMsg>(tangle: &Tangle, mut get_msg: GetMsg) -> Result<Self, DictError>
+    where
+        GetMsg: FnMut(&MsgId) -> Option<Msg>,
+    {
+        let root_msg_id = *tangle.get_id();
+        let moot = tangle
+            .get_moot_details()
+            .filter(|moot| moot.domain.0.starts_with(DOMAIN_PREFIX))
+            .ok_or(DictError::NotADictTangle { root_msg_id })?;
+        let AccountId::Tangle(account_id) = moot.account_id else {
+            return Err(DictError::NotADictTangle { root_msg_id });
+        };
+
+        let mut dict = Self {
+            account_id,
+            domain: moot.domain,
+            tangle: tangle.clone(),
+            fields: BTreeMap::new(),
+            field_roots: BTreeMap::new(),
+        };
+        for msg_id in tangle.topo_sort() {
+            if msg_id == root_msg_id {
+                continue;
+            }
+            if let Some(msg) = get_msg(&msg_id) {
+                if !msg.data().is_null() {
+                    dict.apply(&msg_id, &msg)?;
+                }
+            }
+        }
+        Ok(dict)
+    }
+
+    /// Apply a dict msg, after the msgs it comes after.
+    ///
+    /// Of concurrent updates to a field, the last in topological order wins, whatever order they
+    /// are applied in.
+    pub fn apply(&mut self, msg_id: &MsgId, msg: &Msg) -> Result<(), DictError> {
+        let data: DictMsgData =
+            serde_json::from_value(msg.data().to_value()).map_err(|source| DictError::Data {
+                msg_id: *msg_id,
+                source,
+            })?;
+        if !self.tangle.has(msg_id) {
+            self.tangle.add(msg_id, msg);
+        }
+
+        for (field, value) in data.update {
+            let roots = self.field_roots.entry(field.clone()).or_default();
+            if self.tangle.replace_roots(roots, msg_id, &data.supersedes) {
+                self.fields.insert(field, value);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields.get(field)
+    }
+
+    /// The fields and their values, in order of field
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.fields
+            .iter()
+            .map(|(field, value)| (field.as_str(), value))
+    }
+
+    /// The msgs which last updated the field
+    pub fn field_roots(&self, field: &str) -> Vec<MsgId> {
+        let mut roots: Vec<MsgId> = self
+            .field_roots
+            .get(field)
+            .map(|roots| roots.iter().copied().collect())
+            .unwrap_or_default();
+        roots.sort();
+        roots
+    }
+
+    /// The field roots which no other field root comes after, the earliest msgs needed to know
+    /// the dict.
+    pub fn min_field_roots(&self) -> Vec<MsgId> {
+        self.tangle.min_roots(self.field_roots.values().flatten())
+    }
+
+    /// The depth in the feed tangle of the earliest msg needed to know the dict
+    pub fn min_required_depth(&self) -> u64 {
+        self.tangle
+            .min_required_depth(self.field_roots.values().flatten())
+    }
+
+    /// The msgs in the feed which come before every field root, which can be pruned.
+    pub fn prunable(&self) -> Vec<MsgId> {
+        self.tangle.prunable(self.field_roots.values().flatten())
+    }
+
+    /// Create a msg which updates fields, superseding their field roots.
+    ///
+    /// Fields which already have the value are left out. Returns `None` if no field changes.
+    /// The msg is not applied: add it to the store, then [`Dict::apply`] it.
+    pub fn create_update(&self, opts: DictUpdateOpts) -> Result<Option<Msg>, DictError> {
+        let DictUpdateOpts {
+            update,
+            account_tips,
+            sign_keypair,
+        } = opts;
+
+        let update: Map<String, Value> = update
+            .into_iter()
+            .filter(|(field, value)| self.get(field) != Some(value))
+            .collect();
+        if update.is_empty() {
+            return Ok(None);
+        }
+        let mut supersedes: Vec<MsgId> = update
+            .keys()
+            .flat_map(|field| self.field_roots(field))
+            .collect();
+        supersedes.sort();
+        supersedes.dedup();
+
+        let data = serde_json::to_value(DictMsgData { update, supersedes })
+            .expect("dict msg data serializes to json");
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(data).expect("dict msg data is an object"))
+                .domain(self.domain.clone())
+                .sign_keypair(sign_keypair)
+                .account_id(AccountId::Tangle(self.account_id))
+                .account_tips(account_tips)
+                .tangles(HashMap::from([(
+                    *self.tangle.get_id(),
+                    self.tangle.clone(),
+                )]))
+                .build(),
+        )?;
+        Ok(Some(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+
+    fn update(dict: &Dict, update: Value, keypair: &SignKeypair) -> Result<Msg, Box<dyn Error>> {
+        let Value::Object(update) = update else {
+            panic!("update is an object");
+        };
+        let msg = dict.create_update(
+            DictUpdateOpts::builder()
+                .update(update)
+                .sign_keypair(keypair.clone())
+                .build(),
+        )?;
+        Ok(msg.expect("update changes the dict"))
+    }
+
+    #[test]
+    fn concurrent_updates() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        let moot = Msg::create_moot(
+            AccountId::Tangle(account_id),
+            crate::domain("profile")?,
+            keypair.clone(),
+        )?;
+        let moot_id = moot.id()?;
+        let mut tangle = Tangle::new(moot_id);
+        tangle.add(&moot_id, &moot);
+        let mut dict = Dict::from_tangle(&tangle, |_| None)?;
+
+        let first = update(&dict, json!({ "name": "alice", "bio": "hi" }), &keypair)?;
+        let first_id = first.id()?;
+        dict.apply(&first_id, &first)?;
+        assert!(dict
+            .create_update(
+                DictUpdateOpts::builder()
+                    .update(Map::from_iter([("name".to_string(), json!("alice"))]))
+                    .sign_keypair(keypair.clone())
+                    .build()
+            )?
+            .is_none());
+
+        // two devices rename at the same time
+        let a = update(&dict, json!({ "name": "alice a" }), &keypair)?;
+        let b = update(&dict, json!({ "name": "alice b" }), &keypair)?;
+        let (a_id, b_id) = (a.id()?, b.id()?);
+        dict.apply(&a_id, &a)?;
+        dict.apply(&b_id, &b)?;
+        let winner = if a_id > b_id { "alice a" } else { "alice b" };
+        assert_eq!(dict.get("name"), Some(&json!(winner)));
+        let msgs = HashMap::from([(first_id, first), (a_id, a), (b_id, b)]);
+        let read = Dict::from_tangle(&dict.tangle, |msg_id| msgs.get(msg_id).cloned())?;
+        assert_eq!(read.get("name"), Some(&json!(winner)));
+        assert_eq!(dict.field_roots("name"), {
+            let mut roots = vec![a_id, b_id];
+            roots.sort();
+            roots
+        });
+        assert_eq!(dict.min_field_roots(), vec![first_id]);
+        assert!(dict.prunable().is_empty());
+
+        // the next update supersedes both, then only the bio keeps the first msg
+        let c = update(&dict, json!({ "name": "alice c" }), &keypair)?;
+        let c_id = c.id()?;
+        let data: DictMsgData = serde_json::from_value(c.data().to_value())?;
+        assert_eq!(data.supersedes, dict.field_roots("name"));
+        dict.apply(&c_id, &c)?;
+        assert_eq!(dict.field_roots("name"), vec![c_id]);
+        assert_eq!(dict.field_roots("bio"), vec![first_id]);
+
+        let d = update(&dict, json!({ "bio": "hello" }), &keypair)?;
+        dict.apply(&d.id()?, &d)?;
+        assert_eq!(dict.min_field_roots(), vec![c_id]);
+        let mut prunable = vec![first_id, a_id, b_id];
+        prunable.sort_by_key(|msg_id| (dict.tangle.get_depth(msg_id), *msg_id));
+        assert_eq!(dict.prunable(), prunable);
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+//! Dicts of fields to JSON values, as a CRDT in a feed of msgs, for profile-style data.
+//!
+//! - [staltz/ppppp-dict](https://github.com/staltz/ppppp-dict/blob/master/protospec.md)
+//!
+//! Each change to an account's dict is a msg in the account's feed for the domain
+//! `dict_v1__<subdomain>`, which updates some fields. The dict is the updates applied in the
+//! topological order of the feed tangle, so of concurrent updates to a field, the last in that
+//! order wins.
+//!
+//! The "field roots" of a field are the msgs which last updated it. The msgs before
+//! them can be pruned, see [`Tangle::prunable`](ppppp_msg::Tangle::prunable).
+
+use ppppp_msg::{MsgDomain, MsgDomainDeserializeError};
+
+mod dict;
+
+pub use crate::dict::{Dict, DictError, DictMsgData, DictUpdateOpts};
+
+/// The prefix of the domain of every dict feed
+pub const DOMAIN_PREFIX: &str = "dict_v1__";
+
+/// The domain of the feed for a dict with this subdomain
+pub fn domain(subdomain: &str) -> Result<MsgDomain, MsgDomainDeserializeError> {
+    MsgDomain::try_from(format!("{}{}", DOMAIN_PREFIX, subdomain))
+}
@@ -0,0 +1,65 @@
+use ppppp_bytes::{AsBytes, FromBytes, ToBytes};
+use ppppp_crypto::{BoxError, BoxingKey, CryptoRngCore, Hasher, SecretBoxKey, UnboxingKey};
+use std::io::Write;
+
+/// A symmetric key shared by the devices of an account
+#[derive(Clone, PartialEq, Eq)]
+pub struct GroupKey(SecretBoxKey);
+
+impl GroupKey {
+    pub fn generate<R: CryptoRngCore>(rng: &mut R) -> Self {
+        Self(SecretBoxKey::generate(rng))
+    }
+
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        Self(SecretBoxKey::from_bytes(bytes).unwrap())
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// The id of the key, the base58 of its hash, which is safe to share
+    pub fn id(&self) -> String {
+        let mut hasher = Hasher::new();
+        hasher.write_all(&self.to_bytes()).unwrap();
+        ppppp_base58::encode(hasher.finalize().as_bytes())
+    }
+
+    /// Seal the key to a device.
+    pub fn seal_to<R: CryptoRngCore>(
+        &self,
+        rng: &mut R,
+        boxing_key: &BoxingKey,
+    ) -> Result<Vec<u8>, BoxError> {
+        boxing_key.seal(rng, &self.to_bytes())
+    }
+
+    /// Open a key sealed to a device.
+    pub fn unseal(sealed: &[u8], unboxing_key: &UnboxingKey) -> Result<Self, BoxError> {
+        let bytes: [u8; 32] = unboxing_key
+            .unseal(sealed)?
+            .try_into()
+            .map_err(|_| BoxError)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    /// Encrypt with the key, as a secret box, so whoever has it can decrypt.
+    pub fn encrypt<R: CryptoRngCore>(
+        &self,
+        rng: &mut R,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, BoxError> {
+        self.0.encrypt(rng, plaintext)
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, BoxError> {
+        self.0.decrypt(ciphertext)
+    }
+}
+
+impl std::fmt::Debug for GroupKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("GroupKey").field(&self.id()).finish()
+    }
+}
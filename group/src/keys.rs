@@ -0,0 +1,401 @@
+use ppppp_crypto::{BoxError, BoxKeypair, BoxingKey, CryptoRngCore, SignKeypair};
+use ppppp_msg::{AccountMsgData, AccountPower, Msg, MsgData, MsgDomain, MsgError, MsgId};
+use ppppp_msg_store::{FeedPublishOpts, MsgStore, MsgStoreError};
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{GroupKey, DOMAIN};
+
+#[derive(Debug, thiserror::Error)]
+pub enum GroupError {
+    #[error("store error: {0}")]
+    Store(#[from] MsgStoreError),
+    #[error("msg error: {0}")]
+    Msg(#[from] MsgError),
+    #[error("device is not an encryption key of the account with the internal encryption power")]
+    NotADevice,
+    #[error("no group key for the current epoch, update the group keys first")]
+    NoCurrentKey,
+    #[error("invalid group key msg data in {msg_id}: {source}")]
+    Data {
+        msg_id: MsgId,
+        #[source]
+        source: JsonError,
+    },
+    #[error("group key in {msg_id} does not match its id")]
+    KeyMismatch { msg_id: MsgId },
+    #[error("group box is not base58")]
+    Base58,
+    #[error("group crypto error: {0}")]
+    Box(#[from] BoxError),
+    #[error("invalid group box plaintext: {0}")]
+    Json(#[from] JsonError),
+}
+
+/// The data of a msg which shares a group key with devices
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GroupKeyMsgData {
+    /// The account msg which starts the epoch of the key
+    pub epoch: MsgId,
+    /// The id of the key
+    pub key: String,
+    /// The key sealed to each device, by the boxing key of the device, as base58
+    pub keys: BTreeMap<String, String>,
+}
+
+/// Msg data encrypted with a group key
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct GroupBox {
+    /// The data, encrypted with the key, as base58
+    #[serde(rename = "groupBox")]
+    ciphertext: String,
+    /// The id of the key
+    key: String,
+}
+
+fn domain() -> MsgDomain {
+    MsgDomain::try_from(DOMAIN.to_string()).expect("group keys domain is valid")
+}
+
+/// The current epoch of an account: the account msg which last deleted a key, or the root.
+fn current_epoch(store: &mut MsgStore, account_id: &MsgId) -> Result<MsgId, GroupError> {
+    let tangle = store
+        .tangle(account_id)?
+        .ok_or(MsgStoreError::MsgNotFound {
+            msg_id: *account_id,
+        })?;
+    let mut epoch = *account_id;
+    for msg_id in tangle.topo_sort() {
+        let Some(msg) = store.get(&msg_id)? else {
+            continue;
+        };
+        if let Ok(AccountMsgData::Del { .. }) = serde_json::from_value(msg.data().to_value()) {
+            epoch = msg_id;
+        }
+    }
+    Ok(epoch)
+}
+
+/// The group keys of an account, as a device of the account sees them.
+pub struct GroupKeys {
+    account_id: MsgId,
+    device: BoxKeypair,
+    epoch: MsgId,
+    /// The keys this device has, by id
+    keys: HashMap<String, GroupKey>,
+    /// For each epoch, the ids of its keys, and the devices each key was shared with
+    epochs: HashMap<MsgId, BTreeMap<String, HashSet<String>>>,
+}
+
+impl GroupKeys {
+    /// Load the group keys shared in the account's feed, opening those sealed to the device.
+    pub fn load(
+        store: &mut MsgStore,
+        account_id: &MsgId,
+        device: &BoxKeypair,
+    ) -> Result<Self, GroupError> {
+        let mut keys = Self {
+            account_id: *account_id,
+            device: device.clone(),
+            epoch: current_epoch(store, account_id)?,
+            keys: HashMap::new(),
+            epochs: HashMap::new(),
+        };
+        if let Some(tangle) = store.feed(account_id, &domain())? {
+            for msg_id in tangle.topo_sort() {
+                if &msg_id == tangle.get_id() {
+                    continue;
+                }
+                if let Some(msg) = store.get(&msg_id)? {
+                    if !msg.data().is_null() {
+                        keys.apply(&msg_id, &msg)?;
+                    }
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    fn apply(&mut self, msg_id: &MsgId, msg: &Msg) -> Result<(), GroupError> {
+        let data: GroupKeyMsgData =
+            serde_json::from_value(msg.data().to_value()).map_err(|source| GroupError::Data {
+                msg_id: *msg_id,
+                source,
+            })?;
+        if let Some(sealed) = data.keys.get(&self.device.boxing_key().to_string()) {
+            let sealed = ppppp_base58::decode(sealed).map_err(|_| GroupError::Base58)?;
+            let key = GroupKey::unseal(&sealed, self.device.unboxing_key())?;
+            if key.id() != data.key {
+                return Err(GroupError::KeyMismatch { msg_id: *msg_id });
+            }
+            self.keys.insert(data.key.clone(), key);
+        }
+        self.epochs
+            .entry(data.epoch)
+            .or_default()
+            .entry(data.key)
+            .or_default()
+            .extend(data.keys.into_keys());
+        Ok(())
+    }
+
+    /// The key of the current epoch, as of the last load or update.
+    ///
+    /// If devices rotated at the same time, the epoch has many keys, so every device uses the one
+    /// with the lowest id. `None` if this device doesn't have that key yet.
+    pub fn current(&self) -> Option<&GroupKey> {
+        let key_id = self.epochs.get(&self.epoch)?.keys().next()?;
+        self.keys.get(key_id)
+    }
+
+    /// Share the current key with the devices which don't have it, rotating to a new key if the
+    /// epoch has changed, as a device was deleted.
+    ///
+    /// Returns `false` if every device already has the current key, or this device doesn't have
+    /// it yet and waits for another device to share it, when nothing is published.
+    pub fn update<R: CryptoRngCore>(
+        &mut self,
+        store: &mut MsgStore,
+        rng: &mut R,
+        sign_keypair: &SignKeypair,
+    ) -> Result<bool, GroupError> {
+        self.epoch = current_epoch(store, &self.account_id)?;
+        let account = store.account(&self.account_id)?;
+        let devices: Vec<BoxingKey> = account
+            .keys()
+            .filter(|key| {
+                account
+                    .get_powers(key)
+                    .is_some_and(|powers| powers.contains(&AccountPower::InternalEncryption))
+            })
+            .filter_map(|key| key.boxing_key())
+            .cloned()
+            .collect();
+        if !devices.contains(self.device.boxing_key()) {
+            return Err(GroupError::NotADevice);
+        }
+
+        let current = self
+            .epochs
+            .get(&self.epoch)
+            .and_then(|keys| keys.iter().next());
+        let (key, shared) = match current {
+            Some((key_id, shared)) => match self.keys.get(key_id) {
+                Some(key) => (key.clone(), shared.clone()),
+                None => return Ok(false),
+            },
+            None => (GroupKey::generate(rng), HashSet::new()),
+        };
+        let mut keys = BTreeMap::new();
+        for device in devices {
+            let device_id = device.to_string();
+            if !shared.contains(&device_id) {
+                let sealed = key.seal_to(rng, &device)?;
+                keys.insert(device_id, ppppp_base58::encode(&sealed));
+            }
+        }
+        if keys.is_empty() {
+            return Ok(false);
+        }
+
+        let data = GroupKeyMsgData {
+            epoch: self.epoch,
+            key: key.id(),
+            keys,
+        };
+        let data = serde_json::to_value(data).expect("group key msg data serializes to json");
+        let (msg_id, msg) = store.publish(
+            FeedPublishOpts::builder()
+                .account_id(self.account_id)
+                .domain(domain())
+                .data(MsgData::try_from(data).expect("group key msg data is an object"))
+                .sign_keypair(sign_keypair.clone())
+                .build(),
+        )?;
+        self.apply(&msg_id, &msg)?;
+        Ok(true)
+    }
+
+    /// Encrypt data with the current key.
+    pub fn encrypt<R: CryptoRngCore>(
+        &self,
+        rng: &mut R,
+        data: &MsgData,
+    ) -> Result<MsgData, GroupError> {
+        let key = self.current().ok_or(GroupError::NoCurrentKey)?;
+        let ciphertext = key.encrypt(rng, &serde_json::to_vec(data)?)?;
+        let group_box = GroupBox {
+            ciphertext: ppppp_base58::encode(&ciphertext),
+            key: key.id(),
+        };
+        let value = serde_json::to_value(group_box).expect("group box serializes to json");
+        Ok(MsgData::try_from(value).expect("group box is an object"))
+    }
+
+    /// Decrypt data, or `None` if the data is not encrypted with a key this device has.
+    pub fn decrypt(&self, data: &MsgData) -> Result<Option<MsgData>, GroupError> {
+        let Ok(group_box) = serde_json::from_value::<GroupBox>(data.to_value()) else {
+            return Ok(None);
+        };
+        let Some(key) = self.keys.get(&group_box.key) else {
+            return Ok(None);
+        };
+        let ciphertext =
+            ppppp_base58::decode(&group_box.ciphertext).map_err(|_| GroupError::Base58)?;
+        let plaintext = key.decrypt(&ciphertext)?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng};
+    use ppppp_msg::{AccountId, AccountKey, MsgAccountAddOpts, MsgCreateOpts};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+
+    fn device_key(device: &BoxKeypair) -> AccountKey {
+        AccountKey::ExternalEncryption {
+            algorithm: Default::default(),
+            bytes: device.boxing_key().clone(),
+        }
+    }
+
+    fn add_device(
+        store: &mut MsgStore,
+        account_id: MsgId,
+        keypair: &SignKeypair,
+        device: &BoxKeypair,
+    ) -> Result<(), Box<dyn Error>> {
+        let add = Msg::create_account_add(
+            MsgAccountAddOpts::builder()
+                .account_tangle(store.tangle(&account_id)?.unwrap())
+                .new_key(device_key(device))
+                .powers(vec![AccountPower::InternalEncryption])
+                .sign_keypair(keypair.clone())
+                .build(),
+        )?;
+        store.add_validated(add, &account_id)?;
+        Ok(())
+    }
+
+    #[test]
+    fn rotate_on_del() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut store = MsgStore::open(dir.path().join("log"))?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        store.add_validated(account, &account_id)?;
+        let laptop = BoxKeypair::generate(&mut OsRng);
+        let phone = BoxKeypair::generate(&mut OsRng);
+        add_device(&mut store, account_id, &keypair, &laptop)?;
+        add_device(&mut store, account_id, &keypair, &phone)?;
+
+        let mut laptop_keys = GroupKeys::load(&mut store, &account_id, &laptop)?;
+        assert!(matches!(
+            laptop_keys.encrypt(&mut OsRng, &MsgData::try_from(json!("draft"))?),
+            Err(GroupError::NoCurrentKey)
+        ));
+        assert!(laptop_keys.update(&mut store, &mut OsRng, &keypair)?);
+        assert!(!laptop_keys.update(&mut store, &mut OsRng, &keypair)?);
+        let draft = MsgData::try_from(json!({ "text": "draft" }))?;
+        let encrypted = laptop_keys.encrypt(&mut OsRng, &draft)?;
+
+        let phone_keys = GroupKeys::load(&mut store, &account_id, &phone)?;
+        assert_eq!(phone_keys.current(), laptop_keys.current());
+        let decrypted = phone_keys.decrypt(&encrypted)?.unwrap();
+        assert_eq!(decrypted.to_value(), draft.to_value());
+
+        // the phone is lost, so it is deleted
+        let account_tangle = store.tangle(&account_id)?.unwrap();
+        let del = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(serde_json::to_value(
+                    AccountMsgData::Del {
+                        key: device_key(&phone),
+                    },
+                )?)?)
+                .domain(account_tangle.get_root()?.metadata().domain().clone())
+                .sign_keypair(keypair.clone())
+                .account_id(AccountId::SelfIdentity)
+                .tangles(HashMap::from([(account_id, account_tangle)]))
+                .build(),
+        )?;
+        store.add_validated(del, &account_id)?;
+
+        let old_key = laptop_keys.current().cloned();
+        assert!(laptop_keys.update(&mut store, &mut OsRng, &keypair)?);
+        assert_ne!(laptop_keys.current().cloned(), old_key);
+        let secret = laptop_keys.encrypt(&mut OsRng, &draft)?;
+        assert!(laptop_keys.decrypt(&encrypted)?.is_some());
+
+        let phone_keys = GroupKeys::load(&mut store, &account_id, &phone)?;
+        assert!(phone_keys.decrypt(&encrypted)?.is_some());
+        assert!(phone_keys.decrypt(&secret)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_rotation() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut store = MsgStore::open(dir.path().join("log"))?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        store.add_validated(account, &account_id)?;
+        let laptop = BoxKeypair::generate(&mut OsRng);
+        let phone = BoxKeypair::generate(&mut OsRng);
+        add_device(&mut store, account_id, &keypair, &laptop)?;
+        add_device(&mut store, account_id, &keypair, &phone)?;
+
+        // both devices start the epoch at the same time, and the phone's key also goes to a
+        // tablet which was added meanwhile
+        let mut laptop_keys = GroupKeys::load(&mut store, &account_id, &laptop)?;
+        let mut phone_keys = GroupKeys::load(&mut store, &account_id, &phone)?;
+        assert!(laptop_keys.update(&mut store, &mut OsRng, &keypair)?);
+        let tablet = BoxKeypair::generate(&mut OsRng);
+        add_device(&mut store, account_id, &keypair, &tablet)?;
+        assert!(phone_keys.update(&mut store, &mut OsRng, &keypair)?);
+
+        let mut devices = Vec::new();
+        for device in [&laptop, &phone, &tablet] {
+            devices.push(GroupKeys::load(&mut store, &account_id, device)?);
+        }
+        let lowest = devices[1].epochs[&devices[1].epoch]
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        for keys in &devices {
+            if let Some(key) = keys.current() {
+                assert_eq!(key.id(), lowest);
+            }
+        }
+
+        // whichever key is lowest, a device which has it shares it with the tablet
+        for device in [&tablet, &laptop] {
+            GroupKeys::load(&mut store, &account_id, device)?
+                .update(&mut store, &mut OsRng, &keypair)?;
+        }
+        for device in [&laptop, &phone, &tablet] {
+            let keys = GroupKeys::load(&mut store, &account_id, device)?;
+            assert_eq!(keys.current().map(GroupKey::id), Some(lowest.clone()));
+        }
+        Ok(())
+    }
+}
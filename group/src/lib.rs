@@ -0,0 +1,18 @@
+//! Group encryption, with a symmetric key shared by the devices of an account.
+//!
+//! The devices are the account's `ExternalEncryption` keys which have the `InternalEncryption`
+//! power. A group key is shared in a msg in the account's feed for the [`DOMAIN`], sealed to
+//! each device.
+//!
+//! Each key belongs to an epoch: the account msg which last deleted a key, or the account root.
+//! When a device is deleted, the epoch changes, so the next update rotates to a new key which
+//! the deleted device never gets. Msgs encrypted before can still be read with the old keys.
+
+mod key;
+mod keys;
+
+pub use crate::key::GroupKey;
+pub use crate::keys::{GroupError, GroupKeyMsgData, GroupKeys};
+
+/// The domain of the feed which shares group keys
+pub const DOMAIN: &str = "group_keys";
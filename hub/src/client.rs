@@ -0,0 +1,218 @@
+use futures::{channel::mpsc, future, stream, AsyncRead, AsyncWrite, Stream, StreamExt};
+use ppppp_boxstream::BoxStream;
+use ppppp_crypto::{CryptoRngCore, SignKeypair, VerifyingKey};
+use ppppp_muxrpc::{connect, Body, Connection, Registry, RpcClient, RpcError, RpcResult};
+use ppppp_shse::{client_handshake, NetworkKey, NO_EXTRA};
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::{peer_arg, AttendantsEvent, HubError, Tunnel, TUNNEL_BUFFER};
+
+/// A tunnel another attendant opened to us
+pub struct IncomingTunnel {
+    /// Who opened the tunnel, as the hub says, which the handshake inside should check
+    pub origin: VerifyingKey,
+    pub tunnel: Tunnel,
+}
+
+/// The tunnels other attendants open to us, until the connection to the hub ends
+pub type IncomingTunnels = mpsc::UnboundedReceiver<IncomingTunnel>;
+
+/// An attendant's side of a connection to a hub.
+#[derive(Clone, Debug)]
+pub struct HubClient {
+    rpc: RpcClient,
+}
+
+impl HubClient {
+    /// Connect to a hub: do the client side of a handshake, then start a connection, which does
+    /// nothing unless polled.
+    pub async fn connect<S, R>(
+        mut stream: S,
+        rng: &mut R,
+        network_key: NetworkKey,
+        keypair: SignKeypair,
+        hub_key: VerifyingKey,
+    ) -> Result<(Self, Connection<BoxStream<S>>, IncomingTunnels), HubError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        R: CryptoRngCore + ?Sized,
+    {
+        let outcome =
+            client_handshake(&mut stream, rng, network_key, keypair, hub_key, NO_EXTRA).await?;
+        let (incoming, incoming_tunnels) = mpsc::unbounded();
+        let registry = Arc::new(registry(incoming));
+        let (rpc, connection) = connect(BoxStream::new(stream, &outcome), registry);
+        Ok((Self { rpc }, connection, incoming_tunnels))
+    }
+
+    /// The attendants of the hub: every attendant, then as they join and leave.
+    pub fn attendants(&self) -> Result<impl Stream<Item = RpcResult<AttendantsEvent>>, HubError> {
+        let events = self.rpc.source(&["hub", "attendants"], Vec::new())?;
+        Ok(events.map(|event| event.and_then(Body::into_json)))
+    }
+
+    /// Open a tunnel to another attendant.
+    pub fn tunnel(&self, target: &VerifyingKey) -> Result<Tunnel, HubError> {
+        let (sender, input) = self
+            .rpc
+            .duplex(&["hub", "createTunnel"], vec![json!(target)])?;
+        Ok(Tunnel::new(sender, input))
+    }
+
+    /// Disconnect from the hub.
+    pub fn close(&self) -> Result<(), HubError> {
+        Ok(self.rpc.close()?)
+    }
+}
+
+/// The methods the hub can call: opening tunnels from other attendants
+fn registry(incoming: mpsc::UnboundedSender<IncomingTunnel>) -> Registry {
+    let mut registry = Registry::new();
+    registry.add_duplex(&["hub", "createTunnel"], move |args, input| {
+        let origin = match peer_arg(&args) {
+            Ok(origin) => origin,
+            Err(err) => return stream::once(future::ready(Err(err))).boxed(),
+        };
+        let (sender, output) = mpsc::channel(TUNNEL_BUFFER);
+        let tunnel = Tunnel::new(sender, input);
+        if incoming
+            .unbounded_send(IncomingTunnel { origin, tunnel })
+            .is_err()
+        {
+            let err = RpcError::new("not accepting tunnels");
+            return stream::once(future::ready(Err(err))).boxed();
+        }
+        output.map(Ok).boxed()
+    });
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{
+        executor::block_on,
+        future::{join, join3, try_join},
+        AsyncReadExt, AsyncWriteExt,
+    };
+    use futures_ringbuf::Endpoint;
+    use ppppp_bytes::FromBytes;
+    use ppppp_crypto::OsRng;
+    use ppppp_shse::server_handshake;
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::Hub;
+
+    #[test]
+    fn tunnel_between_attendants() {
+        let network_key = NetworkKey::from_bytes(&[1; 32]).unwrap();
+        let hub_keypair = SignKeypair::generate(&mut OsRng);
+        let hub_key = hub_keypair.verifying_key().clone();
+        let hub = Hub::new(network_key.clone(), hub_keypair);
+        let alice_keypair = SignKeypair::generate(&mut OsRng);
+        let alice_key = alice_keypair.verifying_key().clone();
+        let bob_keypair = SignKeypair::generate(&mut OsRng);
+        let bob_key = bob_keypair.verifying_key().clone();
+
+        let (alice_stream, hub_alice_stream) = Endpoint::pair(256, 256);
+        let (bob_stream, hub_bob_stream) = Endpoint::pair(256, 256);
+
+        let (mut alice_rng, mut bob_rng) = (OsRng, OsRng);
+        let hub_side = join(
+            hub.accept(hub_alice_stream, &mut alice_rng),
+            hub.accept(hub_bob_stream, &mut bob_rng),
+        );
+        let peers = async {
+            let (alice, alice_connection, _) = HubClient::connect(
+                alice_stream,
+                &mut OsRng,
+                network_key.clone(),
+                alice_keypair.clone(),
+                hub_key.clone(),
+            )
+            .await?;
+            let (bob, bob_connection, mut bob_incoming) = HubClient::connect(
+                bob_stream,
+                &mut OsRng,
+                network_key.clone(),
+                bob_keypair.clone(),
+                hub_key.clone(),
+            )
+            .await?;
+
+            let test = async {
+                // wait until both are attendants
+                let mut events = alice.attendants()?;
+                let mut attendants = HashSet::new();
+                while !attendants.contains(&bob_key.to_string()) {
+                    match events.next().await.unwrap().unwrap() {
+                        AttendantsEvent::State { ids } => {
+                            attendants.extend(ids.iter().map(ToString::to_string))
+                        }
+                        AttendantsEvent::Joined { id } => {
+                            attendants.insert(id.to_string());
+                        }
+                        AttendantsEvent::Left { id } => {
+                            attendants.remove(&id.to_string());
+                        }
+                    }
+                }
+                assert!(attendants.contains(&alice_key.to_string()));
+
+                let mut alice_tunnel = alice.tunnel(&bob_key)?;
+                let incoming = bob_incoming.next().await.unwrap();
+                assert_eq!(incoming.origin, alice_key);
+                let mut bob_tunnel = incoming.tunnel;
+
+                // alice and bob shake hands inside the tunnel, so only they can read it
+                let (alice_outcome, bob_outcome) = try_join(
+                    client_handshake(
+                        &mut alice_tunnel,
+                        &mut OsRng,
+                        network_key.clone(),
+                        alice_keypair.clone(),
+                        bob_key.clone(),
+                        NO_EXTRA,
+                    ),
+                    server_handshake(
+                        &mut bob_tunnel,
+                        &mut OsRng,
+                        network_key.clone(),
+                        bob_keypair.clone(),
+                        |key, _| key == &incoming.origin,
+                    ),
+                )
+                .await?;
+                let mut alice_box = BoxStream::new(alice_tunnel, &alice_outcome);
+                let mut bob_box = BoxStream::new(bob_tunnel, &bob_outcome);
+
+                alice_box.write_all(b"hi bob").await.unwrap();
+                alice_box.close().await.unwrap();
+                let mut received = Vec::new();
+                bob_box.read_to_end(&mut received).await.unwrap();
+                assert_eq!(received, b"hi bob");
+
+                // the hub refuses a tunnel to yourself
+                let mut self_tunnel = alice.tunnel(&alice_key)?;
+                assert!(self_tunnel.read(&mut [0; 1]).await.is_err());
+
+                alice.close()?;
+                bob.close()?;
+                Ok::<_, HubError>(())
+            };
+            let (alice_result, bob_result, test_result) =
+                join3(alice_connection, bob_connection, test).await;
+            test_result?;
+            alice_result?;
+            bob_result?;
+            Ok::<_, HubError>(())
+        };
+
+        let ((alice_result, bob_result), peers_result) = block_on(join(hub_side, peers));
+        peers_result.unwrap();
+        alice_result.unwrap();
+        bob_result.unwrap();
+        assert!(hub.attendants().is_empty());
+    }
+}
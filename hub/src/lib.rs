@@ -0,0 +1,46 @@
+//! Hub, a server which cross-connects peers who can't reach each other, as they are behind NAT.
+//!
+//! - [staltz/ppppp-hub](https://github.com/staltz/ppppp-hub)
+//! - [staltz/ppppp-hub-client](https://github.com/staltz/ppppp-hub-client)
+//!
+//! Peers connect to the [`Hub`] with a secret handshake, and become its attendants. An attendant
+//! can open a [`Tunnel`] to another attendant, through the hub, with `hub.createTunnel`. The hub
+//! only relays the bytes of a tunnel, so the peers do a secret handshake of their own inside it,
+//! end to end.
+//!
+//! The hub and [`HubClient`] run over any async stream, so they can be run in process, over
+//! loopback or in memory.
+
+use ppppp_bytes::FromBytes;
+use ppppp_crypto::VerifyingKey;
+use ppppp_muxrpc::{MuxRpcError, RpcError, RpcResult};
+use ppppp_shse::HandshakeError;
+use serde_json::Value;
+
+mod client;
+mod server;
+mod tunnel;
+
+pub use crate::client::{HubClient, IncomingTunnel, IncomingTunnels};
+pub use crate::server::{AttendantsEvent, Hub};
+pub use crate::tunnel::Tunnel;
+
+/// How many bodies a tunnel buffers before its writer has to wait
+pub(crate) const TUNNEL_BUFFER: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HubError {
+    #[error("handshake error: {0}")]
+    Handshake(#[from] HandshakeError),
+    #[error("muxrpc error: {0}")]
+    MuxRpc(#[from] MuxRpcError),
+}
+
+/// The id of a peer, from the first arg of a call
+pub(crate) fn peer_arg(args: &[Value]) -> RpcResult<VerifyingKey> {
+    let id = args
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new("expected a peer id"))?;
+    VerifyingKey::from_base58(id).map_err(|err| RpcError::new(format!("invalid peer id: {err}")))
+}
@@ -0,0 +1,207 @@
+use futures::{
+    channel::mpsc,
+    future,
+    stream::{self, StreamExt},
+    AsyncRead, AsyncWrite, FutureExt,
+};
+use ppppp_boxstream::BoxStream;
+use ppppp_crypto::{CryptoRngCore, SignKeypair, VerifyingKey};
+use ppppp_muxrpc::{connect, Body, Registry, RpcClient, RpcError, RpcResult, RpcStream};
+use ppppp_shse::{server_handshake, NetworkKey};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{peer_arg, HubError};
+
+/// A change to the attendants of a hub, as streamed by `hub.attendants`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum AttendantsEvent {
+    /// Every attendant, sent first
+    State {
+        ids: Vec<VerifyingKey>,
+    },
+    Joined {
+        id: VerifyingKey,
+    },
+    Left {
+        id: VerifyingKey,
+    },
+}
+
+struct Attendant {
+    /// Which connection of the attendant this is, in case it reconnects before we notice it left
+    connection_id: u64,
+    key: VerifyingKey,
+    client: RpcClient,
+}
+
+#[derive(Default)]
+struct Attendants {
+    next_connection_id: u64,
+    attendants: BTreeMap<String, Attendant>,
+    subscribers: Vec<mpsc::UnboundedSender<AttendantsEvent>>,
+}
+
+impl Attendants {
+    fn ids(&self) -> Vec<VerifyingKey> {
+        self.attendants
+            .values()
+            .map(|attendant| attendant.key.clone())
+            .collect()
+    }
+
+    fn notify(&mut self, event: AttendantsEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
+    fn subscribe(&mut self) -> mpsc::UnboundedReceiver<AttendantsEvent> {
+        let (subscriber, events) = mpsc::unbounded();
+        let _ = subscriber.unbounded_send(AttendantsEvent::State { ids: self.ids() });
+        self.subscribers.push(subscriber);
+        events
+    }
+
+    fn join(&mut self, key: VerifyingKey, client: RpcClient) -> u64 {
+        let connection_id = self.next_connection_id;
+        self.next_connection_id += 1;
+        let attendant = Attendant {
+            connection_id,
+            key: key.clone(),
+            client,
+        };
+        if self.attendants.insert(key.to_string(), attendant).is_none() {
+            self.notify(AttendantsEvent::Joined { id: key });
+        }
+        connection_id
+    }
+
+    fn leave(&mut self, key: &VerifyingKey, connection_id: u64) {
+        let id = key.to_string();
+        if self
+            .attendants
+            .get(&id)
+            .is_some_and(|attendant| attendant.connection_id == connection_id)
+        {
+            self.attendants.remove(&id);
+            self.notify(AttendantsEvent::Left { id: key.clone() });
+        }
+    }
+
+    fn client(&self, key: &VerifyingKey) -> Option<RpcClient> {
+        let attendant = self.attendants.get(&key.to_string())?;
+        Some(attendant.client.clone())
+    }
+}
+
+/// A hub, which relays tunnels between its attendants.
+pub struct Hub {
+    network_key: NetworkKey,
+    keypair: SignKeypair,
+    attendants: Arc<Mutex<Attendants>>,
+}
+
+impl Hub {
+    pub fn new(network_key: NetworkKey, keypair: SignKeypair) -> Self {
+        Self {
+            network_key,
+            keypair,
+            attendants: Arc::default(),
+        }
+    }
+
+    /// The peers connected to the hub
+    pub fn attendants(&self) -> Vec<VerifyingKey> {
+        self.attendants.lock().unwrap().ids()
+    }
+
+    /// Accept a peer: do the server side of a handshake, then answer its calls until it
+    /// disconnects, while it is an attendant.
+    pub async fn accept<S, R>(&self, mut stream: S, rng: &mut R) -> Result<(), HubError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        R: CryptoRngCore + ?Sized,
+    {
+        let outcome = server_handshake(
+            &mut stream,
+            rng,
+            self.network_key.clone(),
+            self.keypair.clone(),
+            |_, _| true,
+        )
+        .await?;
+        let key = outcome.remote_key.clone();
+        let registry = Arc::new(self.registry(key.clone()));
+        let (client, connection) = connect(BoxStream::new(stream, &outcome), registry);
+
+        let connection_id = self.attendants.lock().unwrap().join(key.clone(), client);
+        let result = connection.await;
+        self.attendants.lock().unwrap().leave(&key, connection_id);
+        Ok(result?)
+    }
+
+    /// The methods an attendant can call
+    fn registry(&self, origin: VerifyingKey) -> Registry {
+        let mut registry = Registry::new();
+
+        let attendants = self.attendants.clone();
+        registry.add_source(&["hub", "attendants"], move |_| {
+            let events = attendants.lock().unwrap().subscribe();
+            events.map(|event| {
+                let event = serde_json::to_value(event).expect("attendants event serializes");
+                Ok(Body::Json(event))
+            })
+        });
+
+        let attendants = self.attendants.clone();
+        registry.add_duplex(
+            &["hub", "createTunnel"],
+            move |args, input| match create_tunnel(&attendants, &origin, &args) {
+                Ok(client) => relay(client, &origin, input),
+                Err(err) => stream::once(future::ready(Err(err))).boxed(),
+            },
+        );
+
+        registry
+    }
+}
+
+fn create_tunnel(
+    attendants: &Mutex<Attendants>,
+    origin: &VerifyingKey,
+    args: &[serde_json::Value],
+) -> RpcResult<RpcClient> {
+    let target = peer_arg(args)?;
+    if &target == origin {
+        return Err(RpcError::new("cannot tunnel to yourself"));
+    }
+    attendants
+        .lock()
+        .unwrap()
+        .client(&target)
+        .ok_or_else(|| RpcError::new(format!("{target} is not an attendant")))
+}
+
+/// Open a tunnel to the target, then relay what each side sends until both have ended.
+fn relay(target: RpcClient, origin: &VerifyingKey, input: RpcStream) -> RpcStream {
+    let (sender, output) = match target.duplex(&["hub", "createTunnel"], vec![json!(origin)]) {
+        Ok(tunnel) => tunnel,
+        Err(err) => {
+            return stream::once(future::ready(Err(RpcError::new(err.to_string())))).boxed()
+        }
+    };
+    let forward = input
+        .filter_map(|item| future::ready(item.ok()))
+        .map(Ok)
+        .forward(sender)
+        .map(|_| None)
+        .into_stream();
+    stream::select(output.map(Some), forward)
+        .filter_map(future::ready)
+        .boxed()
+}
@@ -0,0 +1,86 @@
+use futures::{channel::mpsc, ready, AsyncRead, AsyncWrite, Sink, StreamExt};
+use ppppp_muxrpc::{Body, RpcStream};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// A stream of bytes to another attendant, through the hub, as binary bodies of a duplex call.
+pub struct Tunnel {
+    sender: mpsc::Sender<Body>,
+    input: RpcStream,
+    /// What's left of the last body read
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl Tunnel {
+    pub(crate) fn new(sender: mpsc::Sender<Body>, input: RpcStream) -> Self {
+        Self {
+            sender,
+            input,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl AsyncRead for Tunnel {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        while this.position == this.buffer.len() {
+            match ready!(this.input.poll_next_unpin(cx)) {
+                Some(Ok(Body::Binary(bytes))) => {
+                    this.buffer = bytes;
+                    this.position = 0;
+                }
+                Some(Ok(_)) => {
+                    let err = io::Error::new(io::ErrorKind::InvalidData, "expected binary body");
+                    return Poll::Ready(Err(err));
+                }
+                Some(Err(err)) => return Poll::Ready(Err(io::Error::other(err))),
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+        let length = buf.len().min(this.buffer.len() - this.position);
+        buf[..length].copy_from_slice(&this.buffer[this.position..this.position + length]);
+        this.position += length;
+        Poll::Ready(Ok(length))
+    }
+}
+
+fn closed<E>(_: E) -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "tunnel is closed")
+}
+
+impl AsyncWrite for Tunnel {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.sender.poll_ready(cx)).map_err(closed)?;
+        Pin::new(&mut this.sender)
+            .start_send(Body::Binary(buf.to_vec()))
+            .map_err(closed)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().sender)
+            .poll_flush(cx)
+            .map_err(closed)
+    }
+
+    // end our side of the tunnel, which the other side reads as the end
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().sender.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
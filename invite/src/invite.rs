@@ -0,0 +1,370 @@
+use ppppp_bytes::{DeserializeBytesError, FromBytes};
+use ppppp_crypto::{SignDeserializeBytesError, VerifyingKey};
+use ppppp_msg::MsgId;
+use std::{fmt, num::ParseIntError, str::FromStr};
+
+/// The start of every invite URI
+pub const INVITE_PREFIX: &str = "ppppp://invite";
+
+const ACCOUNT_PREFIX: &str = "account.";
+
+#[derive(Debug, thiserror::Error)]
+pub enum InviteError {
+    #[error("invite must start with {INVITE_PREFIX}/")]
+    Prefix,
+    #[error("invite has no commands")]
+    NoCommands,
+    #[error("unknown invite command: {command}")]
+    UnknownCommand { command: String },
+    #[error("invite command {command} is missing its {argument} argument")]
+    MissingArgument {
+        command: &'static str,
+        argument: &'static str,
+    },
+    #[error("invalid port {port} in invite: {source}")]
+    Port {
+        port: String,
+        #[source]
+        source: ParseIntError,
+    },
+    #[error("invalid pubkey in invite: {0}")]
+    Pubkey(#[source] SignDeserializeBytesError),
+    #[error("invalid id in invite: {0}")]
+    Id(#[source] DeserializeBytesError),
+    #[error("invalid account in invite, expected {ACCOUNT_PREFIX}<id>: {account}")]
+    Account { account: String },
+    #[error("invite has an empty segment")]
+    EmptySegment,
+    #[error("invalid percent encoding in invite: {segment}")]
+    Encoding { segment: String },
+}
+
+/// Whether a byte can be in a segment as itself, without percent encoding
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+/// Percent encode a host or token, so it is a single segment however it was made.
+fn encode_segment(value: &str) -> String {
+    let mut segment = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if is_unreserved(byte) {
+            segment.push(byte as char);
+        } else {
+            segment.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    segment
+}
+
+fn decode_segment(segment: &str) -> Result<String, InviteError> {
+    let err = || InviteError::Encoding {
+        segment: segment.to_string(),
+    };
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).ok_or_else(err)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| err())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| err())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| err())
+}
+
+/// Parse the account and token arguments of a promise command.
+fn parse_promise<'a>(
+    command: &'static str,
+    args: &mut impl Iterator<Item = &'a str>,
+) -> Result<(MsgId, String), InviteError> {
+    let mut arg = |argument| {
+        args.next()
+            .ok_or(InviteError::MissingArgument { command, argument })
+    };
+    let account = arg("account")?;
+    let account_id = account
+        .strip_prefix(ACCOUNT_PREFIX)
+        .ok_or_else(|| InviteError::Account {
+            account: account.to_string(),
+        })?;
+    let account_id = MsgId::from_base58(account_id).map_err(InviteError::Id)?;
+    let token = decode_segment(arg("token")?)?;
+    Ok((account_id, token))
+}
+
+/// A command of an invite
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InviteCommand {
+    /// Connect to a hub, with a token the hub accepts
+    Join {
+        host: String,
+        port: u16,
+        pubkey: Box<VerifyingKey>,
+        token: String,
+    },
+    /// Follow an account
+    Follow { id: MsgId },
+    /// Connect to a peer through a tunnel on a hub
+    TunnelConnect {
+        hub_pubkey: Box<VerifyingKey>,
+        pubkey: Box<VerifyingKey>,
+    },
+    /// Redeem a promise, which an account made, to follow back
+    PromiseFollow { account_id: MsgId, token: String },
+    /// Redeem a promise, which an account made, to add our key to the account
+    PromiseAccountAdd { account_id: MsgId, token: String },
+}
+
+impl InviteCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InviteCommand::Join { .. } => "join",
+            InviteCommand::Follow { .. } => "follow",
+            InviteCommand::TunnelConnect { .. } => "tunnel-connect",
+            InviteCommand::PromiseFollow { .. } => "promise.follow",
+            InviteCommand::PromiseAccountAdd { .. } => "promise.account-add",
+        }
+    }
+
+    fn parse<'a>(
+        name: &str,
+        args: &mut impl Iterator<Item = &'a str>,
+    ) -> Result<Self, InviteError> {
+        match name {
+            "join" => {
+                let mut arg = |argument| {
+                    args.next().ok_or(InviteError::MissingArgument {
+                        command: "join",
+                        argument,
+                    })
+                };
+                let host = decode_segment(arg("host")?)?;
+                let port = arg("port")?;
+                let port = port.parse().map_err(|source| InviteError::Port {
+                    port: port.to_string(),
+                    source,
+                })?;
+                let pubkey =
+                    VerifyingKey::from_base58(arg("pubkey")?).map_err(InviteError::Pubkey)?;
+                let token = decode_segment(arg("token")?)?;
+                Ok(InviteCommand::Join {
+                    host,
+                    port,
+                    pubkey: Box::new(pubkey),
+                    token,
+                })
+            }
+            "follow" => {
+                let id = args.next().ok_or(InviteError::MissingArgument {
+                    command: "follow",
+                    argument: "id",
+                })?;
+                let id = MsgId::from_base58(id).map_err(InviteError::Id)?;
+                Ok(InviteCommand::Follow { id })
+            }
+            "tunnel-connect" => {
+                let mut arg = |argument| {
+                    let pubkey = args.next().ok_or(InviteError::MissingArgument {
+                        command: "tunnel-connect",
+                        argument,
+                    })?;
+                    VerifyingKey::from_base58(pubkey).map_err(InviteError::Pubkey)
+                };
+                let hub_pubkey = arg("hubPubkey")?;
+                let pubkey = arg("pubkey")?;
+                Ok(InviteCommand::TunnelConnect {
+                    hub_pubkey: Box::new(hub_pubkey),
+                    pubkey: Box::new(pubkey),
+                })
+            }
+            "promise.follow" => {
+                let (account_id, token) = parse_promise("promise.follow", args)?;
+                Ok(InviteCommand::PromiseFollow { account_id, token })
+            }
+            "promise.account-add" => {
+                let (account_id, token) = parse_promise("promise.account-add", args)?;
+                Ok(InviteCommand::PromiseAccountAdd { account_id, token })
+            }
+            _ => Err(InviteError::UnknownCommand {
+                command: name.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for InviteCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        match self {
+            InviteCommand::Join {
+                host,
+                port,
+                pubkey,
+                token,
+            } => write!(
+                f,
+                "/{}/{port}/{pubkey}/{}",
+                encode_segment(host),
+                encode_segment(token)
+            ),
+            InviteCommand::Follow { id } => write!(f, "/{id}"),
+            InviteCommand::TunnelConnect { hub_pubkey, pubkey } => {
+                write!(f, "/{hub_pubkey}/{pubkey}")
+            }
+            InviteCommand::PromiseFollow { account_id, token }
+            | InviteCommand::PromiseAccountAdd { account_id, token } => {
+                write!(f, "/{ACCOUNT_PREFIX}{account_id}/{}", encode_segment(token))
+            }
+        }
+    }
+}
+
+/// An invite: the commands to run, in order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invite {
+    pub commands: Vec<InviteCommand>,
+}
+
+impl Invite {
+    pub fn new(commands: Vec<InviteCommand>) -> Self {
+        Self { commands }
+    }
+}
+
+impl FromStr for Invite {
+    type Err = InviteError;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        let path = uri
+            .strip_prefix(INVITE_PREFIX)
+            .and_then(|path| path.strip_prefix('/'))
+            .ok_or(InviteError::Prefix)?;
+        if path.is_empty() {
+            return Err(InviteError::NoCommands);
+        }
+        let segments: Vec<&str> = path.split('/').collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(InviteError::EmptySegment);
+        }
+        let mut segments = segments.into_iter();
+        let mut commands = Vec::new();
+        while let Some(name) = segments.next() {
+            commands.push(InviteCommand::parse(name, &mut segments)?);
+        }
+        if commands.is_empty() {
+            return Err(InviteError::NoCommands);
+        }
+        Ok(Self { commands })
+    }
+}
+
+impl fmt::Display for Invite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{INVITE_PREFIX}")?;
+        for command in &self.commands {
+            write!(f, "/{command}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{OsRng, SigningKey};
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn generate_and_parse() -> Result<(), Box<dyn Error>> {
+        let pubkey = SigningKey::generate(&mut OsRng).verifying_key();
+        let peer_pubkey = SigningKey::generate(&mut OsRng).verifying_key();
+        let alice = MsgId::from_bytes(&[1; 16])?;
+        let invite = Invite::new(vec![
+            InviteCommand::Join {
+                host: "hub.example.com".to_string(),
+                port: 8008,
+                pubkey: Box::new(pubkey.clone()),
+                token: "hubToken".to_string(),
+            },
+            InviteCommand::Follow { id: alice },
+            InviteCommand::TunnelConnect {
+                hub_pubkey: Box::new(pubkey.clone()),
+                pubkey: Box::new(peer_pubkey.clone()),
+            },
+            InviteCommand::PromiseFollow {
+                account_id: alice,
+                token: "aliceToken".to_string(),
+            },
+            InviteCommand::PromiseAccountAdd {
+                account_id: alice,
+                token: "addToken".to_string(),
+            },
+        ]);
+
+        let uri = invite.to_string();
+        assert_eq!(
+            uri,
+            format!(
+                "ppppp://invite/join/hub.example.com/8008/{pubkey}/hubToken\
+                 /follow/{alice}/tunnel-connect/{pubkey}/{peer_pubkey}\
+                 /promise.follow/account.{alice}/aliceToken\
+                 /promise.account-add/account.{alice}/addToken"
+            )
+        );
+        assert_eq!(uri.parse::<Invite>()?, invite);
+
+        // a host or token with a slash stays one segment
+        let sneaky = Invite::new(vec![InviteCommand::PromiseFollow {
+            account_id: alice,
+            token: format!("token/follow/{alice}"),
+        }]);
+        let uri = sneaky.to_string();
+        assert_eq!(
+            uri,
+            format!("ppppp://invite/promise.follow/account.{alice}/token%2Ffollow%2F{alice}")
+        );
+        assert_eq!(uri.parse::<Invite>()?, sneaky);
+        assert!(matches!(
+            format!("ppppp://invite/follow/{alice}/promise.follow/account.{alice}/%2")
+                .parse::<Invite>(),
+            Err(InviteError::Encoding { .. })
+        ));
+
+        assert!(matches!(
+            format!("ppppp://invite/follow//{alice}").parse::<Invite>(),
+            Err(InviteError::EmptySegment)
+        ));
+        assert!(matches!(
+            format!("ppppp://invite/follow/{alice}/").parse::<Invite>(),
+            Err(InviteError::EmptySegment)
+        ));
+
+        let unknown = format!("ppppp://invite/follow/{alice}/unfollow/{alice}");
+        assert!(matches!(
+            unknown.parse::<Invite>(),
+            Err(InviteError::UnknownCommand { command }) if command == "unfollow"
+        ));
+        assert!(matches!(
+            "ppppp://invite/join/hub.example.com/8008".parse::<Invite>(),
+            Err(InviteError::MissingArgument {
+                command: "join",
+                argument: "pubkey"
+            })
+        ));
+        assert!(matches!(
+            "ppppp://invite/".parse::<Invite>(),
+            Err(InviteError::NoCommands)
+        ));
+        assert!(matches!(
+            "ssb://invite/follow".parse::<Invite>(),
+            Err(InviteError::Prefix)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+//! Invite codes, to onboard peers.
+//!
+//! - [staltz/ppppp-invite](https://github.com/staltz/ppppp-invite)
+//!
+//! An invite is a URI of commands, each a name and its arguments as path segments:
+//!
+//! ```text
+//! ppppp://invite/join/<host>/<port>/<pubkey>/<token>/follow/<id>/promise.follow/account.<id>/<token>
+//! ```
+//!
+//! - `join`: connect to a hub, with a token the hub accepts
+//! - `follow`: follow an account
+//! - `tunnel-connect/<hubPubkey>/<pubkey>`: connect to a peer through a tunnel on a hub
+//! - `promise.follow`: redeem a promise, which an account made, to follow back
+//! - `promise.account-add/account.<id>/<token>`: redeem a promise, which an account made, to add
+//!   our key to the account
+//!
+//! Hosts and tokens are percent encoded, so each is always one segment.
+
+mod invite;
+
+pub use crate::invite::{Invite, InviteCommand, InviteError, INVITE_PREFIX};
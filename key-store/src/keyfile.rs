@@ -0,0 +1,150 @@
+// https://github.com/staltz/ppppp-keypair
+
+use monostate::MustBe;
+use ppppp_base58 as base58;
+use ppppp_bytes::{FromBytes, ToBytes};
+use ppppp_crypto::{SignKeypair, SignatureError, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use zeroize::Zeroizing;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyfileError {
+    #[error("failed to parse keyfile json: {0}")]
+    Json(#[source] JsonError),
+    #[error("failed to decode private key base58: {0}")]
+    DecodeBase58(#[source] base58::DecodeError),
+    #[error("incorrect private key size: {size}")]
+    Size { size: usize },
+    #[error("invalid private key: {0}")]
+    Keypair(#[source] SignatureError),
+    #[error("public key {public} does not match private key")]
+    PublicKeyMismatch { public: Box<VerifyingKey> },
+}
+
+/// The JSON layout of a keypair, as used by ppppp-keypair
+#[derive(Deserialize)]
+struct KeypairJson {
+    #[allow(dead_code)]
+    curve: MustBe!("ed25519"),
+    public: VerifyingKey,
+    private: Zeroizing<String>,
+}
+
+#[derive(Serialize)]
+struct KeypairJsonRef<'a> {
+    curve: MustBe!("ed25519"),
+    public: &'a VerifyingKey,
+    private: &'a str,
+}
+
+static WARNING: &str = "\
+# WARNING: Never show this to anyone.
+# WARNING: Never edit it or use it on multiple devices at once.
+#
+# This is your SECRET, it gives you magical powers. With your secret you can
+# sign your messages so that your friends can verify that the messages came
+# from you. If anyone learns your secret, they can use it to impersonate you.
+#
+# If you use this secret on more than one device you will create a fork and
+# your friends will stop replicating your content.
+#
+";
+
+/// Serialize a keypair to the contents of a keyfile.
+pub fn to_keyfile_string(keypair: &SignKeypair) -> Zeroizing<String> {
+    let private = Zeroizing::new(keypair.to_base58());
+    let json = Zeroizing::new(
+        serde_json::to_string_pretty(&KeypairJsonRef {
+            curve: MustBe!("ed25519"),
+            public: keypair.verifying_key(),
+            private: &private,
+        })
+        .unwrap(),
+    );
+
+    let mut content = Zeroizing::new(String::with_capacity(WARNING.len() + json.len() + 200));
+    content.push_str(WARNING);
+    content.push_str(&json);
+    content.push_str("\n#\n");
+    content.push_str("# The only part of this file that's safe to share is your public name:\n");
+    content.push_str("#\n");
+    content.push_str(&format!("#   {}\n", keypair.verifying_key()));
+    content
+}
+
+/// Parse a keypair from the contents of a keyfile, ignoring `#` comment lines.
+pub fn from_keyfile_str(content: &str) -> Result<SignKeypair, KeyfileError> {
+    let mut json = Zeroizing::new(String::with_capacity(content.len()));
+    for line in content.lines() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        json.push_str(line);
+        json.push('\n');
+    }
+
+    let keypair_json: KeypairJson = serde_json::from_str(&json).map_err(KeyfileError::Json)?;
+
+    let private =
+        Zeroizing::new(base58::decode(&keypair_json.private).map_err(KeyfileError::DecodeBase58)?);
+    let private: Zeroizing<[u8; 64]> = Zeroizing::new(private.as_slice().try_into().map_err(
+        |_| KeyfileError::Size {
+            size: private.len(),
+        },
+    )?);
+    let keypair = SignKeypair::from_bytes(&private).map_err(KeyfileError::Keypair)?;
+
+    if keypair.verifying_key() != &keypair_json.public {
+        return Err(KeyfileError::PublicKeyMismatch {
+            public: Box::new(keypair_json.public),
+        });
+    }
+
+    Ok(keypair)
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn keyfile_roundtrip() -> Result<(), KeyfileError> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let content = to_keyfile_string(&keypair);
+        let decoded = from_keyfile_str(&content)?;
+        assert_eq!(decoded.signing_key(), keypair.signing_key());
+        assert_eq!(decoded.verifying_key(), keypair.verifying_key());
+        Ok(())
+    }
+
+    #[test]
+    fn keyfile_ppppp_keypair_json() -> Result<(), KeyfileError> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let content = format!(
+            r#"{{"curve":"ed25519","public":"{}","private":"{}"}}"#,
+            keypair.verifying_key(),
+            keypair,
+        );
+        let decoded = from_keyfile_str(&content)?;
+        assert_eq!(decoded.signing_key(), keypair.signing_key());
+        Ok(())
+    }
+
+    #[test]
+    fn keyfile_rejects_mismatched_public_key() {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let other = SignKeypair::generate(&mut OsRng);
+        let content = format!(
+            r#"{{"curve":"ed25519","public":"{}","private":"{}"}}"#,
+            other.verifying_key(),
+            keypair,
+        );
+        assert!(matches!(
+            from_keyfile_str(&content),
+            Err(KeyfileError::PublicKeyMismatch { .. })
+        ));
+    }
+}
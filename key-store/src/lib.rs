@@ -0,0 +1,5 @@
+mod keyfile;
+mod store;
+
+pub use crate::keyfile::{from_keyfile_str, to_keyfile_string, KeyfileError};
+pub use crate::store::{load_or_create, read, write, KeyStoreError};
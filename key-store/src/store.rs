@@ -0,0 +1,169 @@
+use ppppp_crypto::{CryptoRngCore, SignKeypair};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+use crate::keyfile::{from_keyfile_str, to_keyfile_string, KeyfileError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyStoreError {
+    #[error("io error on {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("keyfile {path} is world-readable (mode {mode:o})")]
+    WorldReadable { path: PathBuf, mode: u32 },
+    #[error("invalid keyfile {path}: {source}")]
+    Keyfile {
+        path: PathBuf,
+        #[source]
+        source: KeyfileError,
+    },
+}
+
+/// Read a keypair from a keyfile.
+///
+/// Refuses keyfiles which are readable by anyone other than the owner's user and group.
+pub fn read(path: impl AsRef<Path>) -> Result<SignKeypair, KeyStoreError> {
+    let path = path.as_ref();
+    let io_err = |source| KeyStoreError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut file = File::open(path).map_err(io_err)?;
+    check_permissions(path, &file)?;
+
+    let mut content = Zeroizing::new(String::new());
+    file.read_to_string(&mut content).map_err(io_err)?;
+
+    from_keyfile_str(&content).map_err(|source| KeyStoreError::Keyfile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Write a keypair to a new keyfile, only readable and writable by the owner.
+///
+/// Fails if the keyfile already exists.
+pub fn write(path: impl AsRef<Path>, keypair: &SignKeypair) -> Result<(), KeyStoreError> {
+    let path = path.as_ref();
+    let io_err = |source| KeyStoreError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_err)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path).map_err(io_err)?;
+    let content = to_keyfile_string(keypair);
+    file.write_all(content.as_bytes()).map_err(io_err)?;
+    file.sync_all().map_err(io_err)?;
+
+    Ok(())
+}
+
+/// Read a keypair from a keyfile, or generate and write a new keypair if the keyfile doesn't exist.
+pub fn load_or_create<R: CryptoRngCore + ?Sized>(
+    path: impl AsRef<Path>,
+    rng: &mut R,
+) -> Result<SignKeypair, KeyStoreError> {
+    let path = path.as_ref();
+    if path.exists() {
+        return read(path);
+    }
+    let keypair = SignKeypair::generate(rng);
+    write(path, &keypair)?;
+    Ok(keypair)
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path, file: &File) -> Result<(), KeyStoreError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    const WORLD_READABLE: u32 = 0o004;
+
+    let metadata = file.metadata().map_err(|source| KeyStoreError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & WORLD_READABLE != 0 {
+        return Err(KeyStoreError::WorldReadable {
+            path: path.to_path_buf(),
+            mode,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path, _file: &File) -> Result<(), KeyStoreError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::OsRng;
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn write_then_read() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("secret");
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        write(&path, &keypair)?;
+        let loaded = read(&path)?;
+        assert_eq!(loaded.signing_key(), keypair.signing_key());
+
+        assert!(write(&path, &keypair).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn load_or_create_is_stable() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("nested").join("secret");
+
+        let created = load_or_create(&path, &mut OsRng)?;
+        let loaded = load_or_create(&path, &mut OsRng)?;
+        assert_eq!(created.verifying_key(), loaded.verifying_key());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions() -> Result<(), Box<dyn Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("secret");
+        write(&path, &SignKeypair::generate(&mut OsRng))?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+        assert!(matches!(
+            read(&path),
+            Err(KeyStoreError::WorldReadable { mode: 0o644, .. })
+        ));
+        Ok(())
+    }
+}
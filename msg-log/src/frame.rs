@@ -0,0 +1,138 @@
+// A frame is:
+//
+// - header: capacity (u32), kind (u8), length (u32), crc32 of capacity + kind + length + body
+//   (u32), crc32 of the header before it (u32)
+// - body: `capacity` bytes, the first `length` of which are the record, the rest zeros
+// - trailer: capacity (u32), so the log can be read backwards
+//
+// The header has its own checksum, so a corrupt header is caught before its capacity is trusted
+// to find the body.
+//
+// All integers are little-endian.
+
+pub(crate) const HEADER_SIZE: u64 = 17;
+pub(crate) const TRAILER_SIZE: u64 = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum FrameKind {
+    Record = 0,
+    Tombstone = 1,
+}
+
+impl FrameKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FrameKind::Record),
+            1 => Some(FrameKind::Tombstone),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FrameHeader {
+    pub capacity: u32,
+    pub kind: FrameKind,
+    pub length: u32,
+    pub crc: u32,
+}
+
+impl FrameHeader {
+    /// Decode a header, or `None` if it doesn't match its checksum.
+    pub fn decode(bytes: &[u8; HEADER_SIZE as usize]) -> Option<Self> {
+        let header_crc = u32::from_le_bytes(bytes[13..17].try_into().unwrap());
+        if crc32fast::hash(&bytes[..13]) != header_crc {
+            return None;
+        }
+        let capacity = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let kind = FrameKind::from_u8(bytes[4])?;
+        let length = u32::from_le_bytes(bytes[5..9].try_into().unwrap());
+        let crc = u32::from_le_bytes(bytes[9..13].try_into().unwrap());
+        if length > capacity {
+            return None;
+        }
+        Some(Self {
+            capacity,
+            kind,
+            length,
+            crc,
+        })
+    }
+
+    pub fn frame_size(&self) -> u64 {
+        frame_size(self.capacity)
+    }
+
+    /// Whether the body matches the checksum in this header
+    pub fn check(&self, body: &[u8]) -> bool {
+        checksum(self.capacity, self.kind, self.length, body) == self.crc
+    }
+}
+
+pub(crate) fn frame_size(capacity: u32) -> u64 {
+    HEADER_SIZE + capacity as u64 + TRAILER_SIZE
+}
+
+fn checksum(capacity: u32, kind: FrameKind, length: u32, body: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&capacity.to_le_bytes());
+    hasher.update(&[kind as u8]);
+    hasher.update(&length.to_le_bytes());
+    hasher.update(body);
+    hasher.finalize()
+}
+
+/// Encode a whole frame, with a body of `capacity` bytes
+pub(crate) fn encode_frame(kind: FrameKind, data: &[u8], capacity: u32) -> Vec<u8> {
+    let mut frame = encode_header_and_body(kind, data, capacity);
+    frame.extend_from_slice(&capacity.to_le_bytes());
+    frame
+}
+
+/// Encode everything before the trailer, to rewrite a frame in place
+pub(crate) fn encode_header_and_body(kind: FrameKind, data: &[u8], capacity: u32) -> Vec<u8> {
+    let length = data.len() as u32;
+    let mut body = vec![0_u8; capacity as usize];
+    body[..data.len()].copy_from_slice(data);
+    let crc = checksum(capacity, kind, length, &body);
+
+    let mut bytes = Vec::with_capacity(frame_size(capacity) as usize);
+    bytes.extend_from_slice(&capacity.to_le_bytes());
+    bytes.push(kind as u8);
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(&crc.to_le_bytes());
+    let header_crc = crc32fast::hash(&bytes);
+    bytes.extend_from_slice(&header_crc.to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_roundtrip() {
+        let frame = encode_frame(FrameKind::Record, b"hello", 8);
+        assert_eq!(frame.len() as u64, frame_size(8));
+
+        let header = FrameHeader::decode(frame[0..17].try_into().unwrap()).unwrap();
+        assert_eq!(header.capacity, 8);
+        assert_eq!(header.kind, FrameKind::Record);
+        assert_eq!(header.length, 5);
+
+        let body = &frame[17..25];
+        assert!(header.check(body));
+        assert_eq!(&body[..5], b"hello");
+        assert_eq!(&frame[25..], &8_u32.to_le_bytes());
+
+        let mut tampered = body.to_vec();
+        tampered[0] ^= 1;
+        assert!(!header.check(&tampered));
+
+        // a flipped bit in the capacity is caught by the header checksum
+        let mut header_bytes: [u8; 17] = frame[0..17].try_into().unwrap();
+        header_bytes[0] ^= 1;
+        assert!(FrameHeader::decode(&header_bytes).is_none());
+    }
+}
@@ -0,0 +1,6 @@
+mod frame;
+mod log;
+mod msg_log;
+
+pub use crate::log::{Log, LogError, LogIter, LogRevIter};
+pub use crate::msg_log::{MsgLog, MsgLogError, MsgLogIter};
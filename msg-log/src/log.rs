@@ -0,0 +1,574 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::frame::{
+    encode_frame, encode_header_and_body, frame_size, FrameHeader, FrameKind, HEADER_SIZE,
+    TRAILER_SIZE,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("record of {length} bytes is too large")]
+    RecordTooLarge { length: usize },
+    #[error("no record at offset {offset}")]
+    InvalidOffset { offset: u64 },
+    #[error("record at offset {offset} is corrupt")]
+    Corrupt { offset: u64 },
+    #[error("record of {length} bytes does not fit in {capacity} bytes at offset {offset}")]
+    OverwriteTooLarge {
+        offset: u64,
+        capacity: u32,
+        length: usize,
+    },
+}
+
+enum FrameRead {
+    Valid {
+        header: FrameHeader,
+        body: Vec<u8>,
+    },
+    /// The body doesn't match its checksum
+    Corrupt {
+        header: FrameHeader,
+    },
+    /// The trailer doesn't match the header
+    BadTrailer {
+        header: FrameHeader,
+    },
+    /// The header doesn't match its checksum, so where the frame ends is unknown
+    BadHeader,
+    /// The frame runs past the end of the log
+    Torn,
+}
+
+/// An append-only log of byte records, addressed by offset.
+///
+/// Each record is framed with its length and a checksum. On open, a torn write at the tail of the
+/// log is truncated, and a corrupt record is replaced by a tombstone. A record whose header is
+/// corrupt is found from the trailers after it, reading backwards from the end of the log.
+///
+/// Deleted records are replaced by tombstones in place, and only removed by [`Log::compact`].
+#[derive(Debug)]
+pub struct Log {
+    path: PathBuf,
+    file: File,
+    end: u64,
+    repaired: bool,
+}
+
+impl Log {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LogError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let len = file.metadata()?.len();
+        let mut log = Self {
+            path,
+            file,
+            end: 0,
+            repaired: false,
+        };
+        log.recover(len)?;
+        Ok(log)
+    }
+
+    fn recover(&mut self, len: u64) -> Result<(), LogError> {
+        let mut offset = 0;
+        while offset < len {
+            match self.read_frame(offset, len)? {
+                FrameRead::Valid { header, .. } => {
+                    offset += header.frame_size();
+                }
+                FrameRead::Corrupt { header } => {
+                    self.write_in_place(offset, FrameKind::Tombstone, &[], header.capacity)?;
+                    self.repaired = true;
+                    offset += header.frame_size();
+                }
+                FrameRead::BadTrailer { header } => {
+                    // read the frame again, now that it can be read backwards
+                    self.write_trailer(offset + header.frame_size(), header.capacity)?;
+                }
+                FrameRead::BadHeader => match self.resync(offset, len)? {
+                    // whole frames follow, so this isn't the tail
+                    Some(capacity) if offset + frame_size(capacity) < len => {
+                        self.write_in_place(offset, FrameKind::Tombstone, &[], capacity)?;
+                        self.repaired = true;
+                        offset += frame_size(capacity);
+                    }
+                    // a tail which was never written whole, like zeros the file was extended by
+                    _ => {
+                        self.truncate(offset)?;
+                        break;
+                    }
+                },
+                FrameRead::Torn => {
+                    self.truncate(offset)?;
+                    break;
+                }
+            }
+        }
+        self.end = offset;
+        Ok(())
+    }
+
+    fn truncate(&mut self, offset: u64) -> Result<(), LogError> {
+        self.file.set_len(offset)?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    /// Find the capacity of the frame at an offset whose header is corrupt, by reading backwards
+    /// from the end of the log, through frames which are whole.
+    fn resync(&mut self, offset: u64, len: u64) -> Result<Option<u32>, LogError> {
+        let mut end = len;
+        while end >= offset + frame_size(0) {
+            let capacity = self.read_trailer(end)?;
+            let Some(start) = end.checked_sub(frame_size(capacity)) else {
+                return Ok(None);
+            };
+            if start <= offset {
+                return Ok((start == offset).then_some(capacity));
+            }
+            match self.read_frame(start, end)? {
+                FrameRead::Valid { .. } | FrameRead::Corrupt { .. } => end = start,
+                _ => return Ok(None),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The capacity of the frame which ends at an offset, from its trailer
+    fn read_trailer(&mut self, end: u64) -> Result<u32, LogError> {
+        let mut trailer = [0_u8; TRAILER_SIZE as usize];
+        self.file.seek(SeekFrom::Start(end - TRAILER_SIZE))?;
+        self.file.read_exact(&mut trailer)?;
+        Ok(u32::from_le_bytes(trailer))
+    }
+
+    fn write_trailer(&mut self, end: u64, capacity: u32) -> Result<(), LogError> {
+        self.file.seek(SeekFrom::Start(end - TRAILER_SIZE))?;
+        self.file.write_all(&capacity.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn read_frame(&mut self, offset: u64, end: u64) -> Result<FrameRead, LogError> {
+        if offset + HEADER_SIZE > end {
+            return Ok(FrameRead::Torn);
+        }
+
+        let mut header_bytes = [0_u8; HEADER_SIZE as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut header_bytes)?;
+        let Some(header) = FrameHeader::decode(&header_bytes) else {
+            return Ok(FrameRead::BadHeader);
+        };
+        if offset + header.frame_size() > end {
+            return Ok(FrameRead::Torn);
+        }
+
+        let mut body = vec![0_u8; header.capacity as usize];
+        self.file.read_exact(&mut body)?;
+        let mut trailer = [0_u8; TRAILER_SIZE as usize];
+        self.file.read_exact(&mut trailer)?;
+        if u32::from_le_bytes(trailer) != header.capacity {
+            return Ok(FrameRead::BadTrailer { header });
+        }
+
+        if header.check(&body) {
+            Ok(FrameRead::Valid { header, body })
+        } else {
+            Ok(FrameRead::Corrupt { header })
+        }
+    }
+
+    fn read_valid_frame(&mut self, offset: u64) -> Result<(FrameHeader, Vec<u8>), LogError> {
+        match self.read_frame(offset, self.end)? {
+            FrameRead::Valid { header, body } => Ok((header, body)),
+            FrameRead::Corrupt { .. } | FrameRead::BadTrailer { .. } => {
+                Err(LogError::Corrupt { offset })
+            }
+            // after recovery, every frame has a whole header, so this isn't the start of one
+            FrameRead::BadHeader | FrameRead::Torn => Err(LogError::InvalidOffset { offset }),
+        }
+    }
+
+    fn write_in_place(
+        &mut self,
+        offset: u64,
+        kind: FrameKind,
+        data: &[u8],
+        capacity: u32,
+    ) -> Result<(), LogError> {
+        let bytes = encode_header_and_body(kind, data, capacity);
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Append a record, returning its offset.
+    pub fn append(&mut self, data: &[u8]) -> Result<u64, LogError> {
+        let capacity: u32 = data
+            .len()
+            .try_into()
+            .map_err(|_| LogError::RecordTooLarge { length: data.len() })?;
+        let frame = encode_frame(FrameKind::Record, data, capacity);
+        let offset = self.end;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&frame)?;
+        self.end += frame.len() as u64;
+        Ok(offset)
+    }
+
+    /// Get the record at an offset, or `None` if it was deleted.
+    pub fn get(&mut self, offset: u64) -> Result<Option<Vec<u8>>, LogError> {
+        let (header, mut body) = self.read_valid_frame(offset)?;
+        match header.kind {
+            FrameKind::Record => {
+                body.truncate(header.length as usize);
+                Ok(Some(body))
+            }
+            FrameKind::Tombstone => Ok(None),
+        }
+    }
+
+    /// Replace the record at an offset with a record no larger than the original.
+    pub fn overwrite(&mut self, offset: u64, data: &[u8]) -> Result<(), LogError> {
+        let (header, _) = self.read_valid_frame(offset)?;
+        if header.kind == FrameKind::Tombstone {
+            return Err(LogError::InvalidOffset { offset });
+        }
+        if data.len() > header.capacity as usize {
+            return Err(LogError::OverwriteTooLarge {
+                offset,
+                capacity: header.capacity,
+                length: data.len(),
+            });
+        }
+        self.write_in_place(offset, FrameKind::Record, data, header.capacity)
+    }
+
+    /// Replace the record at an offset with a tombstone.
+    pub fn del(&mut self, offset: u64) -> Result<(), LogError> {
+        let (header, _) = self.read_valid_frame(offset)?;
+        self.write_in_place(offset, FrameKind::Tombstone, &[], header.capacity)
+    }
+
+    /// Flush all writes to disk.
+    pub fn sync(&mut self) -> Result<(), LogError> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Iterate over records from oldest to newest, skipping deleted records.
+    pub fn iter(&mut self) -> LogIter<'_> {
+        self.iter_from(0)
+    }
+
+    /// Iterate over records from an offset to the newest, skipping deleted records.
+    ///
+    /// The offset must be the offset of a record, or a past [`Log::size`].
+    pub fn iter_from(&mut self, offset: u64) -> LogIter<'_> {
+        LogIter { log: self, offset }
+    }
+
+    /// Iterate over records from newest to oldest, skipping deleted records.
+    pub fn iter_rev(&mut self) -> LogRevIter<'_> {
+        let offset = self.end;
+        LogRevIter { log: self, offset }
+    }
+
+    /// The size of the log in bytes, and the offset of the next record.
+    pub fn size(&self) -> u64 {
+        self.end
+    }
+
+    /// Whether a corrupt record was replaced by a tombstone on open.
+    pub fn is_repaired(&self) -> bool {
+        self.repaired
+    }
+
+    /// Rewrite the log without tombstones or unused space.
+    ///
+    /// Returns the new offset of every record which moved.
+    pub fn compact(&mut self) -> Result<HashMap<u64, u64>, LogError> {
+        let compact_path = self.path.with_extension("compacting");
+        let mut compact_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&compact_path)?;
+
+        let mut moved = HashMap::new();
+        let mut new_offset = 0_u64;
+        for record in self.iter() {
+            let (offset, data) = record?;
+            let frame = encode_frame(FrameKind::Record, &data, data.len() as u32);
+            compact_file.write_all(&frame)?;
+            if offset != new_offset {
+                moved.insert(offset, new_offset);
+            }
+            new_offset += frame.len() as u64;
+        }
+        compact_file.sync_all()?;
+        fs::rename(&compact_path, &self.path)?;
+
+        self.file = compact_file;
+        self.end = new_offset;
+        Ok(moved)
+    }
+}
+
+pub struct LogIter<'a> {
+    log: &'a mut Log,
+    offset: u64,
+}
+
+impl<'a> Iterator for LogIter<'a> {
+    type Item = Result<(u64, Vec<u8>), LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.log.end {
+            let offset = self.offset;
+            let (header, mut body) = match self.log.read_valid_frame(offset) {
+                Ok(frame) => frame,
+                Err(err) => {
+                    self.offset = self.log.end;
+                    return Some(Err(err));
+                }
+            };
+            self.offset += header.frame_size();
+            if header.kind == FrameKind::Record {
+                body.truncate(header.length as usize);
+                return Some(Ok((offset, body)));
+            }
+        }
+        None
+    }
+}
+
+pub struct LogRevIter<'a> {
+    log: &'a mut Log,
+    offset: u64,
+}
+
+impl<'a> Iterator for LogRevIter<'a> {
+    type Item = Result<(u64, Vec<u8>), LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset > 0 {
+            let result = self.log.read_trailer(self.offset).and_then(|capacity| {
+                self.offset
+                    .checked_sub(frame_size(capacity))
+                    .ok_or(LogError::Corrupt {
+                        offset: self.offset,
+                    })
+            });
+            let offset = match result {
+                Ok(offset) => offset,
+                Err(err) => {
+                    self.offset = 0;
+                    return Some(Err(err));
+                }
+            };
+            let (header, mut body) = match self.log.read_valid_frame(offset) {
+                Ok(frame) => frame,
+                Err(err) => {
+                    self.offset = 0;
+                    return Some(Err(err));
+                }
+            };
+            self.offset = offset;
+            if header.kind == FrameKind::Record {
+                body.truncate(header.length as usize);
+                return Some(Ok((offset, body)));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+
+    fn collect(iter: impl Iterator<Item = Result<(u64, Vec<u8>), LogError>>) -> Vec<Vec<u8>> {
+        iter.map(|record| record.unwrap().1).collect()
+    }
+
+    #[test]
+    fn append_get_iter() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut log = Log::open(dir.path().join("log"))?;
+
+        let a = log.append(b"a")?;
+        let b = log.append(b"bb")?;
+        let c = log.append(b"ccc")?;
+
+        assert_eq!(log.get(b)?, Some(b"bb".to_vec()));
+        assert_eq!(
+            collect(log.iter()),
+            vec![b"a".to_vec(), b"bb".to_vec(), b"ccc".to_vec()]
+        );
+        assert_eq!(
+            collect(log.iter_rev()),
+            vec![b"ccc".to_vec(), b"bb".to_vec(), b"a".to_vec()]
+        );
+
+        log.sync()?;
+        drop(log);
+        let mut log = Log::open(dir.path().join("log"))?;
+        assert_eq!(log.get(a)?, Some(b"a".to_vec()));
+        assert_eq!(log.get(c)?, Some(b"ccc".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn del_overwrite_compact() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut log = Log::open(dir.path().join("log"))?;
+
+        let a = log.append(b"aaaa")?;
+        let b = log.append(b"bbbb")?;
+        let c = log.append(b"cccc")?;
+
+        log.del(a)?;
+        log.overwrite(b, b"b")?;
+        assert!(matches!(
+            log.overwrite(c, b"ccccc"),
+            Err(LogError::OverwriteTooLarge { .. })
+        ));
+        assert_eq!(log.get(a)?, None);
+        assert_eq!(log.get(b)?, Some(b"b".to_vec()));
+        assert_eq!(collect(log.iter()), vec![b"b".to_vec(), b"cccc".to_vec()]);
+        assert_eq!(
+            collect(log.iter_rev()),
+            vec![b"cccc".to_vec(), b"b".to_vec()]
+        );
+
+        let size = log.size();
+        let moved = log.compact()?;
+        assert!(log.size() < size);
+        assert_eq!(moved.get(&b), Some(&0));
+        assert_eq!(log.get(moved[&c])?, Some(b"cccc".to_vec()));
+        assert_eq!(collect(log.iter()), vec![b"b".to_vec(), b"cccc".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_torn_tail() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+        let mut log = Log::open(&path)?;
+        log.append(b"first")?;
+        let second = log.append(b"second")?;
+        let size = log.size();
+        drop(log);
+
+        // a write cut off halfway through the frame
+        let frame = encode_frame(FrameKind::Record, b"third", 5);
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&frame[..frame.len() / 2])?;
+        drop(file);
+
+        let mut log = Log::open(&path)?;
+        assert!(!log.is_repaired());
+        assert_eq!(log.size(), size);
+        assert_eq!(fs::metadata(&path)?.len(), size);
+        assert_eq!(log.get(second)?, Some(b"second".to_vec()));
+        let third = log.append(b"third")?;
+        assert_eq!(log.get(third)?, Some(b"third".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_garbage_tail() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+        let mut log = Log::open(&path)?;
+        log.append(b"first")?;
+        let second = log.append(b"second")?;
+        let size = log.size();
+        drop(log);
+
+        // a file extended by a crash, without the data written
+        let garbage: Vec<u8> = (0..64_u32).map(|i| (i * 167 + 13) as u8).collect();
+        for tail in [vec![0_u8; 64], garbage] {
+            let mut file = OpenOptions::new().append(true).open(&path)?;
+            file.write_all(&tail)?;
+            drop(file);
+
+            let mut log = Log::open(&path)?;
+            assert_eq!(log.size(), size);
+            assert_eq!(fs::metadata(&path)?.len(), size);
+            assert_eq!(log.get(second)?, Some(b"second".to_vec()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn tombstone_corrupt_record() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+        let mut log = Log::open(&path)?;
+        let first = log.append(b"first")?;
+        log.append(b"second")?;
+        drop(log);
+
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::Start(first + HEADER_SIZE))?;
+        file.write_all(b"F")?;
+        drop(file);
+
+        let mut log = Log::open(&path)?;
+        assert_eq!(log.get(first)?, None);
+        assert_eq!(collect(log.iter()), vec![b"second".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn tombstone_corrupt_header() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+        let mut log = Log::open(&path)?;
+        let first = log.append(b"first")?;
+        let second = log.append(b"second")?;
+        let third = log.append(b"third")?;
+        let size = log.size();
+        drop(log);
+
+        // a flipped bit in the capacity of a frame in the middle of the log
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut byte = [0_u8; 1];
+        file.seek(SeekFrom::Start(second))?;
+        file.read_exact(&mut byte)?;
+        file.seek(SeekFrom::Start(second))?;
+        file.write_all(&[byte[0] ^ 0x80])?;
+        drop(file);
+
+        let mut log = Log::open(&path)?;
+        assert!(log.is_repaired());
+        assert_eq!(log.size(), size);
+        assert_eq!(fs::metadata(&path)?.len(), size);
+        assert_eq!(log.get(first)?, Some(b"first".to_vec()));
+        assert_eq!(log.get(second)?, None);
+        assert_eq!(log.get(third)?, Some(b"third".to_vec()));
+        assert_eq!(
+            collect(log.iter_rev()),
+            vec![b"third".to_vec(), b"first".to_vec()]
+        );
+        Ok(())
+    }
+}
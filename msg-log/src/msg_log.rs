@@ -0,0 +1,175 @@
+use ppppp_msg::{Msg, MsgCborError};
+use std::{collections::HashMap, path::Path};
+
+use crate::log::{Log, LogError, LogIter, LogRevIter};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MsgLogError {
+    #[error("log error: {0}")]
+    Log(#[from] LogError),
+    #[error("failed to encode or decode msg: {0}")]
+    Cbor(#[from] MsgCborError),
+}
+
+/// An append-only log of msgs, addressed by offset, with each msg encoded as CBOR.
+#[derive(Debug)]
+pub struct MsgLog {
+    log: Log,
+}
+
+impl MsgLog {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MsgLogError> {
+        Ok(Self {
+            log: Log::open(path)?,
+        })
+    }
+
+    fn encode(msg: &Msg) -> Result<Vec<u8>, MsgLogError> {
+        Ok(msg.to_cbor()?)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Msg, MsgLogError> {
+        Ok(Msg::from_cbor(bytes)?)
+    }
+
+    /// Append a msg, returning its offset.
+    pub fn append(&mut self, msg: &Msg) -> Result<u64, MsgLogError> {
+        let bytes = Self::encode(msg)?;
+        Ok(self.log.append(&bytes)?)
+    }
+
+    /// Get the msg at an offset, or `None` if it was deleted.
+    pub fn get(&mut self, offset: u64) -> Result<Option<Msg>, MsgLogError> {
+        self.log
+            .get(offset)?
+            .map(|bytes| Self::decode(&bytes))
+            .transpose()
+    }
+
+    /// Delete the msg at an offset, leaving a tombstone until the log is compacted.
+    pub fn del(&mut self, offset: u64) -> Result<(), MsgLogError> {
+        Ok(self.log.del(offset)?)
+    }
+
+    /// Erase the data of the msg at an offset, keeping its metadata and signature.
+    pub fn erase(&mut self, offset: u64) -> Result<(), MsgLogError> {
+        let Some(msg) = self.get(offset)? else {
+            return Err(LogError::InvalidOffset { offset }.into());
+        };
+        let bytes = Self::encode(&msg.erase())?;
+        Ok(self.log.overwrite(offset, &bytes)?)
+    }
+
+    pub fn sync(&mut self) -> Result<(), MsgLogError> {
+        Ok(self.log.sync()?)
+    }
+
+    /// Iterate over msgs from oldest to newest.
+    pub fn iter(&mut self) -> MsgLogIter<LogIter<'_>> {
+        MsgLogIter(self.log.iter())
+    }
+
+    /// Iterate over msgs from an offset to the newest.
+    ///
+    /// The offset must be the offset of a msg, or a past [`MsgLog::size`].
+    pub fn iter_from(&mut self, offset: u64) -> MsgLogIter<LogIter<'_>> {
+        MsgLogIter(self.log.iter_from(offset))
+    }
+
+    /// Iterate over msgs from newest to oldest.
+    pub fn iter_rev(&mut self) -> MsgLogIter<LogRevIter<'_>> {
+        MsgLogIter(self.log.iter_rev())
+    }
+
+    /// The size of the log in bytes, and the offset of the next msg.
+    pub fn size(&self) -> u64 {
+        self.log.size()
+    }
+
+    /// Whether a corrupt msg was replaced by a tombstone on open.
+    pub fn is_repaired(&self) -> bool {
+        self.log.is_repaired()
+    }
+
+    /// Rewrite the log without deleted msgs or erased data.
+    ///
+    /// Returns the new offset of every msg which moved.
+    pub fn compact(&mut self) -> Result<HashMap<u64, u64>, MsgLogError> {
+        Ok(self.log.compact()?)
+    }
+}
+
+pub struct MsgLogIter<I>(I);
+
+impl<I> Iterator for MsgLogIter<I>
+where
+    I: Iterator<Item = Result<(u64, Vec<u8>), LogError>>,
+{
+    type Item = Result<(u64, Msg), MsgLogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.0.next()?;
+        Some(
+            record
+                .map_err(MsgLogError::from)
+                .and_then(|(offset, bytes)| Ok((offset, MsgLog::decode(&bytes)?))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{OsRng, SignKeypair};
+    use ppppp_msg::{AccountId, MsgCreateOpts, MsgData, MsgDomain};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn msg_log_roundtrip() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut log = MsgLog::open(dir.path().join("log"))?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let msgs = (0..3)
+            .map(|i| {
+                Msg::create(
+                    MsgCreateOpts::builder()
+                        .data(MsgData::try_from(
+                            json!({ "text": format!("hello {}", i) }),
+                        )?)
+                        .domain(domain.clone())
+                        .sign_keypair(keypair.clone())
+                        .account_id(AccountId::Any)
+                        .build(),
+                )
+                .map_err(Box::<dyn Error>::from)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut offsets = Vec::new();
+        for msg in &msgs {
+            offsets.push(log.append(msg)?);
+        }
+
+        let ids: Vec<_> = log
+            .iter_rev()
+            .map(|record| record.unwrap().1.id().unwrap())
+            .collect();
+        let expected: Vec<_> = msgs.iter().rev().map(|msg| msg.id().unwrap()).collect();
+        assert_eq!(ids, expected);
+
+        log.erase(offsets[1])?;
+        let erased = log.get(offsets[1])?.unwrap();
+        assert!(erased.data().is_null());
+        assert_eq!(erased.id()?, msgs[1].id()?);
+        erased.verify_signature()?;
+
+        log.del(offsets[0])?;
+        assert!(log.get(offsets[0])?.is_none());
+        assert_eq!(log.iter().count(), 2);
+        Ok(())
+    }
+}
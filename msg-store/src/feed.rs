@@ -0,0 +1,212 @@
+use ppppp_crypto::SignKeypair;
+use ppppp_msg::{
+    validate, Account, AccountId, Msg, MsgCreateOpts, MsgData, MsgDomain, MsgId, Tangle, TangleType,
+};
+use std::collections::HashMap;
+use typed_builder::TypedBuilder;
+
+use crate::{MsgStore, MsgStoreError};
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct FeedPublishOpts {
+    #[builder(setter(into))]
+    pub account_id: MsgId,
+    #[builder(setter(into))]
+    pub domain: MsgDomain,
+    #[builder(setter(into))]
+    pub data: MsgData,
+    #[builder(setter(into))]
+    pub sign_keypair: SignKeypair,
+}
+
+impl MsgStore {
+    /// Get the current state of an account, from its account tangle.
+    pub fn account(&mut self, account_id: &MsgId) -> Result<Account, MsgStoreError> {
+        let tangle = self.tangle(account_id)?.ok_or(MsgStoreError::MsgNotFound {
+            msg_id: *account_id,
+        })?;
+        let mut msgs = HashMap::new();
+        for msg_id in tangle.topo_sort() {
+            if let Some(msg) = self.get(&msg_id)? {
+                msgs.insert(msg_id, msg);
+            }
+        }
+        Ok(Account::from_tangle(&tangle, |msg_id| msgs.remove(msg_id))?)
+    }
+
+    /// Validate a msg as part of a tangle, then add it.
+    ///
+    /// The tangle root must already be stored, unless the msg is the tangle root.
+    pub fn add_validated(
+        &mut self,
+        msg: Msg,
+        tangle_root_msg_id: &MsgId,
+    ) -> Result<MsgId, MsgStoreError> {
+        let msg_id = msg.id()?;
+        if self.has(&msg_id) {
+            return Ok(msg_id);
+        }
+
+        if &msg_id == tangle_root_msg_id {
+            let mut tangle = Tangle::new(msg_id);
+            tangle.add(&msg_id, &msg);
+            validate(&msg, &msg_id, &tangle, &[], tangle_root_msg_id)?;
+            if tangle.get_type()? == TangleType::Account {
+                Account::from_tangle(&tangle, |_| None)?;
+            }
+            return self.add(msg);
+        }
+
+        let tangle = self
+            .tangle(tangle_root_msg_id)?
+            .ok_or(MsgStoreError::MsgNotFound {
+                msg_id: *tangle_root_msg_id,
+            })?;
+        if tangle.get_type()? == TangleType::Account {
+            validate(&msg, &msg_id, &tangle, &[], tangle_root_msg_id)?;
+            // check the signing key has the power to add or del keys
+            self.account(tangle_root_msg_id)?.apply(&msg_id, &msg)?;
+        } else {
+            let verifying_keys = match msg.metadata().account_id() {
+                AccountId::Tangle(account_id) => self.account(account_id)?.verifying_keys(),
+                _ => Vec::new(),
+            };
+            validate(&msg, &msg_id, &tangle, &verifying_keys, tangle_root_msg_id)?;
+        }
+
+        self.add(msg)
+    }
+
+    /// Publish a msg to an account's feed for a domain.
+    ///
+    /// Creates the feed's moot if it doesn't exist yet, and references the tips of the feed tangle
+    /// and the account tangle.
+    pub fn publish(&mut self, opts: FeedPublishOpts) -> Result<(MsgId, Msg), MsgStoreError> {
+        let FeedPublishOpts {
+            account_id,
+            domain,
+            data,
+            sign_keypair,
+        } = opts;
+
+        let account_tangle = self
+            .tangle(&account_id)?
+            .ok_or(MsgStoreError::MsgNotFound { msg_id: account_id })?;
+        let mut account_tips: Vec<MsgId> = account_tangle.get_tips().into_iter().collect();
+        account_tips.sort();
+
+        let moot_id = Msg::get_moot_id(AccountId::Tangle(account_id), domain.clone())?;
+        let moot = if self.has(&moot_id) {
+            None
+        } else {
+            Some(Msg::create_moot(
+                AccountId::Tangle(account_id),
+                domain.clone(),
+                sign_keypair.clone(),
+            )?)
+        };
+        let feed_tangle = match &moot {
+            Some(moot) => {
+                let mut tangle = Tangle::new(moot_id);
+                tangle.add(&moot_id, moot);
+                tangle
+            }
+            None => self.tangle(&moot_id)?.unwrap(),
+        };
+
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(data)
+                .domain(domain)
+                .sign_keypair(sign_keypair)
+                .account_id(AccountId::Tangle(account_id))
+                .account_tips(Some(account_tips))
+                .tangles(HashMap::from([(moot_id, feed_tangle.clone())]))
+                .build(),
+        )?;
+
+        // validate the msg before storing the moot, so a failed publish stores nothing
+        let verifying_keys = self.account(&account_id)?.verifying_keys();
+        validate(&msg, &msg.id()?, &feed_tangle, &verifying_keys, &moot_id)?;
+        if let Some(moot) = moot {
+            self.add_validated(moot, &moot_id)?;
+        }
+        let msg_id = self.add_validated(msg.clone(), &moot_id)?;
+
+        Ok((msg_id, msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng};
+    use serde_json::json;
+    use std::{collections::HashSet, error::Error};
+
+    use super::*;
+
+    #[test]
+    fn publish_to_feed() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut store = MsgStore::open(dir.path().join("log"))?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        store.add_validated(account, &account_id)?;
+
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let mut msg_ids = Vec::new();
+        for i in 0..2 {
+            let (msg_id, msg) = store.publish(
+                FeedPublishOpts::builder()
+                    .account_id(account_id)
+                    .domain(domain.clone())
+                    .data(MsgData::try_from(
+                        json!({ "text": format!("hello {}", i) }),
+                    )?)
+                    .sign_keypair(keypair.clone())
+                    .build(),
+            )?;
+            assert_eq!(msg.metadata().account_tips(), &Some(vec![account_id]));
+            msg_ids.push(msg_id);
+        }
+
+        let feed = store.feed(&account_id, &domain)?.unwrap();
+        let moot = feed.get_root()?;
+        assert!(moot.is_moot(Some(AccountId::Tangle(account_id)), Some(domain.clone())));
+        assert_eq!(feed.size(), 3);
+        assert_eq!(feed.get_tips(), HashSet::from([msg_ids[1]]));
+
+        // a key which is not part of the account cannot publish
+        let stranger = SignKeypair::generate(&mut OsRng);
+        let result = store.publish(
+            FeedPublishOpts::builder()
+                .account_id(account_id)
+                .domain(domain.clone())
+                .data(MsgData::try_from(json!({ "text": "impostor" }))?)
+                .sign_keypair(stranger)
+                .build(),
+        );
+        assert!(matches!(result, Err(MsgStoreError::Validate(_))));
+
+        // nor create the moot of a new feed
+        let domain: MsgDomain = "about".to_string().try_into()?;
+        let result = store.publish(
+            FeedPublishOpts::builder()
+                .account_id(account_id)
+                .domain(domain.clone())
+                .data(MsgData::try_from(json!({ "name": "impostor" }))?)
+                .sign_keypair(SignKeypair::generate(&mut OsRng))
+                .build(),
+        );
+        assert!(matches!(result, Err(MsgStoreError::Validate(_))));
+        let moot_id = Msg::get_moot_id(AccountId::Tangle(account_id), domain)?;
+        assert!(!store.has(&moot_id));
+        Ok(())
+    }
+}
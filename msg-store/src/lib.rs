@@ -0,0 +1,5 @@
+mod feed;
+mod store;
+
+pub use crate::feed::FeedPublishOpts;
+pub use crate::store::{MsgStore, MsgStoreError};
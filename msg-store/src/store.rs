@@ -0,0 +1,390 @@
+use ppppp_msg::{
+    AccountError, AccountId, Msg, MsgDomain, MsgError, MsgId, Tangle,
+    TangleMissingRootMessageError, ValidateError,
+};
+use ppppp_msg_log::{MsgLog, MsgLogError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MsgStoreError {
+    #[error("log error: {0}")]
+    Log(#[from] MsgLogError),
+    #[error("io error on {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("msg error: {0}")]
+    Msg(#[from] MsgError),
+    #[error("msg not found: {msg_id}")]
+    MsgNotFound { msg_id: MsgId },
+    #[error(transparent)]
+    TangleMissingRootMessage(#[from] TangleMissingRootMessageError),
+    #[error("invalid msg: {0}")]
+    Validate(#[from] ValidateError),
+    #[error("account error: {0}")]
+    Account(#[from] AccountError),
+}
+
+/// A store of msgs, persisted in a [`MsgLog`], with indexes to get msgs by id and tangles by root.
+///
+/// The indexes are checkpointed to a file next to the log, on [`MsgStore::checkpoint`] and on
+/// drop. On open, the checkpoint is caught up with the msgs appended after it, and the indexes
+/// are only rebuilt from the whole log if the checkpoint is missing or stale.
+#[derive(Debug)]
+pub struct MsgStore {
+    log: MsgLog,
+    index_path: PathBuf,
+    indexes: Indexes,
+}
+
+/// The indexes of a [`MsgStore`]
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Indexes {
+    /// The size of the log the indexes are up to date with
+    log_size: u64,
+    offsets: HashMap<MsgId, u64>,
+    tangles: HashMap<MsgId, Tangle>,
+    tangle_msg_ids: HashMap<MsgId, HashSet<MsgId>>,
+}
+
+impl MsgStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MsgStoreError> {
+        let path = path.as_ref();
+        let log = MsgLog::open(path)?;
+        let index_path = path.with_extension("index");
+        let indexes = match Self::read_indexes(&index_path) {
+            // the log only grew since the checkpoint, so the indexes are missing only new msgs
+            Some(indexes) if indexes.log_size <= log.size() && !log.is_repaired() => indexes,
+            _ => Indexes::default(),
+        };
+        let mut store = Self {
+            log,
+            index_path,
+            indexes,
+        };
+
+        let mut msgs = Vec::new();
+        for record in store.log.iter_from(store.indexes.log_size) {
+            let (offset, msg) = record?;
+            msgs.push((offset, msg));
+        }
+        for (offset, msg) in msgs {
+            let msg_id = msg.id()?;
+            store.indexes.offsets.insert(msg_id, offset);
+            store.index(&msg_id, &msg)?;
+        }
+        Ok(store)
+    }
+
+    fn read_indexes(index_path: &Path) -> Option<Indexes> {
+        let content = fs::read(index_path).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    /// Persist the indexes next to the log, so the next open only scans msgs added after this.
+    pub fn checkpoint(&mut self) -> Result<(), MsgStoreError> {
+        let io_err = |source| MsgStoreError::Io {
+            path: self.index_path.clone(),
+            source,
+        };
+        // the indexes must not be ahead of the log on disk
+        self.log.sync()?;
+        self.indexes.log_size = self.log.size();
+        let content = serde_json::to_vec(&self.indexes).expect("indexes serialize to json");
+
+        let dir = self.index_path.parent().unwrap_or(Path::new("."));
+        let file = NamedTempFile::new_in(dir).map_err(io_err)?;
+        fs::write(file.path(), content).map_err(io_err)?;
+        file.persist(&self.index_path)
+            .map_err(|err| io_err(err.error))?;
+        Ok(())
+    }
+
+    /// Remove the checkpoint before changing msgs in place, as it can't be caught up with that.
+    fn invalidate_checkpoint(&mut self) -> Result<(), MsgStoreError> {
+        match fs::remove_file(&self.index_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(MsgStoreError::Io {
+                path: self.index_path.clone(),
+                source: err,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Add a msg to the indexes of the tangles it belongs to.
+    fn index(&mut self, msg_id: &MsgId, msg: &Msg) -> Result<(), MsgStoreError> {
+        for root_msg_id in msg.metadata().tangles().keys() {
+            if !self.indexes.tangles.contains_key(root_msg_id) {
+                let mut tangle = Tangle::new(*root_msg_id);
+                if let Some(root_msg) = self.get(root_msg_id)? {
+                    tangle.add(root_msg_id, &root_msg);
+                }
+                self.indexes.tangles.insert(*root_msg_id, tangle);
+            }
+            self.indexes
+                .tangles
+                .get_mut(root_msg_id)
+                .unwrap()
+                .add(msg_id, msg);
+            self.indexes
+                .tangle_msg_ids
+                .entry(*root_msg_id)
+                .or_default()
+                .insert(*msg_id);
+        }
+
+        // a root which arrives after some of its descendants
+        if let Some(tangle) = self.indexes.tangles.get_mut(msg_id) {
+            tangle.add(msg_id, msg);
+        }
+
+        Ok(())
+    }
+
+    /// Add a msg, returning its id. Adding a msg which is already stored does nothing.
+    pub fn add(&mut self, msg: Msg) -> Result<MsgId, MsgStoreError> {
+        let msg_id = msg.id()?;
+        if self.indexes.offsets.contains_key(&msg_id) {
+            return Ok(msg_id);
+        }
+
+        let offset = self.log.append(&msg)?;
+        self.indexes.offsets.insert(msg_id, offset);
+        self.index(&msg_id, &msg)?;
+
+        Ok(msg_id)
+    }
+
+    pub fn has(&self, msg_id: &MsgId) -> bool {
+        self.indexes.offsets.contains_key(msg_id)
+    }
+
+    pub fn get(&mut self, msg_id: &MsgId) -> Result<Option<Msg>, MsgStoreError> {
+        let Some(offset) = self.indexes.offsets.get(msg_id) else {
+            return Ok(None);
+        };
+        Ok(self.log.get(*offset)?)
+    }
+
+    /// Get the tangle with this root msg, from the indexes.
+    ///
+    /// Returns `None` if neither the root msg nor any of its descendants are stored.
+    pub fn tangle(&mut self, root_msg_id: &MsgId) -> Result<Option<Tangle>, MsgStoreError> {
+        if let Some(tangle) = self.indexes.tangles.get(root_msg_id) {
+            return Ok(Some(tangle.clone()));
+        }
+        let Some(root_msg) = self.get(root_msg_id)? else {
+            return Ok(None);
+        };
+        let mut tangle = Tangle::new(*root_msg_id);
+        tangle.add(root_msg_id, &root_msg);
+        Ok(Some(tangle))
+    }
+
+    /// Get the feed tangle of an account for a domain.
+    pub fn feed(
+        &mut self,
+        account_id: &MsgId,
+        domain: &MsgDomain,
+    ) -> Result<Option<Tangle>, MsgStoreError> {
+        let moot_id = Msg::get_moot_id(AccountId::Tangle(*account_id), domain.clone())?;
+        self.tangle(&moot_id)
+    }
+
+    /// Iterate over the ids of all stored msgs, in no particular order.
+    pub fn msg_ids(&self) -> impl Iterator<Item = &MsgId> {
+        self.indexes.offsets.keys()
+    }
+
+    /// Delete a msg, and remove it from the tangles it belongs to.
+    pub fn del(&mut self, msg_id: &MsgId) -> Result<(), MsgStoreError> {
+        let msg = self
+            .get(msg_id)?
+            .ok_or(MsgStoreError::MsgNotFound { msg_id: *msg_id })?;
+        self.invalidate_checkpoint()?;
+        let offset = self.indexes.offsets.remove(msg_id).unwrap();
+        self.log.del(offset)?;
+
+        let mut root_msg_ids: Vec<MsgId> = msg.metadata().tangles().keys().cloned().collect();
+        root_msg_ids.push(*msg_id);
+        for root_msg_id in root_msg_ids {
+            self.reindex_tangle(&root_msg_id)?;
+        }
+        Ok(())
+    }
+
+    /// Erase the data of a msg, keeping its metadata so it stays in its tangles.
+    pub fn erase(&mut self, msg_id: &MsgId) -> Result<(), MsgStoreError> {
+        let offset = self
+            .indexes
+            .offsets
+            .get(msg_id)
+            .ok_or(MsgStoreError::MsgNotFound { msg_id: *msg_id })?;
+        let offset = *offset;
+        self.invalidate_checkpoint()?;
+        self.log.erase(offset)?;
+        Ok(())
+    }
+
+    fn reindex_tangle(&mut self, root_msg_id: &MsgId) -> Result<(), MsgStoreError> {
+        if self.indexes.tangles.remove(root_msg_id).is_none() {
+            return Ok(());
+        }
+        let mut tangle = Tangle::new(*root_msg_id);
+        if let Some(root_msg) = self.get(root_msg_id)? {
+            tangle.add(root_msg_id, &root_msg);
+        }
+        let mut msg_ids = self
+            .indexes
+            .tangle_msg_ids
+            .remove(root_msg_id)
+            .unwrap_or_default();
+        let mut msgs = Vec::new();
+        for msg_id in msg_ids.clone() {
+            match self.get(&msg_id)? {
+                Some(msg) => msgs.push((msg_id, msg)),
+                None => {
+                    msg_ids.remove(&msg_id);
+                }
+            }
+        }
+        msgs.sort_by_key(|(_, msg)| msg.metadata().tangles()[root_msg_id].depth());
+        for (msg_id, msg) in msgs {
+            tangle.add(&msg_id, &msg);
+        }
+        if !msg_ids.is_empty() {
+            self.indexes.tangles.insert(*root_msg_id, tangle);
+            self.indexes.tangle_msg_ids.insert(*root_msg_id, msg_ids);
+        }
+        Ok(())
+    }
+
+    /// Remove deleted msgs and erased data from the log.
+    pub fn compact(&mut self) -> Result<(), MsgStoreError> {
+        self.invalidate_checkpoint()?;
+        let moved = self.log.compact()?;
+        for offset in self.indexes.offsets.values_mut() {
+            if let Some(new_offset) = moved.get(offset) {
+                *offset = *new_offset;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MsgStore {
+    fn drop(&mut self) {
+        // a store which fails to checkpoint rebuilds its indexes on the next open
+        let _ = self.checkpoint();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng, SignKeypair};
+    use ppppp_msg::{MsgCreateOpts, MsgData};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn store_indexes_tangles() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+        let mut store = MsgStore::open(&path)?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = store.add(account)?;
+
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let moot = Msg::create_moot(
+            AccountId::Tangle(account_id),
+            domain.clone(),
+            keypair.clone(),
+        )?;
+        let moot_id = store.add(moot)?;
+
+        let mut msg_ids = Vec::new();
+        for i in 0..3 {
+            let tangle = store.feed(&account_id, &domain)?.unwrap();
+            let msg = Msg::create(
+                MsgCreateOpts::builder()
+                    .data(MsgData::try_from(
+                        json!({ "text": format!("hello {}", i) }),
+                    )?)
+                    .domain(domain.clone())
+                    .sign_keypair(keypair.clone())
+                    .account_id(AccountId::Tangle(account_id))
+                    .account_tips(Some(vec![account_id]))
+                    .tangles(HashMap::from([(moot_id, tangle)]))
+                    .build(),
+            )?;
+            msg_ids.push(store.add(msg)?);
+        }
+
+        let tangle = store.tangle(&moot_id)?.unwrap();
+        assert_eq!(tangle.size(), 4);
+        assert_eq!(tangle.get_max_depth(), 3);
+        assert_eq!(tangle.get_tips(), HashSet::from([msg_ids[2]]));
+        assert_eq!(store.get(&msg_ids[1])?.unwrap().id()?, msg_ids[1]);
+
+        drop(store);
+        assert!(path.with_extension("index").exists());
+        let mut store = MsgStore::open(&path)?;
+        let reopened = store.feed(&account_id, &domain)?.unwrap();
+        assert_eq!(reopened.topo_sort(), tangle.topo_sort());
+        assert_eq!(reopened.get_tips(), tangle.get_tips());
+
+        store.del(&msg_ids[2])?;
+        assert!(!store.has(&msg_ids[2]));
+        assert!(!path.with_extension("index").exists());
+        let tangle = store.tangle(&moot_id)?.unwrap();
+        assert_eq!(tangle.size(), 3);
+        assert_eq!(tangle.get_tips(), HashSet::from([msg_ids[1]]));
+
+        store.erase(&msg_ids[1])?;
+        store.compact()?;
+        assert!(store.get(&msg_ids[1])?.unwrap().data().is_null());
+        assert_eq!(store.get(&msg_ids[0])?.unwrap().id()?, msg_ids[0]);
+
+        // a compacted log is indexed in full, after a checkpoint which is caught up on open
+        drop(store);
+        let mut store = MsgStore::open(&path)?;
+        assert_eq!(store.tangle(&moot_id)?.unwrap().size(), 3);
+        let tangle = store.feed(&account_id, &domain)?.unwrap();
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(json!({ "text": "after checkpoint" }))?)
+                .domain(domain.clone())
+                .sign_keypair(keypair.clone())
+                .account_id(AccountId::Tangle(account_id))
+                .account_tips(Some(vec![account_id]))
+                .tangles(HashMap::from([(moot_id, tangle)]))
+                .build(),
+        )?;
+        let msg_id = store.add(msg)?;
+        // not dropped, so the checkpoint is behind the log
+        std::mem::forget(store);
+        let mut store = MsgStore::open(&path)?;
+        assert!(store.has(&msg_id));
+        assert_eq!(
+            store.tangle(&moot_id)?.unwrap().get_tips(),
+            HashSet::from([msg_id])
+        );
+        Ok(())
+    }
+}
@@ -1,40 +1,107 @@
 // https://github.com/staltz/ppppp-db/blob/master/protospec.md#account-tangle-msgs
 
 use monostate::MustBe;
-use ppppp_crypto::{Nonce, Signature, VerifyingKey};
+use ppppp_bytes::FromBytes;
+use ppppp_crypto::{BoxingKey, Nonce, Signature, SignatureError, SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use serde_json::Error as JsonError;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
-use crate::MsgId;
+use crate::{tangle::TangleMissingRootMessageError, Msg, MsgId, Tangle, TangleType};
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
-#[serde(untagged)]
+/// The account of a msg: an account tangle id, "self" for msgs in an account tangle, or "any"
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AccountId {
     Tangle(MsgId),
-    #[serde(rename = "self")]
     SelfIdentity,
-    #[serde(rename = "any")]
     Any,
 }
 
+impl AccountId {
+    const SELF_IDENTITY: &'static str = "self";
+    const ANY: &'static str = "any";
+}
+
+impl Serialize for AccountId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            AccountId::Tangle(msg_id) => msg_id.serialize(serializer),
+            AccountId::SelfIdentity => serializer.serialize_str(Self::SELF_IDENTITY),
+            AccountId::Any => serializer.serialize_str(Self::ANY),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AccountId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct AccountIdVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for AccountIdVisitor {
+            type Value = AccountId;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("\"self\", \"any\", or an account msg id")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                match value {
+                    AccountId::SELF_IDENTITY => Ok(AccountId::SelfIdentity),
+                    AccountId::ANY => Ok(AccountId::Any),
+                    _ => MsgId::from_base58(value)
+                        .map(AccountId::Tangle)
+                        .map_err(E::custom),
+                }
+            }
+
+            fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                let bytes = value
+                    .try_into()
+                    .map_err(|_| E::invalid_length(value.len(), &"16 bytes"))?;
+                MsgId::from_bytes(bytes)
+                    .map(AccountId::Tangle)
+                    .map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(AccountIdVisitor)
+    }
+}
+
 impl Display for AccountId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            AccountId::Tangle(msg_id) => write!(f, "AccountId::Tangle({}", msg_id),
+            AccountId::Tangle(msg_id) => write!(f, "AccountId::Tangle({})", msg_id),
             AccountId::SelfIdentity => write!(f, "AccountId::SelfIdentity"),
             AccountId::Any => write!(f, "AccountId::Any"),
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(tag = "action", rename = "kebab-case")]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
 pub enum AccountMsgData {
     Add {
         key: AccountKey,
         // nonce required only on the account tangle's root
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         nonce: Option<Nonce>,
         // required only on non-root msgs
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         consent: Option<AccountConsent>,
         // list of powers granted to this key, defaults to []
         #[serde(rename = "accountPowers", default)]
@@ -46,15 +113,34 @@ pub enum AccountMsgData {
 }
 
 /// base58 encoded signature of the string `:account-add:<ID>` where `<ID>` is the account's ID
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct AccountConsent(Signature);
 
+impl AccountConsent {
+    /// Consent, as the key being added, to be added to the account
+    pub fn create(account_id: &MsgId, signing_key: &SigningKey) -> Self {
+        AccountConsent(signing_key.sign(&Self::to_signable(account_id)))
+    }
+
+    pub fn verify(
+        &self,
+        account_id: &MsgId,
+        verifying_key: &VerifyingKey,
+    ) -> Result<(), SignatureError> {
+        verifying_key.verify(&Self::to_signable(account_id), &self.0)
+    }
+
+    fn to_signable(account_id: &MsgId) -> Vec<u8> {
+        format!(":account-add:{}", account_id).into_bytes()
+    }
+}
+
 /// "add" means this shs peer can validly add more keys to the account tangle
 /// "del" means this shs peer can validly revoke keys from the account tangle
 /// "internal-encryption" means this shs peer should get access to symmetric key
 /// "external-encryption" means this shs peer should get access to asymmetric key
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(rename = "kebab-case")]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
 pub enum AccountPower {
     Add,
     Del,
@@ -62,8 +148,8 @@ pub enum AccountPower {
     ExternalEncryption,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(tag = "purpose", rename = "kebab-case")]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(tag = "purpose", rename_all = "kebab-case")]
 pub enum AccountKey {
     // secret-handshake and digital signatures
     ShsAndExternalSignature {
@@ -73,7 +159,7 @@ pub enum AccountKey {
     // asymmetric encryption
     ExternalEncryption {
         algorithm: MustBe!("x25519-xsalsa20-poly1305"),
-        // TODO bytes: BoxingKey
+        bytes: BoxingKey,
     },
     // digital signature of internal messages
     InternalSignature {
@@ -81,3 +167,523 @@ pub enum AccountKey {
         bytes: VerifyingKey,
     },
 }
+
+impl AccountKey {
+    /// The key to verify signatures with, if this is a signature key
+    pub fn verifying_key(&self) -> Option<&VerifyingKey> {
+        match self {
+            AccountKey::ShsAndExternalSignature { bytes, .. } => Some(bytes),
+            AccountKey::InternalSignature { bytes, .. } => Some(bytes),
+            AccountKey::ExternalEncryption { .. } => None,
+        }
+    }
+
+    /// The key to encrypt to, if this is an encryption key
+    pub fn boxing_key(&self) -> Option<&BoxingKey> {
+        match self {
+            AccountKey::ExternalEncryption { bytes, .. } => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccountError {
+    #[error("tangle missing root message: {root_msg_id}")]
+    TangleMissingRootMessage { root_msg_id: MsgId },
+    #[error("tangle {root_msg_id} is not an account tangle")]
+    NotAnAccountTangle { root_msg_id: MsgId },
+    #[error("account msg {msg_id} is missing")]
+    MissingMsg { msg_id: MsgId },
+    #[error("invalid account msg data in {msg_id}: {source}")]
+    Data {
+        msg_id: MsgId,
+        #[source]
+        source: JsonError,
+    },
+    #[error("account root {msg_id} must add the key which signed it")]
+    RootMustAddSigningKey { msg_id: MsgId },
+    #[error(
+        "account msg {msg_id} signed by {verifying_key}, which does not have the {power:?} power"
+    )]
+    MissingPower {
+        msg_id: MsgId,
+        verifying_key: Box<VerifyingKey>,
+        power: AccountPower,
+    },
+}
+
+/// The state of an account: which keys are active, and which powers each key has.
+///
+/// Built by folding the account tangle's `add` and `del` msgs in topological order.
+#[derive(Clone, Debug)]
+pub struct Account {
+    id: MsgId,
+    keys: HashMap<AccountKey, HashSet<AccountPower>>,
+}
+
+impl Account {
+    pub fn from_tangle<GetMsg>(tangle: &Tangle, mut get_msg: GetMsg) -> Result<Self, AccountError>
+    where
+        GetMsg: FnMut(&MsgId) -> Option<Msg>,
+    {
+        let root_msg_id = *tangle.get_id();
+        let tangle_type =
+            tangle
+                .get_type()
+                .map_err(|TangleMissingRootMessageError { root_msg_id }| {
+                    AccountError::TangleMissingRootMessage { root_msg_id }
+                })?;
+        if tangle_type != TangleType::Account {
+            return Err(AccountError::NotAnAccountTangle { root_msg_id });
+        }
+
+        let mut account = Self {
+            id: root_msg_id,
+            keys: HashMap::new(),
+        };
+        for msg_id in tangle.topo_sort() {
+            let msg = if msg_id == root_msg_id {
+                tangle.get_root().unwrap()
+            } else {
+                get_msg(&msg_id).ok_or(AccountError::MissingMsg { msg_id })?
+            };
+            account.apply(&msg_id, &msg)?;
+        }
+        Ok(account)
+    }
+
+    /// Apply the next account msg, in topological order.
+    pub fn apply(&mut self, msg_id: &MsgId, msg: &Msg) -> Result<(), AccountError> {
+        let data: AccountMsgData =
+            serde_json::from_value(msg.data().to_value()).map_err(|source| AccountError::Data {
+                msg_id: *msg_id,
+                source,
+            })?;
+        let verifying_key = msg.verifying_key();
+
+        if msg_id == &self.id {
+            let AccountMsgData::Add {
+                key,
+                account_powers,
+                ..
+            } = data
+            else {
+                return Err(AccountError::RootMustAddSigningKey { msg_id: *msg_id });
+            };
+            if key.verifying_key() != Some(verifying_key) {
+                return Err(AccountError::RootMustAddSigningKey { msg_id: *msg_id });
+            }
+            self.keys.insert(key, account_powers.into_iter().collect());
+            return Ok(());
+        }
+
+        match data {
+            AccountMsgData::Add {
+                key,
+                account_powers,
+                ..
+            } => {
+                self.check_power(msg_id, verifying_key, AccountPower::Add)?;
+                self.keys.entry(key).or_default().extend(account_powers);
+            }
+            AccountMsgData::Del { key } => {
+                self.check_power(msg_id, verifying_key, AccountPower::Del)?;
+                self.keys.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_power(
+        &self,
+        msg_id: &MsgId,
+        verifying_key: &VerifyingKey,
+        power: AccountPower,
+    ) -> Result<(), AccountError> {
+        if self.has_power(verifying_key, power) {
+            Ok(())
+        } else {
+            Err(AccountError::MissingPower {
+                msg_id: *msg_id,
+                verifying_key: Box::new(verifying_key.clone()),
+                power,
+            })
+        }
+    }
+
+    pub fn id(&self) -> &MsgId {
+        &self.id
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &AccountKey> {
+        self.keys.keys()
+    }
+
+    pub fn has_key(&self, key: &AccountKey) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    pub fn get_powers(&self, key: &AccountKey) -> Option<&HashSet<AccountPower>> {
+        self.keys.get(key)
+    }
+
+    /// Whether any active signature key with these bytes has the power
+    pub fn has_power(&self, verifying_key: &VerifyingKey, power: AccountPower) -> bool {
+        self.keys.iter().any(|(key, powers)| {
+            key.verifying_key() == Some(verifying_key) && powers.contains(&power)
+        })
+    }
+
+    /// The active signature keys, to validate msgs published by this account
+    pub fn verifying_keys(&self) -> Vec<VerifyingKey> {
+        self.keys
+            .keys()
+            .filter_map(|key| key.verifying_key())
+            .cloned()
+            .collect()
+    }
+
+    /// The active encryption keys, to encrypt msgs to this account
+    pub fn boxing_keys(&self) -> Vec<BoxingKey> {
+        self.keys
+            .keys()
+            .filter_map(|key| key.boxing_key())
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{OsRng, SignKeypair};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+    use crate::{
+        validate, MsgAccountAddError, MsgAccountAddOpts, MsgCreateOpts, MsgData, MsgDomain,
+        ValidateError,
+    };
+
+    fn signature_key(keypair: &SignKeypair) -> AccountKey {
+        AccountKey::ShsAndExternalSignature {
+            algorithm: MustBe!("ed25519"),
+            bytes: keypair.verifying_key().clone(),
+        }
+    }
+
+    fn create_account_msg(
+        data: AccountMsgData,
+        sign_keypair: &SignKeypair,
+        tangle: &Tangle,
+    ) -> Result<(MsgId, Msg), Box<dyn Error>> {
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(serde_json::to_value(data)?)?)
+                .domain(tangle.get_root()?.metadata().domain().clone())
+                .sign_keypair(sign_keypair.clone())
+                .account_id(AccountId::SelfIdentity)
+                .tangles(HashMap::from([(*tangle.get_id(), tangle.clone())]))
+                .build(),
+        )?;
+        Ok((msg.id()?, msg))
+    }
+
+    #[test]
+    fn account_tracks_keys_and_powers() -> Result<(), Box<dyn Error>> {
+        let root_keypair = SignKeypair::generate(&mut OsRng);
+        let device_keypair = SignKeypair::generate(&mut OsRng);
+        let domain: MsgDomain = "account".to_string().try_into()?;
+
+        let root = Msg::create_account(root_keypair.clone(), domain, None::<fn() -> Nonce>)?;
+        let root_id = root.id()?;
+        let mut tangle = Tangle::new(root_id);
+        tangle.add(&root_id, &root);
+        let mut msgs = HashMap::new();
+
+        let (add_id, add) = create_account_msg(
+            AccountMsgData::Add {
+                key: signature_key(&device_keypair),
+                nonce: None,
+                consent: None,
+                account_powers: vec![AccountPower::Add],
+            },
+            &root_keypair,
+            &tangle,
+        )?;
+        tangle.add(&add_id, &add);
+        msgs.insert(add_id, add);
+
+        let account = Account::from_tangle(&tangle, |msg_id| msgs.get(msg_id).cloned())?;
+        assert_eq!(account.id(), &root_id);
+        assert_eq!(account.verifying_keys().len(), 2);
+        assert!(account.has_power(device_keypair.verifying_key(), AccountPower::Add));
+        assert!(!account.has_power(device_keypair.verifying_key(), AccountPower::Del));
+        assert!(account.has_power(root_keypair.verifying_key(), AccountPower::Del));
+
+        // the device key cannot revoke the root key without the del power
+        let (del_id, del) = create_account_msg(
+            AccountMsgData::Del {
+                key: signature_key(&root_keypair),
+            },
+            &device_keypair,
+            &tangle,
+        )?;
+        let mut invalid_tangle = tangle.clone();
+        invalid_tangle.add(&del_id, &del);
+        let mut invalid_msgs = msgs.clone();
+        invalid_msgs.insert(del_id, del);
+        assert!(matches!(
+            Account::from_tangle(&invalid_tangle, |msg_id| invalid_msgs.get(msg_id).cloned()),
+            Err(AccountError::MissingPower {
+                power: AccountPower::Del,
+                ..
+            })
+        ));
+
+        // but the root key can revoke the device key
+        let (del_id, del) = create_account_msg(
+            AccountMsgData::Del {
+                key: signature_key(&device_keypair),
+            },
+            &root_keypair,
+            &tangle,
+        )?;
+        tangle.add(&del_id, &del);
+        msgs.insert(del_id, del);
+        let account = Account::from_tangle(&tangle, |msg_id| msgs.get(msg_id).cloned())?;
+        assert_eq!(
+            account.verifying_keys(),
+            vec![root_keypair.verifying_key().clone()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn account_add_consent() -> Result<(), Box<dyn Error>> {
+        let root_keypair = SignKeypair::generate(&mut OsRng);
+        let device_keypair = SignKeypair::generate(&mut OsRng);
+        let stranger_keypair = SignKeypair::generate(&mut OsRng);
+
+        let root = Msg::create_account(
+            root_keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let root_id = root.id()?;
+        let mut tangle = Tangle::new(root_id);
+        tangle.add(&root_id, &root);
+        let account = Account::from_tangle(&tangle, |_| None)?;
+
+        let add = Msg::create_account_add(
+            MsgAccountAddOpts::builder()
+                .account_tangle(tangle.clone())
+                .new_key(signature_key(&device_keypair))
+                .powers(vec![AccountPower::Add])
+                .consent_keypair(device_keypair.clone())
+                .sign_keypair(root_keypair.clone())
+                .build(),
+        )?;
+        validate(
+            &add,
+            &add.id()?,
+            &tangle,
+            &account.verifying_keys(),
+            &root_id,
+        )?;
+
+        // consent signed by a different key than the one being added
+        let (forged_id, forged) = create_account_msg(
+            AccountMsgData::Add {
+                key: signature_key(&device_keypair),
+                nonce: None,
+                consent: Some(AccountConsent::create(
+                    &root_id,
+                    stranger_keypair.signing_key(),
+                )),
+                account_powers: vec![AccountPower::Add],
+            },
+            &root_keypair,
+            &tangle,
+        )?;
+        assert!(matches!(
+            validate(
+                &forged,
+                &forged_id,
+                &tangle,
+                &account.verifying_keys(),
+                &root_id
+            ),
+            Err(ValidateError::AccountConsentDoesNotVerify(_))
+        ));
+
+        assert!(matches!(
+            Msg::create_account_add(
+                MsgAccountAddOpts::builder()
+                    .account_tangle(tangle.clone())
+                    .new_key(signature_key(&device_keypair))
+                    .consent_keypair(stranger_keypair.clone())
+                    .sign_keypair(root_keypair.clone())
+                    .build(),
+            ),
+            Err(MsgAccountAddError::ConsentKeyMismatch)
+        ));
+        assert!(matches!(
+            Msg::create_account_add(
+                MsgAccountAddOpts::builder()
+                    .account_tangle(tangle.clone())
+                    .new_key(signature_key(&device_keypair))
+                    .sign_keypair(root_keypair.clone())
+                    .build(),
+            ),
+            Err(MsgAccountAddError::MissingConsent)
+        ));
+
+        // a consent the device created elsewhere, which is checked against the key
+        let consent = AccountConsent::create(&root_id, device_keypair.signing_key());
+        let add = Msg::create_account_add(
+            MsgAccountAddOpts::builder()
+                .account_tangle(tangle.clone())
+                .new_key(signature_key(&device_keypair))
+                .powers(vec![AccountPower::Add])
+                .consent(consent)
+                .sign_keypair(root_keypair.clone())
+                .build(),
+        )?;
+        validate(
+            &add,
+            &add.id()?,
+            &tangle,
+            &account.verifying_keys(),
+            &root_id,
+        )?;
+        assert!(matches!(
+            Msg::create_account_add(
+                MsgAccountAddOpts::builder()
+                    .account_tangle(tangle.clone())
+                    .new_key(signature_key(&device_keypair))
+                    .consent(AccountConsent::create(
+                        &root_id,
+                        stranger_keypair.signing_key()
+                    ))
+                    .sign_keypair(root_keypair)
+                    .build(),
+            ),
+            Err(MsgAccountAddError::ConsentDoesNotVerify(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn account_keys_validate_feed_msgs() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let stranger_keypair = SignKeypair::generate(&mut OsRng);
+
+        let root = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let root_id = root.id()?;
+        let mut account_tangle = Tangle::new(root_id);
+        account_tangle.add(&root_id, &root);
+        let account = Account::from_tangle(&account_tangle, |_| None)?;
+
+        let account_id = AccountId::Tangle(root_id);
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let moot = Msg::create_moot(account_id.clone(), domain.clone(), keypair.clone())?;
+        let moot_id = moot.id()?;
+        let mut feed_tangle = Tangle::new(moot_id);
+        feed_tangle.add(&moot_id, &moot);
+
+        for (sign_keypair, is_valid) in [(&keypair, true), (&stranger_keypair, false)] {
+            let msg = Msg::create(
+                MsgCreateOpts::builder()
+                    .data(MsgData::try_from(json!({ "text": "hello world" }))?)
+                    .domain(domain.clone())
+                    .sign_keypair(sign_keypair.clone())
+                    .account_id(account_id.clone())
+                    .account_tips(Some(vec![root_id]))
+                    .tangles(HashMap::from([(moot_id, feed_tangle.clone())]))
+                    .build(),
+            )?;
+            let result = validate(
+                &msg,
+                &msg.id()?,
+                &feed_tangle,
+                &account.verifying_keys(),
+                &moot_id,
+            );
+            assert_eq!(result.is_ok(), is_valid, "{:?}", result);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn account_add_json_roundtrip() -> Result<(), Box<dyn Error>> {
+        let value = json!({
+            "action": "add",
+            "key": {
+                "purpose": "external-encryption",
+                "algorithm": "x25519-xsalsa20-poly1305",
+                "bytes": "25VA4nUv2JPLgkUbuwJTwYSWxpTnMFeMZt2H5RZ4Pbfn",
+            },
+            "consent": "4n2A5aMbAiXjTLJYUKMmBxtoR7i2zymsJp9aRNJALWgHh5oAUZYizqFBgjPzmJVVvvfWoBUs4Mx7YYMqqDfMJEpG",
+            "accountPowers": ["internal-encryption", "external-encryption"],
+        });
+        let data: AccountMsgData = serde_json::from_value(value.clone())?;
+        let AccountMsgData::Add {
+            key: AccountKey::ExternalEncryption { ref bytes, .. },
+            ref account_powers,
+            ..
+        } = data
+        else {
+            panic!("expected external encryption key add, got {:?}", data);
+        };
+        assert_eq!(
+            bytes.to_string(),
+            "25VA4nUv2JPLgkUbuwJTwYSWxpTnMFeMZt2H5RZ4Pbfn"
+        );
+        assert_eq!(
+            account_powers,
+            &vec![
+                AccountPower::InternalEncryption,
+                AccountPower::ExternalEncryption
+            ]
+        );
+        assert_eq!(serde_json::to_value(&data)?, value);
+        Ok(())
+    }
+
+    #[test]
+    fn account_del_json_roundtrip() -> Result<(), Box<dyn Error>> {
+        let value = json!({
+            "action": "del",
+            "key": {
+                "purpose": "shs-and-external-signature",
+                "algorithm": "ed25519",
+                "bytes": "4mjQ5aJu378cEu6TksRG3uXAiKFiwGjYQtWAjfVjDAJW",
+            },
+        });
+        let data: AccountMsgData = serde_json::from_value(value.clone())?;
+        assert!(matches!(
+            data,
+            AccountMsgData::Del {
+                key: AccountKey::ShsAndExternalSignature { .. }
+            }
+        ));
+        assert_eq!(serde_json::to_value(&data)?, value);
+
+        let wrong_algorithm: Result<AccountMsgData, _> = serde_json::from_value(json!({
+            "action": "del",
+            "key": {
+                "purpose": "external-encryption",
+                "algorithm": "ed25519",
+                "bytes": "4mjQ5aJu378cEu6TksRG3uXAiKFiwGjYQtWAjfVjDAJW",
+            },
+        }));
+        assert!(wrong_algorithm.is_err());
+        Ok(())
+    }
+}
@@ -7,11 +7,16 @@ mod validate;
 
 pub use ppppp_bytes::DeserializeBytesError;
 
-pub use crate::account::AccountId;
-pub use crate::domain::MsgDomain;
+pub use crate::account::{
+    Account, AccountConsent, AccountError, AccountId, AccountKey, AccountMsgData, AccountPower,
+};
+pub use crate::domain::{MsgDomain, MsgDomainDeserializeError};
 pub use crate::hash::{MsgDataHash, MsgMetadataHash};
-pub use crate::msg::{Msg, MsgData, MsgId, MsgMetadata, MsgSignature, MsgTangle, MsgTangles};
-pub use crate::tangle::{Tangle, TangleType};
+pub use crate::msg::{
+    Msg, MsgAccountAddError, MsgAccountAddOpts, MsgCborError, MsgCreateOpts, MsgData, MsgError,
+    MsgId, MsgMetadata, MsgSignature, MsgTangle, MsgTangles,
+};
+pub use crate::tangle::{Tangle, TangleMissingRootMessageError, TangleType};
 pub use crate::validate::{validate, ValidateError};
 
 pub struct MootDetails {
@@ -1,3 +1,4 @@
+use ciborium::{de::Error as CborDecodeError, ser::Error as CborEncodeError};
 use getter_methods::GetterMethods;
 use json_canon::to_writer as canon_json_to_writer;
 use monostate::MustBe;
@@ -13,7 +14,8 @@ use std::{
 use typed_builder::TypedBuilder;
 
 use crate::{
-    account::{AccountKey, AccountMsgData, AccountPower},
+    account::{AccountConsent, AccountKey, AccountMsgData, AccountPower},
+    tangle::TangleMissingRootMessageError,
     AccountId, MsgDataHash, MsgDomain, MsgMetadataHash, Tangle,
 };
 
@@ -23,6 +25,38 @@ pub enum MsgError {
     JsonCanon(#[source] JsonError),
     #[error("failed to verify signature: {0}")]
     Signature(#[source] SignatureError),
+    #[error("tangle missing root message: {root_msg_id}")]
+    TangleMissingRootMessage { root_msg_id: MsgId },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MsgAccountAddError {
+    #[error("msg error: {0}")]
+    Msg(#[from] MsgError),
+    #[error("adding a signature key needs its consent")]
+    MissingConsent,
+    #[error("consent keypair does not match the key being added")]
+    ConsentKeyMismatch,
+    #[error("consent does not verify against the key being added: {0}")]
+    ConsentDoesNotVerify(#[source] SignatureError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MsgCborError {
+    #[error("failed to encode cbor: {0}")]
+    Encode(#[source] CborEncodeError<std::io::Error>),
+    #[error("failed to decode cbor: {0}")]
+    Decode(#[source] CborDecodeError<std::io::Error>),
+}
+
+fn to_cbor<T: Serialize>(value: &T) -> Result<Vec<u8>, MsgCborError> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(value, &mut bytes).map_err(MsgCborError::Encode)?;
+    Ok(bytes)
+}
+
+fn from_cbor<T: for<'de> Deserialize<'de>>(bytes: &[u8]) -> Result<T, MsgCborError> {
+    ciborium::from_reader(bytes).map_err(MsgCborError::Decode)
 }
 
 pub type MsgId = MsgMetadataHash;
@@ -43,6 +77,26 @@ pub struct MsgCreateOpts {
     pub tangles: HashMap<MsgId, Tangle>,
 }
 
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct MsgAccountAddOpts {
+    /// The tangle of the account, whose root id is the account id
+    #[builder(setter(into))]
+    pub account_tangle: Tangle,
+    #[builder(setter(into))]
+    pub new_key: AccountKey,
+    #[builder(default, setter(into))]
+    pub powers: Vec<AccountPower>,
+    /// The keypair of the key being added, to consent if the key is a signature key
+    #[builder(default, setter(strip_option))]
+    pub consent_keypair: Option<SignKeypair>,
+    /// The consent of the key being added, if its keypair isn't here to create it
+    #[builder(default, setter(strip_option))]
+    pub consent: Option<AccountConsent>,
+    /// The keypair of an existing key with the "add" power
+    #[builder(setter(into))]
+    pub sign_keypair: SignKeypair,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, GetterMethods)]
 #[serde(deny_unknown_fields, rename = "camelCase")]
 pub struct Msg {
@@ -169,6 +223,68 @@ impl Msg {
         )
     }
 
+    /// Create a msg which adds a key to an account.
+    ///
+    /// A signature key must consent to be added, with either its keypair or a consent it created.
+    pub fn create_account_add(opts: MsgAccountAddOpts) -> Result<Msg, MsgAccountAddError> {
+        let MsgAccountAddOpts {
+            account_tangle,
+            new_key,
+            powers,
+            consent_keypair,
+            consent,
+            sign_keypair,
+        } = opts;
+
+        let root_msg = account_tangle.get_root().map_err(
+            |TangleMissingRootMessageError { root_msg_id }| MsgError::TangleMissingRootMessage {
+                root_msg_id,
+            },
+        )?;
+        let domain = root_msg.metadata().domain().clone();
+        let account_id = *account_tangle.get_id();
+
+        let consent = match (new_key.verifying_key(), consent, consent_keypair) {
+            (None, _, _) => None,
+            (Some(verifying_key), Some(consent), _) => {
+                consent
+                    .verify(&account_id, verifying_key)
+                    .map_err(MsgAccountAddError::ConsentDoesNotVerify)?;
+                Some(consent)
+            }
+            (Some(verifying_key), None, Some(consent_keypair)) => {
+                if verifying_key != consent_keypair.verifying_key() {
+                    return Err(MsgAccountAddError::ConsentKeyMismatch);
+                }
+                Some(AccountConsent::create(
+                    &account_id,
+                    consent_keypair.signing_key(),
+                ))
+            }
+            (Some(_), None, None) => return Err(MsgAccountAddError::MissingConsent),
+        };
+
+        let data: Value = to_value(AccountMsgData::Add {
+            key: new_key,
+            nonce: None,
+            consent,
+            account_powers: powers,
+        })
+        .unwrap();
+        let data = MsgData(data);
+
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(data)
+                .account_id(AccountId::SelfIdentity)
+                .sign_keypair(sign_keypair)
+                .domain(domain)
+                .tangles(HashMap::from([(account_id, account_tangle)]))
+                .build(),
+        )?;
+        Ok(msg)
+    }
+
     pub fn id(&self) -> Result<MsgId, MsgError> {
         let hash = self.metadata.to_hash()?;
         Ok(hash)
@@ -193,10 +309,22 @@ impl Msg {
         let metadata = self.metadata();
         !(metadata.data_hash().is_some()
             || metadata.data_size() != 0
-            || (account_id.is_some() && metadata.account_id() == &account_id.unwrap())
+            || account_id.is_some_and(|account_id| metadata.account_id() != &account_id)
             || metadata.account_tips().is_some()
             || !metadata.tangles().is_empty()
-            || (find_domain.is_some() && metadata.domain() == &find_domain.unwrap()))
+            || find_domain.is_some_and(|find_domain| metadata.domain() != &find_domain))
+    }
+
+    /// Encode as CBOR, a compact binary alternative to JSON.
+    ///
+    /// Ids, keys, and signatures are encoded as raw bytes instead of base58. The signature is still
+    /// over the canonical JSON of the metadata, so converting between CBOR and JSON is lossless.
+    pub fn to_cbor(&self) -> Result<Vec<u8>, MsgCborError> {
+        to_cbor(self)
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, MsgCborError> {
+        from_cbor(bytes)
     }
 
     pub fn verify_signature(&self) -> Result<(), MsgError> {
@@ -246,6 +374,10 @@ impl MsgData {
     pub fn as_object(&self) -> Option<&Map<String, Value>> {
         self.0.as_object()
     }
+
+    pub fn to_value(&self) -> Value {
+        self.0.clone()
+    }
 }
 
 #[derive(Copy, Clone, Debug, thiserror::Error)]
@@ -274,10 +406,25 @@ pub struct MsgMetadata {
     data_size: u64,
     domain: MsgDomain,
     tangles: MsgTangles,
-    #[serde(rename = "v")]
+    #[serde(rename = "v", deserialize_with = "deserialize_version")]
     version: MustBe!(3_u8),
 }
 
+/// `MustBe!(3_u8)` only visits a `u8`, but JSON and CBOR visit every unsigned integer as a `u64`.
+fn deserialize_version<'de, D>(deserializer: D) -> Result<MustBe!(3_u8), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let version = u64::deserialize(deserializer)?;
+    if version != 3 {
+        return Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Unsigned(version),
+            &"msg version 3",
+        ));
+    }
+    Ok(Default::default())
+}
+
 impl MsgMetadata {
     pub fn to_hash(&self) -> Result<MsgMetadataHash, MsgError> {
         let mut hasher = Hasher::new();
@@ -312,6 +459,15 @@ impl MsgMetadata {
         Ok([TAG, json.as_slice()].concat())
     }
 
+    /// Encode as CBOR, see [`Msg::to_cbor`].
+    pub fn to_cbor(&self) -> Result<Vec<u8>, MsgCborError> {
+        to_cbor(self)
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, MsgCborError> {
+        from_cbor(bytes)
+    }
+
     pub fn get_moot(account_id: AccountId, domain: MsgDomain) -> Self {
         Self {
             account_id,
@@ -328,13 +484,24 @@ impl MsgMetadata {
 #[derive(Clone, Debug, Deserialize, Serialize, GetterMethods)]
 #[serde(deny_unknown_fields, rename = "camelCase")]
 pub struct MsgTangle {
-    #[serde(rename = "prev")]
+    #[serde(rename = "prev", serialize_with = "serialize_sorted")]
     prev_msg_ids: HashSet<MsgId>,
     depth: u64,
 }
 
 pub type MsgTangles = HashMap<MsgId, MsgTangle>;
 
+// canonical json only sorts object keys, so sets must be sorted to have a stable hash. sort the
+// base58 strings, as ppppp-db does, not the bytes, so msg ids are the same.
+fn serialize_sorted<S>(msg_ids: &HashSet<MsgId>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let mut msg_ids: Vec<&MsgId> = msg_ids.iter().collect();
+    msg_ids.sort_by_cached_key(|msg_id| msg_id.to_string());
+    msg_ids.serialize(serializer)
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MsgSignature(Signature);
 
@@ -362,4 +529,89 @@ mod tests {
         assert_eq!(hash.to_string(), "Cz1jtXr2oBrhk8czWiz6kH");
         assert_eq!(size, 23);
     }
+
+    #[test]
+    fn cbor_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let keypair = SignKeypair::generate(&mut ppppp_crypto::OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(
+                    json!({ "text": "hello", "count": 3, "ratio": 0.5, "tags": ["a", null] }),
+                )?)
+                .domain(MsgDomain::try_from("post".to_string())?)
+                .sign_keypair(keypair)
+                .account_id(AccountId::Tangle(account_id))
+                .account_tips(Some(vec![account_id]))
+                .build(),
+        )?;
+
+        for msg in [account, msg] {
+            let cbor = msg.to_cbor()?;
+            let json = serde_json::to_vec(&msg)?;
+            assert!(cbor.len() < json.len());
+
+            let decoded = Msg::from_cbor(&cbor)?;
+            assert_eq!(decoded.id()?, msg.id()?);
+            assert_eq!(serde_json::to_vec(&decoded)?, json);
+            decoded.verify_signature()?;
+
+            let metadata = MsgMetadata::from_cbor(&msg.metadata().to_cbor()?)?;
+            assert_eq!(metadata.to_hash()?, msg.id()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn prev_sorted_as_base58() -> Result<(), Box<dyn std::error::Error>> {
+        // 07ff.. is before ff.. as bytes, but after it as base58
+        let metadata: MsgMetadata = serde_json::from_value(json!({
+            "account": "any",
+            "account_tips": null,
+            "data_hash": null,
+            "data_size": 0,
+            "domain": "post",
+            "tangles": {
+                "8C2kCzsB2fJy9MiZos1mS": {
+                    "depth": 1,
+                    "prev": ["zJD6NJ3sv7DgayLUdJvEJ", "YcVfxkQb6JRzqk5kF2tNLv"],
+                },
+            },
+            "v": 3,
+        }))?;
+        let value = serde_json::to_value(&metadata)?;
+        assert_eq!(
+            value["tangles"]["8C2kCzsB2fJy9MiZos1mS"]["prev"],
+            json!(["YcVfxkQb6JRzqk5kF2tNLv", "zJD6NJ3sv7DgayLUdJvEJ"])
+        );
+        // pinned from this implementation, not checked against ppppp-db
+        assert_eq!(metadata.to_hash()?.to_string(), "3KvEjSpLdeo99sSHq2vdr2");
+        Ok(())
+    }
+
+    #[test]
+    fn is_moot() -> Result<(), Box<dyn std::error::Error>> {
+        let keypair = SignKeypair::generate(&mut ppppp_crypto::OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = AccountId::Tangle(account.id()?);
+        let domain = MsgDomain::try_from("post".to_string())?;
+        let other_domain = MsgDomain::try_from("follows".to_string())?;
+        let moot = Msg::create_moot(account_id.clone(), domain.clone(), keypair.clone())?;
+
+        assert!(moot.is_moot(None, None));
+        assert!(moot.is_moot(Some(account_id.clone()), Some(domain.clone())));
+        assert!(!moot.is_moot(Some(AccountId::Any), None));
+        assert!(!moot.is_moot(None, Some(other_domain)));
+        assert!(!account.is_moot(None, None));
+        Ok(())
+    }
 }
@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use lipmaa_link::lipmaa;
+use serde::{Deserialize, Serialize};
 
 use crate::{AccountId, MootDetails, Msg, MsgId};
 
@@ -17,7 +18,7 @@ pub enum TangleType {
     Weave,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Tangle {
     root_msg_id: MsgId,
     root_msg: Option<Msg>,
@@ -43,7 +44,10 @@ impl Tangle {
 
     pub fn add(&mut self, msg_hash: &MsgId, msg: &Msg) {
         if msg_hash == &self.root_msg_id && self.root_msg.is_none() {
-            self.tips.insert(*msg_hash);
+            // the root is only a tip if none of its descendants were added before it
+            if self.tips.is_empty() {
+                self.tips.insert(*msg_hash);
+            }
             self.per_depth.insert(0, HashSet::from([*msg_hash]));
             self.depth.insert(*msg_hash, 0);
             self.root_msg = Some(msg.clone());
@@ -87,8 +91,10 @@ impl Tangle {
         }
         let mut sorted = Vec::new();
         for i in 0..=self.max_depth {
-            let at_depth = self.get_all_at_depth(i);
-            sorted.extend(at_depth.iter().cloned());
+            // sort concurrent msgs by id, so the order is the same for everyone
+            let mut at_depth: Vec<MsgId> = self.get_all_at_depth(i).into_iter().collect();
+            at_depth.sort();
+            sorted.extend(at_depth);
         }
         sorted
     }
@@ -217,6 +223,61 @@ impl Tangle {
         false
     }
 
+    /// Replace the roots of a part of a CRDT in this tangle, like an item of a set or a field of
+    /// a dict, with a msg which changes it.
+    ///
+    /// Roots which the msg supersedes or comes after are no longer roots. Concurrent msgs are
+    /// both roots, until a later msg supersedes them. Returns whether the msg is the last of the
+    /// roots in topological order.
+    pub fn replace_roots(
+        &self,
+        roots: &mut HashSet<MsgId>,
+        msg_id: &MsgId,
+        supersedes: &[MsgId],
+    ) -> bool {
+        roots.retain(|root| !supersedes.contains(root) && !self.precedes(root, msg_id));
+        let order = (self.get_depth(msg_id), *msg_id);
+        let is_last = roots
+            .iter()
+            .all(|root| (self.get_depth(root), *root) < order);
+        roots.insert(*msg_id);
+        is_last
+    }
+
+    /// The roots of a CRDT in this tangle which no other root comes after, the earliest msgs
+    /// needed to know the CRDT.
+    pub fn min_roots<'a>(&self, roots: impl IntoIterator<Item = &'a MsgId>) -> Vec<MsgId> {
+        let roots: HashSet<MsgId> = roots.into_iter().copied().collect();
+        let mut min_roots = self.get_minimum_among(roots.into_iter().collect());
+        min_roots.sort();
+        min_roots
+    }
+
+    /// The depth of the earliest msg needed to know a CRDT in this tangle, from its roots
+    pub fn min_required_depth<'a>(&self, roots: impl IntoIterator<Item = &'a MsgId>) -> u64 {
+        self.min_roots(roots)
+            .iter()
+            .filter_map(|msg_id| self.get_depth(msg_id))
+            .min()
+            .unwrap_or(self.max_depth + 1)
+    }
+
+    /// The msgs which come before every root of a CRDT in this tangle.
+    ///
+    /// Those msgs are no longer needed to know the CRDT, so they can be pruned. The tangle root
+    /// is never prunable.
+    pub fn prunable<'a>(&self, roots: impl IntoIterator<Item = &'a MsgId>) -> Vec<MsgId> {
+        let min_depth = self.min_required_depth(roots);
+        self.topo_sort()
+            .into_iter()
+            .filter(|msg_id| msg_id != &self.root_msg_id)
+            .filter(|msg_id| {
+                self.get_depth(msg_id)
+                    .is_some_and(|depth| depth < min_depth)
+            })
+            .collect()
+    }
+
     pub fn size(&self) -> usize {
         self.depth.len()
     }
@@ -239,3 +300,50 @@ impl Tangle {
         str
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng, SignKeypair};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+    use crate::{MsgCreateOpts, MsgData};
+
+    #[test]
+    fn root_added_last_is_not_a_tip() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = AccountId::Tangle(account.id()?);
+        let moot = Msg::create_moot(
+            account_id.clone(),
+            "post".to_string().try_into()?,
+            keypair.clone(),
+        )?;
+        let moot_id = moot.id()?;
+        let mut tangle = Tangle::new(moot_id);
+        tangle.add(&moot_id, &moot);
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(json!({ "text": "hello" }))?)
+                .domain(moot.metadata().domain().clone())
+                .sign_keypair(keypair)
+                .account_id(account_id)
+                .tangles(HashMap::from([(moot_id, tangle)]))
+                .build(),
+        )?;
+        let msg_id = msg.id()?;
+
+        // as when indexing a log in which the msg comes before its root
+        let mut tangle = Tangle::new(moot_id);
+        tangle.add(&msg_id, &msg);
+        tangle.add(&moot_id, &moot);
+        assert_eq!(tangle.get_tips(), HashSet::from([msg_id]));
+        assert_eq!(tangle.topo_sort(), vec![moot_id, msg_id]);
+        Ok(())
+    }
+}
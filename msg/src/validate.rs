@@ -4,8 +4,8 @@ use ppppp_crypto::{SignatureError, VerifyingKey};
 use serde_json::Error as JsonError;
 
 use crate::{
-    msg::MsgError, tangle::TangleMissingRootMessageError, AccountId, MootDetails, Msg, MsgData,
-    MsgDomain, MsgId, Tangle, TangleType,
+    msg::MsgError, tangle::TangleMissingRootMessageError, AccountId, AccountMsgData, MootDetails,
+    Msg, MsgData, MsgDomain, MsgId, Tangle, TangleType,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -60,6 +60,12 @@ pub enum ValidateError {
     DataHashDoesNotMatchMetadata,
     #[error("data must be null, string, or object")]
     DataMustBeNullOrStringOrObject { msg_data: MsgData },
+    #[error("invalid account msg data: {0}")]
+    AccountMsgData(#[source] JsonError),
+    #[error("account add of a signature key must have consent")]
+    AccountAddMustHaveConsent,
+    #[error("account consent does not verify against the added key: {0}")]
+    AccountConsentDoesNotVerify(#[source] SignatureError),
 }
 
 pub fn validate(
@@ -88,6 +94,9 @@ pub fn validate(
         validate_tangle_root(msg, msg_id, tangle_root_msg_id)?;
     } else {
         validate_tangle(msg, tangle, tangle_root_msg_id)?;
+        if tangle_type == TangleType::Account {
+            validate_account_consent(msg, tangle_root_msg_id)?;
+        }
     }
 
     validate_signature(msg)?;
@@ -144,7 +153,7 @@ fn validate_verifying_key_and_account(
                 account_id: account_id.clone(),
             });
         }
-        if account_id != &AccountId::Any && verifying_keys.iter().any(|k| k == verifying_key) {
+        if account_id != &AccountId::Any && !verifying_keys.iter().any(|k| k == verifying_key) {
             return Err(ValidateError::VerifyingKeyMustBeFromAccount {
                 verifying_key: Box::new(verifying_key.clone()),
                 verifying_keys: Box::new(verifying_keys.to_vec()),
@@ -166,10 +175,28 @@ fn validate_verifying_key_and_account(
     Ok(())
 }
 
+fn validate_account_consent(msg: &Msg, account_id: &MsgId) -> Result<(), ValidateError> {
+    let data: AccountMsgData =
+        serde_json::from_value(msg.data().to_value()).map_err(ValidateError::AccountMsgData)?;
+    let AccountMsgData::Add { key, consent, .. } = data else {
+        return Ok(());
+    };
+    let Some(verifying_key) = key.verifying_key() else {
+        return Ok(());
+    };
+    let consent = consent.ok_or(ValidateError::AccountAddMustHaveConsent)?;
+    consent
+        .verify(account_id, verifying_key)
+        .map_err(ValidateError::AccountConsentDoesNotVerify)
+}
+
 pub fn validate_signature(msg: &Msg) -> Result<(), ValidateError> {
     msg.verify_signature().map_err(|err| match err {
         MsgError::JsonCanon(json_err) => ValidateError::JsonCanon(json_err),
         MsgError::Signature(sig_err) => ValidateError::Signature(sig_err),
+        MsgError::TangleMissingRootMessage { root_msg_id } => {
+            ValidateError::TangleMissingRootMessage { root_msg_id }
+        }
     })?;
 
     Ok(())
@@ -252,7 +279,7 @@ fn validate_tangle_root(
     msg_id: &MsgId,
     tangle_root_msg_id: &MsgId,
 ) -> Result<(), ValidateError> {
-    if msg_id == tangle_root_msg_id {
+    if msg_id != tangle_root_msg_id {
         Err(ValidateError::IfEmptyTangleThenMsgIdMustMatchTangleRootMsgId)
     } else if msg.metadata().tangles().contains_key(tangle_root_msg_id) {
         Err(ValidateError::TangleRootMustNotHaveSelfTangles)
@@ -260,3 +287,70 @@ fn validate_tangle_root(
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng, SignKeypair};
+    use serde_json::json;
+    use std::{collections::HashMap, error::Error};
+
+    use super::*;
+    use crate::MsgCreateOpts;
+
+    #[test]
+    fn verifying_key_must_be_from_account() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let stranger_keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = AccountId::Tangle(account.id()?);
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let moot = Msg::create_moot(account_id.clone(), domain.clone(), keypair.clone())?;
+        let moot_id = moot.id()?;
+        let mut tangle = Tangle::new(moot_id);
+        tangle.add(&moot_id, &moot);
+
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(json!({ "text": "hello" }))?)
+                .domain(domain)
+                .sign_keypair(stranger_keypair)
+                .account_id(account_id)
+                .tangles(HashMap::from([(moot_id, tangle.clone())]))
+                .build(),
+        )?;
+        let verifying_keys = [keypair.verifying_key().clone()];
+        assert!(matches!(
+            validate(&msg, &msg.id()?, &tangle, &verifying_keys, &moot_id),
+            Err(ValidateError::VerifyingKeyMustBeFromAccount { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn tangle_root_must_be_the_msg() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        let moot = Msg::create_moot(
+            AccountId::Tangle(account_id),
+            "post".to_string().try_into()?,
+            keypair,
+        )?;
+        let moot_id = moot.id()?;
+
+        validate_tangle_root(&moot, &moot_id, &moot_id)?;
+        assert!(matches!(
+            validate_tangle_root(&moot, &moot_id, &account_id),
+            Err(ValidateError::IfEmptyTangleThenMsgIdMustMatchTangleRootMsgId)
+        ));
+        Ok(())
+    }
+}
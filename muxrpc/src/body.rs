@@ -0,0 +1,100 @@
+use ppppp_packetstream::BodyType;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// The body of a response or stream item
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Body {
+    Binary(Vec<u8>),
+    Text(String),
+    Json(Value),
+}
+
+impl Body {
+    /// Deserialize a JSON or text body.
+    pub fn into_json<T: DeserializeOwned>(self) -> RpcResult<T> {
+        let value = match self {
+            Body::Json(value) => value,
+            Body::Text(text) => Value::String(text),
+            Body::Binary(_) => return Err(RpcError::new("expected json body, got binary")),
+        };
+        serde_json::from_value(value).map_err(|err| RpcError::new(err.to_string()))
+    }
+
+    pub(crate) fn encode(self) -> (BodyType, Vec<u8>) {
+        match self {
+            Body::Binary(bytes) => (BodyType::Binary, bytes),
+            Body::Text(text) => (BodyType::Utf8, text.into_bytes()),
+            Body::Json(value) => (BodyType::Json, value.to_string().into_bytes()),
+        }
+    }
+
+    pub(crate) fn decode(body_type: BodyType, body: Vec<u8>) -> RpcResult<Self> {
+        match body_type {
+            BodyType::Binary => Ok(Body::Binary(body)),
+            BodyType::Utf8 => String::from_utf8(body)
+                .map(Body::Text)
+                .map_err(|_| RpcError::new("invalid utf8 body")),
+            BodyType::Json => serde_json::from_slice(&body)
+                .map(Body::Json)
+                .map_err(|_| RpcError::new("invalid json body")),
+        }
+    }
+}
+
+impl From<Value> for Body {
+    fn from(value: Value) -> Self {
+        Body::Json(value)
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Body::Text(text)
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::Binary(bytes)
+    }
+}
+
+/// An error sent between peers, as a response or to end a stream
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[error("{name}: {message}")]
+pub struct RpcError {
+    #[serde(default = "default_error_name")]
+    pub name: String,
+    pub message: String,
+}
+
+fn default_error_name() -> String {
+    "Error".to_string()
+}
+
+pub type RpcResult<T> = Result<T, RpcError>;
+
+impl RpcError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            name: default_error_name(),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("error serializes to json")
+    }
+
+    /// Decode an error body, which from some peers is only text.
+    pub(crate) fn decode(body_type: BodyType, body: Vec<u8>) -> Self {
+        match Body::decode(body_type, body) {
+            Ok(Body::Json(value)) => serde_json::from_value(value.clone())
+                .unwrap_or_else(|_| Self::new(value.to_string())),
+            Ok(Body::Text(text)) => Self::new(text),
+            Ok(Body::Binary(_)) => Self::new("binary error"),
+            Err(err) => err,
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use futures::{
+    channel::{mpsc, oneshot},
+    stream::BoxStream,
+    StreamExt,
+};
+use serde_json::Value;
+
+use crate::{Body, CallType, Manifest, MuxRpcError, Request, RpcResult, RpcStream, STREAM_BUFFER};
+
+/// What a client asks of its connection
+pub(crate) enum Command {
+    Call {
+        request: Request,
+        response: oneshot::Sender<RpcResult<Body>>,
+    },
+    Stream {
+        request: Request,
+        input: mpsc::Sender<RpcResult<Body>>,
+        output: Option<BoxStream<'static, Body>>,
+    },
+    Close,
+}
+
+/// Makes calls to the remote peer of a connection.
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl RpcClient {
+    pub(crate) fn new(commands: mpsc::UnboundedSender<Command>) -> Self {
+        Self { commands }
+    }
+
+    fn send(&self, command: Command) -> Result<(), MuxRpcError> {
+        self.commands
+            .unbounded_send(command)
+            .map_err(|_| MuxRpcError::Closed)
+    }
+
+    fn request<S: AsRef<str>>(name: &[S], call_type: CallType, args: Vec<Value>) -> Request {
+        Request {
+            name: name.iter().map(|part| part.as_ref().to_string()).collect(),
+            call_type,
+            args,
+        }
+    }
+
+    /// Call an async or sync method.
+    pub async fn call<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        args: Vec<Value>,
+    ) -> Result<Body, MuxRpcError> {
+        let (response, receiver) = oneshot::channel();
+        self.send(Command::Call {
+            request: Self::request(name, CallType::Async, args),
+            response,
+        })?;
+        let body = receiver.await.map_err(|_| MuxRpcError::Closed)??;
+        Ok(body)
+    }
+
+    /// Get the remote's manifest.
+    pub async fn manifest(&self) -> Result<Manifest, MuxRpcError> {
+        let body = self.call(&["manifest"], Vec::new()).await?;
+        Ok(body.into_json()?)
+    }
+
+    fn stream<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        call_type: CallType,
+        args: Vec<Value>,
+        output: Option<BoxStream<'static, Body>>,
+    ) -> Result<RpcStream, MuxRpcError> {
+        let (input, receiver) = mpsc::channel(STREAM_BUFFER);
+        self.send(Command::Stream {
+            request: Self::request(name, call_type, args),
+            input,
+            output,
+        })?;
+        Ok(receiver.boxed())
+    }
+
+    /// Call a source method, returning the stream it sends.
+    pub fn source<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        args: Vec<Value>,
+    ) -> Result<RpcStream, MuxRpcError> {
+        self.stream(name, CallType::Source, args, None)
+    }
+
+    /// Call a sink method, returning a sender to stream to it, which ends the stream when closed.
+    ///
+    /// The returned stream has no items, but ends with an error if the sink fails.
+    pub fn sink<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        args: Vec<Value>,
+    ) -> Result<(mpsc::Sender<Body>, RpcStream), MuxRpcError> {
+        let (sender, output) = mpsc::channel(STREAM_BUFFER);
+        let input = self.stream(name, CallType::Sink, args, Some(output.boxed()))?;
+        Ok((sender, input))
+    }
+
+    /// Call a duplex method, returning a sender to stream to it and the stream it sends.
+    pub fn duplex<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        args: Vec<Value>,
+    ) -> Result<(mpsc::Sender<Body>, RpcStream), MuxRpcError> {
+        let (sender, output) = mpsc::channel(STREAM_BUFFER);
+        let input = self.stream(name, CallType::Duplex, args, Some(output.boxed()))?;
+        Ok((sender, input))
+    }
+
+    /// Say goodbye, which ends the connection once the remote says goodbye too.
+    pub fn close(&self) -> Result<(), MuxRpcError> {
+        self.send(Command::Close)
+    }
+}
@@ -0,0 +1,574 @@
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture},
+    io::{ReadHalf, WriteHalf},
+    stream::{self, AbortHandle, BoxStream, FuturesUnordered, SelectAll},
+    AsyncRead, AsyncReadExt, AsyncWrite, Future, FutureExt, Sink, Stream, StreamExt,
+};
+use ppppp_packetstream::{
+    BodyType, Mux, MuxEvent, Packet, PacketReader, PacketWriter, RequestId, StreamKind,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use crate::{
+    client::Command,
+    registry::{method_error, Handler},
+    Body, CallType, MuxRpcError, Registry, Request, RpcClient, RpcError, RpcResult, RpcStream,
+    STREAM_BUFFER,
+};
+
+/// What we send on our side of a stream
+enum Outgoing {
+    Data(Body),
+    End(Option<RpcError>),
+}
+
+/// An item the remote streamed to us, waiting for room in a full input
+struct Blocked {
+    id: RequestId,
+    item: RpcResult<Body>,
+    end: bool,
+}
+
+/// A muxrpc connection, which answers calls from the remote, and makes calls for our clients.
+///
+/// The connection does nothing unless polled, and is done once both peers have said goodbye.
+pub struct Connection<S> {
+    registry: Arc<Registry>,
+    mux: Mux,
+    reader: PacketReader<ReadHalf<S>>,
+    writer: PacketWriter<WriteHalf<S>>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    /// Packets waiting to be written, in order
+    queue: VecDeque<Packet>,
+    needs_flush: bool,
+    /// Whether we want to say goodbye, or already have
+    closing: bool,
+    closed: bool,
+    reader_done: bool,
+    /// Async handlers working on their responses
+    calls: FuturesUnordered<BoxFuture<'static, (RequestId, RpcResult<Body>)>>,
+    /// Our sides of streams, which are only polled when there is room to write
+    outgoing: SelectAll<BoxStream<'static, (RequestId, Outgoing)>>,
+    aborts: HashMap<RequestId, AbortHandle>,
+    /// Where to send responses to our calls
+    responses: HashMap<RequestId, oneshot::Sender<RpcResult<Body>>>,
+    /// Where to send what the remote streams to us
+    inputs: HashMap<RequestId, mpsc::Sender<RpcResult<Body>>>,
+    /// While an input is full, we stop reading
+    blocked: Option<Blocked>,
+}
+
+/// Start a muxrpc connection over a stream, answering calls with the methods in the registry.
+pub fn connect<S>(stream: S, registry: Arc<Registry>) -> (RpcClient, Connection<S>)
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let (reader, writer) = stream.split();
+    let (commands_sender, commands) = mpsc::unbounded();
+    let connection = Connection {
+        registry,
+        mux: Mux::new(),
+        reader: PacketReader::new(reader),
+        writer: PacketWriter::new(writer),
+        commands,
+        queue: VecDeque::new(),
+        needs_flush: false,
+        closing: false,
+        closed: false,
+        reader_done: false,
+        calls: FuturesUnordered::new(),
+        outgoing: SelectAll::new(),
+        aborts: HashMap::new(),
+        responses: HashMap::new(),
+        inputs: HashMap::new(),
+        blocked: None,
+    };
+    (RpcClient::new(commands_sender), connection)
+}
+
+/// Send items until the first error, then end with it.
+fn handler_output(output: RpcStream) -> impl Stream<Item = Outgoing> {
+    output
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .scan(false, |ended, item| {
+            if *ended {
+                return future::ready(None);
+            }
+            *ended = !matches!(item, Some(Ok(_)));
+            future::ready(Some(match item {
+                Some(Ok(body)) => Outgoing::Data(body),
+                Some(Err(err)) => Outgoing::End(Some(err)),
+                None => Outgoing::End(None),
+            }))
+        })
+}
+
+fn decode_request(body_type: BodyType, body: Vec<u8>) -> RpcResult<Request> {
+    Body::decode(body_type, body)?.into_json()
+}
+
+fn encode_request(request: &Request) -> Vec<u8> {
+    serde_json::to_vec(request).expect("request serializes to json")
+}
+
+/// Decode the body which ends a stream, which is `true` unless it is an error.
+fn decode_end(body_type: BodyType, body: Vec<u8>) -> Option<RpcError> {
+    if body_type == BodyType::Json && body == b"true" {
+        return None;
+    }
+    Some(RpcError::decode(body_type, body))
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    fn add_input(&mut self, id: RequestId) -> RpcStream {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        self.inputs.insert(id, sender);
+        receiver.boxed()
+    }
+
+    fn push_input(&mut self, id: RequestId, item: RpcResult<Body>, end: bool) {
+        let Some(input) = self.inputs.get_mut(&id) else {
+            return;
+        };
+        match input.try_send(item) {
+            Ok(()) if !end => {}
+            Err(err) if err.is_full() => {
+                self.blocked = Some(Blocked {
+                    id,
+                    item: err.into_inner(),
+                    end,
+                })
+            }
+            _ => {
+                self.inputs.remove(&id);
+            }
+        }
+    }
+
+    fn add_outgoing<St>(&mut self, id: RequestId, outgoing: St)
+    where
+        St: Stream<Item = Outgoing> + Send + 'static,
+    {
+        let (outgoing, abort) = stream::abortable(outgoing);
+        self.aborts.insert(id, abort);
+        self.outgoing
+            .push(outgoing.map(move |outgoing| (id, outgoing)).boxed());
+    }
+
+    fn send_outgoing(&mut self, id: RequestId, outgoing: Outgoing) {
+        // the stream may have been ended on both sides while this was waiting
+        let packet = match outgoing {
+            Outgoing::Data(body) => {
+                let (body_type, body) = body.encode();
+                self.mux.send(id, body_type, body)
+            }
+            Outgoing::End(error) => {
+                self.aborts.remove(&id);
+                self.mux.end(id, error.map(|err| err.encode()))
+            }
+        };
+        if let Ok(packet) = packet {
+            self.queue.push_back(packet);
+        }
+    }
+
+    fn respond(&mut self, id: RequestId, result: RpcResult<Body>) -> Result<(), MuxRpcError> {
+        let (body_type, body, is_error) = match result {
+            Ok(body) => {
+                let (body_type, body) = body.encode();
+                (body_type, body, false)
+            }
+            Err(err) => (BodyType::Json, err.encode(), true),
+        };
+        let packet = self.mux.respond(id, body_type, body, is_error)?;
+        self.queue.push_back(packet);
+        Ok(())
+    }
+
+    fn reject_stream(&mut self, id: RequestId, error: RpcError) -> Result<(), MuxRpcError> {
+        self.mux.accept(id, StreamKind::Duplex)?;
+        let packet = self.mux.end(id, Some(error.encode()))?;
+        self.queue.push_back(packet);
+        Ok(())
+    }
+
+    /// End our side of a stream the remote has ended, unless we have more to send on it.
+    fn end_with_remote(&mut self, id: RequestId) -> Result<(), MuxRpcError> {
+        let Some(kind) = self.mux.stream_kind(id) else {
+            return Ok(());
+        };
+        let sends_only = matches!(
+            (id, kind),
+            (RequestId::Local(_), StreamKind::Sink) | (RequestId::Remote(_), StreamKind::Source)
+        );
+        if let Some(abort) = self.aborts.get(&id) {
+            if !sends_only {
+                return Ok(());
+            }
+            abort.abort();
+            self.aborts.remove(&id);
+        }
+        let packet = self.mux.end(id, None)?;
+        self.queue.push_back(packet);
+        Ok(())
+    }
+
+    fn handle_request(
+        &mut self,
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+    ) -> Result<(), MuxRpcError> {
+        let request = match decode_request(body_type, body) {
+            Ok(request) => request,
+            Err(err) => return self.respond(id, Err(err)),
+        };
+        let registry = self.registry.clone();
+        let handler = registry.get(&request.name);
+        match handler {
+            Some(Handler::Async(handler)) if !request.call_type.is_stream() => {
+                let response = handler(request.args);
+                self.calls
+                    .push(response.map(move |result| (id, result)).boxed());
+                Ok(())
+            }
+            Some(Handler::Sync(handler)) if !request.call_type.is_stream() => {
+                self.respond(id, handler(request.args))
+            }
+            // every connection answers with its manifest, unless overridden
+            None if request.name == ["manifest"] => {
+                let manifest = serde_json::to_value(registry.manifest())?;
+                self.respond(id, Ok(Body::Json(manifest)))
+            }
+            _ => self.respond(
+                id,
+                Err(method_error(&request.name, request.call_type, handler)),
+            ),
+        }
+    }
+
+    fn handle_stream_request(
+        &mut self,
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+    ) -> Result<(), MuxRpcError> {
+        let request = match decode_request(body_type, body) {
+            Ok(request) => request,
+            Err(err) => return self.reject_stream(id, err),
+        };
+        let registry = self.registry.clone();
+        let handler = registry.get(&request.name);
+        match (handler, request.call_type) {
+            (Some(Handler::Source(handler)), CallType::Source) => {
+                self.mux.accept(id, StreamKind::Source)?;
+                self.add_outgoing(id, handler_output(handler(request.args)));
+            }
+            (Some(Handler::Sink(handler)), CallType::Sink) => {
+                self.mux.accept(id, StreamKind::Sink)?;
+                let input = self.add_input(id);
+                let done = handler(request.args, input);
+                self.add_outgoing(
+                    id,
+                    stream::once(done).map(|result| Outgoing::End(result.err())),
+                );
+            }
+            (Some(Handler::Duplex(handler)), CallType::Duplex) => {
+                self.mux.accept(id, StreamKind::Duplex)?;
+                let input = self.add_input(id);
+                self.add_outgoing(id, handler_output(handler(request.args, input)));
+            }
+            _ => self.reject_stream(id, method_error(&request.name, request.call_type, handler))?,
+        }
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, packet: Packet) -> Result<(), MuxRpcError> {
+        match self.mux.recv(packet)? {
+            MuxEvent::Request {
+                id,
+                body_type,
+                body,
+            } => self.handle_request(id, body_type, body)?,
+            MuxEvent::Response {
+                id,
+                body_type,
+                body,
+                is_error,
+            } => {
+                if let Some(response) = self.responses.remove(&id) {
+                    let result = match is_error {
+                        true => Err(RpcError::decode(body_type, body)),
+                        false => Body::decode(body_type, body),
+                    };
+                    let _ = response.send(result);
+                }
+            }
+            MuxEvent::StreamOpened {
+                id,
+                body_type,
+                body,
+            } => self.handle_stream_request(id, body_type, body)?,
+            MuxEvent::StreamData {
+                id,
+                body_type,
+                body,
+            } => self.push_input(id, Body::decode(body_type, body), false),
+            MuxEvent::StreamEnded {
+                id,
+                body_type,
+                body,
+            } => {
+                match decode_end(body_type, body) {
+                    Some(err) => self.push_input(id, Err(err), true),
+                    None => {
+                        self.inputs.remove(&id);
+                    }
+                }
+                self.end_with_remote(id)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Call { request, response } => {
+                let (id, packet) = self.mux.request(BodyType::Json, encode_request(&request));
+                self.queue.push_back(packet);
+                self.responses.insert(id, response);
+            }
+            Command::Stream {
+                request,
+                input,
+                output,
+            } => {
+                let kind = match request.call_type {
+                    CallType::Source => StreamKind::Source,
+                    CallType::Sink => StreamKind::Sink,
+                    _ => StreamKind::Duplex,
+                };
+                let (id, packet) = self
+                    .mux
+                    .open(kind, BodyType::Json, encode_request(&request));
+                self.queue.push_back(packet);
+                self.inputs.insert(id, input);
+                if let Some(output) = output {
+                    let end = stream::once(future::ready(Outgoing::End(None)));
+                    self.add_outgoing(id, output.map(Outgoing::Data).chain(end));
+                }
+            }
+            Command::Close => self.closing = true,
+        }
+    }
+}
+
+impl<S> Future for Connection<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    type Output = Result<(), MuxRpcError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let mut progress = false;
+
+            if this.closing {
+                this.queue.clear();
+                if !this.closed && Pin::new(&mut this.writer).poll_close(cx)?.is_ready() {
+                    this.closed = true;
+                }
+                if this.closed && this.reader_done {
+                    return Poll::Ready(Ok(()));
+                }
+            }
+
+            while !this.queue.is_empty() {
+                if Pin::new(&mut this.writer).poll_ready(cx)?.is_pending() {
+                    break;
+                }
+                let packet = this.queue.pop_front().unwrap();
+                Pin::new(&mut this.writer).start_send(packet)?;
+                this.needs_flush = true;
+                progress = true;
+            }
+            if this.needs_flush
+                && this.queue.is_empty()
+                && Pin::new(&mut this.writer).poll_flush(cx)?.is_ready()
+            {
+                this.needs_flush = false;
+            }
+
+            if !this.closing {
+                if let Poll::Ready(Some(command)) = this.commands.poll_next_unpin(cx) {
+                    this.handle_command(command);
+                    progress = true;
+                }
+                if let Poll::Ready(Some((id, result))) = this.calls.poll_next_unpin(cx) {
+                    this.respond(id, result)?;
+                    progress = true;
+                }
+                if this.queue.is_empty() {
+                    if let Poll::Ready(Some((id, outgoing))) = this.outgoing.poll_next_unpin(cx) {
+                        this.send_outgoing(id, outgoing);
+                        progress = true;
+                    }
+                }
+            }
+
+            if let Some(blocked) = this.blocked.take() {
+                if let Some(input) = this.inputs.get_mut(&blocked.id) {
+                    match input.poll_ready(cx) {
+                        Poll::Pending => this.blocked = Some(blocked),
+                        Poll::Ready(ready) => {
+                            if ready.is_err()
+                                || input.start_send(blocked.item).is_err()
+                                || blocked.end
+                            {
+                                this.inputs.remove(&blocked.id);
+                            }
+                            progress = true;
+                        }
+                    }
+                }
+            }
+
+            if this.blocked.is_none() && !this.reader_done {
+                match this.reader.poll_next_unpin(cx) {
+                    Poll::Ready(Some(packet)) => {
+                        this.handle_packet(packet?)?;
+                        progress = true;
+                    }
+                    Poll::Ready(None) => {
+                        // the remote said goodbye, so we say it back
+                        this.reader_done = true;
+                        this.closing = true;
+                        progress = true;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, future::join3, SinkExt};
+    use futures_ringbuf::Endpoint;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry
+            .add_sync(&["add"], |args| {
+                let sum: i64 = args.iter().filter_map(Value::as_i64).sum();
+                Ok(json!(sum).into())
+            })
+            .add_async(&["fail"], |_| async { Err(RpcError::new("nope")) })
+            .add_source(&["count"], |args| {
+                let n = args.first().and_then(Value::as_u64).unwrap_or(0);
+                stream::iter((0..n).map(|i| Ok(json!(i).into())))
+            })
+            .add_sink(&["expect", "three"], |_, input| async move {
+                match input.count().await {
+                    3 => Ok(()),
+                    n => Err(RpcError::new(format!("got {}", n))),
+                }
+            })
+            .add_duplex(&["echo"], |_, input| input);
+        registry
+    }
+
+    fn run<F, Fut>(test: F)
+    where
+        F: FnOnce(RpcClient) -> Fut,
+        Fut: Future<Output = Result<(), MuxRpcError>>,
+    {
+        // a small buffer, so streams have to wait for each other
+        let (client_stream, server_stream) = Endpoint::pair(256, 256);
+        let (client, client_connection) = connect(client_stream, Arc::new(Registry::new()));
+        let (_, server_connection) = connect(server_stream, Arc::new(registry()));
+        let (client_result, server_result, test_result) =
+            block_on(join3(client_connection, server_connection, async {
+                test(client.clone()).await?;
+                client.close()
+            }));
+        test_result.unwrap();
+        client_result.unwrap();
+        server_result.unwrap();
+    }
+
+    #[test]
+    fn async_calls() {
+        run(|client| async move {
+            let sum = client
+                .call(&["add"], vec![json!(1), json!(2), json!(3)])
+                .await?;
+            assert_eq!(sum, Body::Json(json!(6)));
+
+            let err = client.call(&["fail"], vec![]).await.unwrap_err();
+            assert!(matches!(err, MuxRpcError::Remote(err) if err.message == "nope"));
+            let err = client.call(&["count"], vec![]).await.unwrap_err();
+            assert!(matches!(err, MuxRpcError::Remote(_)));
+            let err = client.call(&["missing"], vec![]).await.unwrap_err();
+            assert!(matches!(err, MuxRpcError::Remote(_)));
+
+            assert_eq!(&client.manifest().await?, registry().manifest());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn streams() {
+        run(|client| async move {
+            let count: Vec<Value> = client
+                .source(&["count"], vec![json!(1000)])?
+                .map(|item| item.and_then(Body::into_json).unwrap())
+                .collect()
+                .await;
+            assert_eq!(count, (0..1000).map(|i| json!(i)).collect::<Vec<_>>());
+
+            let items: Vec<Body> = (0..3).map(|i| Body::Text(i.to_string())).collect();
+            let (mut sender, echoes) = client.duplex(&["echo"], vec![])?;
+            for item in &items {
+                sender.send(item.clone()).await.unwrap();
+            }
+            sender.close_channel();
+            let echoes: Vec<RpcResult<Body>> = echoes.collect().await;
+            assert_eq!(echoes, items.iter().cloned().map(Ok).collect::<Vec<_>>());
+
+            for (n, expected_errors) in [(3, 0), (2, 1)] {
+                let (mut sender, done) = client.sink(&["expect", "three"], vec![])?;
+                for item in items.iter().take(n) {
+                    sender.send(item.clone()).await.unwrap();
+                }
+                sender.close_channel();
+                let errors: Vec<RpcResult<Body>> = done.collect().await;
+                assert_eq!(errors.len(), expected_errors);
+            }
+
+            let mut missing = client.source(&["missing"], vec![])?;
+            assert!(matches!(missing.next().await, Some(Err(_))));
+            assert!(missing.next().await.is_none());
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,37 @@
+//! Muxrpc, remote procedure calls over a packet stream.
+//!
+//! - [Scuttlebutt Protocol Guide: RPC protocol](https://ssbc.github.io/scuttlebutt-protocol-guide/#rpc-protocol)
+//!
+//! A call is async (or sync) with one response, or a source, sink, or duplex stream. The methods
+//! a peer offers, and how each is called, are described by its manifest.
+
+use ppppp_packetstream::{MuxError, PacketStreamError};
+
+mod body;
+mod client;
+mod connection;
+mod manifest;
+mod registry;
+
+pub use crate::body::{Body, RpcError, RpcResult};
+pub use crate::client::RpcClient;
+pub use crate::connection::{connect, Connection};
+pub use crate::manifest::{CallType, Manifest, ManifestEntry, Request};
+pub use crate::registry::{Registry, RpcStream, Service};
+
+/// How many items a stream buffers before its sender has to wait
+pub(crate) const STREAM_BUFFER: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MuxRpcError {
+    #[error("packet stream error: {0}")]
+    PacketStream(#[from] PacketStreamError),
+    #[error("multiplexing error: {0}")]
+    Mux(#[from] MuxError),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("remote error: {0}")]
+    Remote(#[from] RpcError),
+    #[error("connection is closed")]
+    Closed,
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// How a method is called
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallType {
+    /// One request, one response, later
+    Async,
+    /// One request, one response, right away. Called the same as async.
+    Sync,
+    /// The callee streams to the caller
+    Source,
+    /// The caller streams to the callee
+    Sink,
+    /// Both stream to each other
+    Duplex,
+}
+
+impl CallType {
+    pub fn is_stream(self) -> bool {
+        matches!(self, CallType::Source | CallType::Sink | CallType::Duplex)
+    }
+}
+
+/// A method in a manifest, or a group of methods
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ManifestEntry {
+    Method(CallType),
+    Group(Manifest),
+}
+
+/// The methods a peer offers, by name, like `{ "whoami": "sync", "blobs": { "get": "source" } }`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Manifest(BTreeMap<String, ManifestEntry>);
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a method, creating any groups on the way to it.
+    pub fn insert<S: AsRef<str>>(&mut self, name: &[S], call_type: CallType) {
+        let Some((last, groups)) = name.split_last() else {
+            return;
+        };
+        let mut manifest = self;
+        for group in groups {
+            let entry = manifest
+                .0
+                .entry(group.as_ref().to_string())
+                .or_insert_with(|| ManifestEntry::Group(Manifest::new()));
+            if let ManifestEntry::Method(_) = entry {
+                *entry = ManifestEntry::Group(Manifest::new());
+            }
+            let ManifestEntry::Group(group) = entry else {
+                unreachable!()
+            };
+            manifest = group;
+        }
+        manifest
+            .0
+            .insert(last.as_ref().to_string(), ManifestEntry::Method(call_type));
+    }
+
+    /// Get how a method is called, if the manifest has it.
+    pub fn get<S: AsRef<str>>(&self, name: &[S]) -> Option<CallType> {
+        let (last, groups) = name.split_last()?;
+        let mut manifest = self;
+        for group in groups {
+            match manifest.0.get(group.as_ref())? {
+                ManifestEntry::Group(group) => manifest = group,
+                ManifestEntry::Method(_) => return None,
+            }
+        }
+        match manifest.0.get(last.as_ref())? {
+            ManifestEntry::Method(call_type) => Some(*call_type),
+            ManifestEntry::Group(_) => None,
+        }
+    }
+}
+
+/// The body of the packet which makes a call
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    pub name: Vec<String>,
+    #[serde(rename = "type")]
+    pub call_type: CallType,
+    #[serde(default)]
+    pub args: Vec<Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn manifest_groups() -> Result<(), serde_json::Error> {
+        let mut manifest = Manifest::new();
+        manifest.insert(&["whoami"], CallType::Sync);
+        manifest.insert(&["blobs", "get"], CallType::Source);
+        manifest.insert(&["blobs", "add"], CallType::Sink);
+
+        assert_eq!(manifest.get(&["blobs", "get"]), Some(CallType::Source));
+        assert_eq!(manifest.get(&["blobs"]), None);
+        assert_eq!(manifest.get(&["whoami", "get"]), None);
+        assert_eq!(manifest.get::<&str>(&[]), None);
+
+        let value = json!({ "whoami": "sync", "blobs": { "get": "source", "add": "sink" } });
+        assert_eq!(serde_json::to_value(&manifest)?, value);
+        assert_eq!(serde_json::from_value::<Manifest>(value)?, manifest);
+        Ok(())
+    }
+}
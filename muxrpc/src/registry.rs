@@ -0,0 +1,220 @@
+use futures::{
+    future::{self, BoxFuture},
+    stream::{self, BoxStream, Stream},
+    Future, FutureExt, StreamExt,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::{Body, CallType, Manifest, RpcError, RpcResult};
+
+/// A stream of items, or an error which ends the stream
+pub type RpcStream = BoxStream<'static, RpcResult<Body>>;
+
+type AsyncHandler = Box<dyn Fn(Vec<Value>) -> BoxFuture<'static, RpcResult<Body>> + Send + Sync>;
+type SyncHandler = Box<dyn Fn(Vec<Value>) -> RpcResult<Body> + Send + Sync>;
+type SourceHandler = Box<dyn Fn(Vec<Value>) -> RpcStream + Send + Sync>;
+type SinkHandler =
+    Box<dyn Fn(Vec<Value>, RpcStream) -> BoxFuture<'static, RpcResult<()>> + Send + Sync>;
+type DuplexHandler = Box<dyn Fn(Vec<Value>, RpcStream) -> RpcStream + Send + Sync>;
+
+/// A method, which is called with the request args
+pub(crate) enum Handler {
+    Async(AsyncHandler),
+    Sync(SyncHandler),
+    Source(SourceHandler),
+    Sink(SinkHandler),
+    Duplex(DuplexHandler),
+}
+
+impl Handler {
+    pub(crate) fn call_type(&self) -> CallType {
+        match self {
+            Handler::Async(_) => CallType::Async,
+            Handler::Sync(_) => CallType::Sync,
+            Handler::Source(_) => CallType::Source,
+            Handler::Sink(_) => CallType::Sink,
+            Handler::Duplex(_) => CallType::Duplex,
+        }
+    }
+}
+
+/// The error to answer a call with, when there is no handler for it.
+pub(crate) fn method_error(
+    name: &[String],
+    call_type: CallType,
+    handler: Option<&Handler>,
+) -> RpcError {
+    let name = name.join(".");
+    match handler {
+        None => RpcError::new(format!("no method {}", name)),
+        Some(handler) => RpcError::new(format!(
+            "method {} is {:?}, but was called as {:?}",
+            name,
+            handler.call_type(),
+            call_type
+        )),
+    }
+}
+
+/// A group of methods, registered together.
+///
+/// For example, a service over a msg store might register `msg.get` and `msg.publish`.
+pub trait Service {
+    fn register(&self, registry: &mut Registry);
+}
+
+/// The methods we answer calls to, and the manifest which describes them
+#[derive(Default)]
+pub struct Registry {
+    manifest: Manifest,
+    handlers: HashMap<Vec<String>, Handler>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn register<S: Service>(&mut self, service: &S) -> &mut Self {
+        service.register(self);
+        self
+    }
+
+    fn insert<S: AsRef<str>>(&mut self, name: &[S], handler: Handler) -> &mut Self {
+        self.manifest.insert(name, handler.call_type());
+        let name = name.iter().map(|part| part.as_ref().to_string()).collect();
+        self.handlers.insert(name, handler);
+        self
+    }
+
+    pub(crate) fn get(&self, name: &[String]) -> Option<&Handler> {
+        self.handlers.get(name)
+    }
+
+    pub fn add_async<S, F, Fut>(&mut self, name: &[S], handler: F) -> &mut Self
+    where
+        S: AsRef<str>,
+        F: Fn(Vec<Value>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RpcResult<Body>> + Send + 'static,
+    {
+        self.insert(
+            name,
+            Handler::Async(Box::new(move |args| handler(args).boxed())),
+        )
+    }
+
+    pub fn add_sync<S, F>(&mut self, name: &[S], handler: F) -> &mut Self
+    where
+        S: AsRef<str>,
+        F: Fn(Vec<Value>) -> RpcResult<Body> + Send + Sync + 'static,
+    {
+        self.insert(name, Handler::Sync(Box::new(handler)))
+    }
+
+    pub fn add_source<S, F, St>(&mut self, name: &[S], handler: F) -> &mut Self
+    where
+        S: AsRef<str>,
+        F: Fn(Vec<Value>) -> St + Send + Sync + 'static,
+        St: Stream<Item = RpcResult<Body>> + Send + 'static,
+    {
+        self.insert(
+            name,
+            Handler::Source(Box::new(move |args| handler(args).boxed())),
+        )
+    }
+
+    /// Add a sink, whose handler reads the stream until it ends, or returns early with an error.
+    pub fn add_sink<S, F, Fut>(&mut self, name: &[S], handler: F) -> &mut Self
+    where
+        S: AsRef<str>,
+        F: Fn(Vec<Value>, RpcStream) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RpcResult<()>> + Send + 'static,
+    {
+        self.insert(
+            name,
+            Handler::Sink(Box::new(move |args, input| handler(args, input).boxed())),
+        )
+    }
+
+    pub fn add_duplex<S, F, St>(&mut self, name: &[S], handler: F) -> &mut Self
+    where
+        S: AsRef<str>,
+        F: Fn(Vec<Value>, RpcStream) -> St + Send + Sync + 'static,
+        St: Stream<Item = RpcResult<Body>> + Send + 'static,
+    {
+        self.insert(
+            name,
+            Handler::Duplex(Box::new(move |args, input| handler(args, input).boxed())),
+        )
+    }
+
+    fn lookup<S: AsRef<str>>(&self, name: &[S]) -> (Vec<String>, Option<&Handler>) {
+        let name: Vec<String> = name.iter().map(|part| part.as_ref().to_string()).collect();
+        let handler = self.handlers.get(&name);
+        (name, handler)
+    }
+
+    /// Call an async or sync method in process, as if the call came from a remote.
+    pub fn call<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        args: Vec<Value>,
+    ) -> BoxFuture<'static, RpcResult<Body>> {
+        match self.lookup(name) {
+            (_, Some(Handler::Async(handler))) => handler(args),
+            (_, Some(Handler::Sync(handler))) => future::ready(handler(args)).boxed(),
+            (name, handler) => {
+                let error = method_error(&name, CallType::Async, handler);
+                future::ready(Err(error)).boxed()
+            }
+        }
+    }
+
+    /// Call a source method in process.
+    pub fn source<S: AsRef<str>>(&self, name: &[S], args: Vec<Value>) -> RpcStream {
+        match self.lookup(name) {
+            (_, Some(Handler::Source(handler))) => handler(args),
+            (name, handler) => {
+                let error = method_error(&name, CallType::Source, handler);
+                stream::once(future::ready(Err(error))).boxed()
+            }
+        }
+    }
+
+    /// Call a sink method in process.
+    pub fn sink<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        args: Vec<Value>,
+        input: RpcStream,
+    ) -> BoxFuture<'static, RpcResult<()>> {
+        match self.lookup(name) {
+            (_, Some(Handler::Sink(handler))) => handler(args, input),
+            (name, handler) => {
+                let error = method_error(&name, CallType::Sink, handler);
+                future::ready(Err(error)).boxed()
+            }
+        }
+    }
+
+    /// Call a duplex method in process.
+    pub fn duplex<S: AsRef<str>>(
+        &self,
+        name: &[S],
+        args: Vec<Value>,
+        input: RpcStream,
+    ) -> RpcStream {
+        match self.lookup(name) {
+            (_, Some(Handler::Duplex(handler))) => handler(args, input),
+            (name, handler) => {
+                let error = method_error(&name, CallType::Duplex, handler);
+                stream::once(future::ready(Err(error))).boxed()
+            }
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Packet stream, the multiplexed framing which muxrpc is built on.
+//!
+//! - [Scuttlebutt Protocol Guide: RPC protocol](https://ssbc.github.io/scuttlebutt-protocol-guide/#rpc-protocol)
+//!
+//! Each packet is a 9 byte header, holding flags, the body length, and the request number, then
+//! the body. A header of all zeros is the goodbye, which ends the packet stream.
+
+use std::io;
+
+mod mux;
+mod packet;
+mod stream;
+
+pub use crate::mux::{Mux, MuxError, MuxEvent, RequestId, StreamKind};
+pub use crate::packet::{
+    BodyType, Decoded, Packet, PacketDecoder, PacketHeader, HEADER_LENGTH, MAX_BODY_LENGTH,
+};
+pub use crate::stream::{PacketReader, PacketWriter};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PacketStreamError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid body type in packet flags: {flags:#06b}")]
+    InvalidBodyType { flags: u8 },
+    #[error("packet body of {body_length} bytes is longer than the max of {max_body_length}")]
+    BodyTooLong {
+        body_length: u32,
+        max_body_length: u32,
+    },
+    #[error("stream ended in the middle of a packet")]
+    Truncated,
+    #[error("packet stream was closed with a goodbye")]
+    Closed,
+}
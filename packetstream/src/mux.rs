@@ -0,0 +1,441 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{BodyType, Packet};
+
+/// The body which ends a stream without an error
+const END_BODY: &[u8] = b"true";
+
+/// A request, told apart by who made it, since both sides number their requests from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RequestId {
+    /// A request we made, sent as a positive request number
+    Local(i32),
+    /// A request the remote made, which we respond to with a negative request number
+    Remote(i32),
+}
+
+impl RequestId {
+    fn from_request_number(request_number: i32) -> Result<Self, MuxError> {
+        match request_number {
+            0 | i32::MIN => Err(MuxError::InvalidRequestNumber { request_number }),
+            n if n > 0 => Ok(RequestId::Remote(n)),
+            n => Ok(RequestId::Local(-n)),
+        }
+    }
+
+    fn to_request_number(self) -> i32 {
+        match self {
+            RequestId::Local(n) => n,
+            RequestId::Remote(n) => -n,
+        }
+    }
+}
+
+/// Which way data flows on a stream, from the point of view of the side which opened it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamKind {
+    /// Both sides send data
+    Duplex,
+    /// Only the side which did not open the stream sends data
+    Source,
+    /// Only the side which opened the stream sends data
+    Sink,
+}
+
+#[derive(Clone, Debug)]
+struct StreamState {
+    kind: StreamKind,
+    sent_end: bool,
+    received_end: bool,
+}
+
+impl StreamState {
+    fn new(kind: StreamKind) -> Self {
+        Self {
+            kind,
+            sent_end: false,
+            received_end: false,
+        }
+    }
+}
+
+fn can_send(id: RequestId, kind: StreamKind) -> bool {
+    match id {
+        RequestId::Local(_) => kind != StreamKind::Source,
+        RequestId::Remote(_) => kind != StreamKind::Sink,
+    }
+}
+
+fn can_receive(id: RequestId, kind: StreamKind) -> bool {
+    match id {
+        RequestId::Local(_) => kind != StreamKind::Sink,
+        RequestId::Remote(_) => kind != StreamKind::Source,
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MuxError {
+    #[error("invalid request number: {request_number}")]
+    InvalidRequestNumber { request_number: i32 },
+    #[error("no open request or stream for {0:?}")]
+    UnknownRequest(RequestId),
+    #[error("stream {0:?} has already ended in this direction")]
+    StreamEnded(RequestId),
+    #[error("stream {0:?} does not carry data in this direction")]
+    WrongDirection(RequestId),
+}
+
+/// What a received packet means, once multiplexed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MuxEvent {
+    /// The remote made a request, which we should [`Mux::respond`] to
+    Request {
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+    },
+    /// The response to one of our requests
+    Response {
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+        is_error: bool,
+    },
+    /// The remote opened a stream, which we should [`Mux::accept`] with its kind
+    StreamOpened {
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+    },
+    StreamData {
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+    },
+    /// The remote ended its side of a stream, with an error unless the body is `true`
+    StreamEnded {
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+    },
+}
+
+/// Multiplexes requests and streams over one packet stream.
+///
+/// The mux does no io: it makes the packets to send, and makes sense of the packets received, while
+/// keeping track of which requests and streams are open.
+#[derive(Debug)]
+pub struct Mux {
+    next_request_number: i32,
+    requests: HashSet<RequestId>,
+    streams: HashMap<RequestId, StreamState>,
+}
+
+impl Default for Mux {
+    fn default() -> Self {
+        Self {
+            next_request_number: 1,
+            requests: HashSet::new(),
+            streams: HashMap::new(),
+        }
+    }
+}
+
+impl Mux {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The next request number, wrapping around to 1 and skipping those still open.
+    fn next_id(&mut self) -> RequestId {
+        loop {
+            let id = RequestId::Local(self.next_request_number);
+            self.next_request_number = self.next_request_number.checked_add(1).unwrap_or(1);
+            if !self.is_open(id) {
+                return id;
+            }
+        }
+    }
+
+    /// Whether a request is awaiting its response, or a stream has not ended on both sides.
+    pub fn is_open(&self, id: RequestId) -> bool {
+        self.requests.contains(&id) || self.streams.contains_key(&id)
+    }
+
+    /// The kind of a stream which has not ended on both sides.
+    pub fn stream_kind(&self, id: RequestId) -> Option<StreamKind> {
+        self.streams.get(&id).map(|stream| stream.kind)
+    }
+
+    /// Make a request which expects one response.
+    pub fn request(&mut self, body_type: BodyType, body: Vec<u8>) -> (RequestId, Packet) {
+        let id = self.next_id();
+        self.requests.insert(id);
+        let packet = Packet {
+            stream: false,
+            end_or_error: false,
+            body_type,
+            request_number: id.to_request_number(),
+            body,
+        };
+        (id, packet)
+    }
+
+    /// Open a stream, where the first packet carries the request.
+    pub fn open(
+        &mut self,
+        kind: StreamKind,
+        body_type: BodyType,
+        body: Vec<u8>,
+    ) -> (RequestId, Packet) {
+        let id = self.next_id();
+        self.streams.insert(id, StreamState::new(kind));
+        let packet = Packet {
+            stream: true,
+            end_or_error: false,
+            body_type,
+            request_number: id.to_request_number(),
+            body,
+        };
+        (id, packet)
+    }
+
+    /// Respond to a request the remote made.
+    pub fn respond(
+        &mut self,
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+        is_error: bool,
+    ) -> Result<Packet, MuxError> {
+        if !matches!(id, RequestId::Remote(_)) || !self.requests.remove(&id) {
+            return Err(MuxError::UnknownRequest(id));
+        }
+        Ok(Packet {
+            stream: false,
+            end_or_error: is_error,
+            body_type,
+            request_number: id.to_request_number(),
+            body,
+        })
+    }
+
+    /// Set the kind of a stream the remote opened.
+    pub fn accept(&mut self, id: RequestId, kind: StreamKind) -> Result<(), MuxError> {
+        match self.streams.get_mut(&id) {
+            Some(stream) if matches!(id, RequestId::Remote(_)) => {
+                stream.kind = kind;
+                Ok(())
+            }
+            _ => Err(MuxError::UnknownRequest(id)),
+        }
+    }
+
+    /// Send data on a stream.
+    pub fn send(
+        &mut self,
+        id: RequestId,
+        body_type: BodyType,
+        body: Vec<u8>,
+    ) -> Result<Packet, MuxError> {
+        let stream = self.streams.get(&id).ok_or(MuxError::UnknownRequest(id))?;
+        if stream.sent_end {
+            return Err(MuxError::StreamEnded(id));
+        }
+        if !can_send(id, stream.kind) {
+            return Err(MuxError::WrongDirection(id));
+        }
+        Ok(Packet {
+            stream: true,
+            end_or_error: false,
+            body_type,
+            request_number: id.to_request_number(),
+            body,
+        })
+    }
+
+    /// End our side of a stream, with a JSON error body or else `true`.
+    pub fn end(&mut self, id: RequestId, error: Option<Vec<u8>>) -> Result<Packet, MuxError> {
+        let stream = self
+            .streams
+            .get_mut(&id)
+            .ok_or(MuxError::UnknownRequest(id))?;
+        if stream.sent_end {
+            return Err(MuxError::StreamEnded(id));
+        }
+        stream.sent_end = true;
+        if stream.received_end {
+            self.streams.remove(&id);
+        }
+        Ok(Packet {
+            stream: true,
+            end_or_error: true,
+            body_type: BodyType::Json,
+            request_number: id.to_request_number(),
+            body: error.unwrap_or_else(|| END_BODY.to_vec()),
+        })
+    }
+
+    /// Make sense of a received packet.
+    pub fn recv(&mut self, packet: Packet) -> Result<MuxEvent, MuxError> {
+        let id = RequestId::from_request_number(packet.request_number)?;
+        let Packet {
+            stream,
+            end_or_error,
+            body_type,
+            body,
+            ..
+        } = packet;
+
+        if !stream {
+            return match id {
+                RequestId::Remote(_) => {
+                    self.requests.insert(id);
+                    Ok(MuxEvent::Request {
+                        id,
+                        body_type,
+                        body,
+                    })
+                }
+                RequestId::Local(_) => {
+                    if !self.requests.remove(&id) {
+                        return Err(MuxError::UnknownRequest(id));
+                    }
+                    Ok(MuxEvent::Response {
+                        id,
+                        body_type,
+                        body,
+                        is_error: end_or_error,
+                    })
+                }
+            };
+        }
+
+        let Some(stream) = self.streams.get_mut(&id) else {
+            return match id {
+                RequestId::Remote(_) if !end_or_error => {
+                    // the kind is unknown until the stream is accepted
+                    self.streams
+                        .insert(id, StreamState::new(StreamKind::Duplex));
+                    Ok(MuxEvent::StreamOpened {
+                        id,
+                        body_type,
+                        body,
+                    })
+                }
+                _ => Err(MuxError::UnknownRequest(id)),
+            };
+        };
+        if stream.received_end {
+            return Err(MuxError::StreamEnded(id));
+        }
+        if end_or_error {
+            stream.received_end = true;
+            if stream.sent_end {
+                self.streams.remove(&id);
+            }
+            return Ok(MuxEvent::StreamEnded {
+                id,
+                body_type,
+                body,
+            });
+        }
+        if !can_receive(id, stream.kind) {
+            return Err(MuxError::WrongDirection(id));
+        }
+        Ok(MuxEvent::StreamData {
+            id,
+            body_type,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_and_response() -> Result<(), MuxError> {
+        let mut client = Mux::new();
+        let mut server = Mux::new();
+
+        let (id, request) = client.request(BodyType::Json, b"{}".to_vec());
+        assert_eq!(request.request_number, 1);
+        let MuxEvent::Request { id: server_id, .. } = server.recv(request)? else {
+            panic!("expected a request");
+        };
+        assert_eq!(server_id, RequestId::Remote(1));
+
+        let response = server.respond(server_id, BodyType::Utf8, b"hi".to_vec(), false)?;
+        assert_eq!(response.request_number, -1);
+        assert!(!server.is_open(server_id));
+        assert_eq!(
+            client.recv(response.clone())?,
+            MuxEvent::Response {
+                id,
+                body_type: BodyType::Utf8,
+                body: b"hi".to_vec(),
+                is_error: false,
+            }
+        );
+        assert!(!client.is_open(id));
+        assert!(matches!(
+            client.recv(response),
+            Err(MuxError::UnknownRequest(RequestId::Local(1)))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn request_numbers_wrap_around_open_requests() {
+        let mut mux = Mux::new();
+        let (first, _) = mux.request(BodyType::Json, b"{}".to_vec());
+        let (second, _) = mux.open(StreamKind::Duplex, BodyType::Json, b"{}".to_vec());
+        mux.next_request_number = i32::MAX;
+
+        let (last, _) = mux.request(BodyType::Json, b"{}".to_vec());
+        assert_eq!(last, RequestId::Local(i32::MAX));
+        let (id, request) = mux.request(BodyType::Json, b"{}".to_vec());
+        assert!(id != first && id != second);
+        assert_eq!(request.request_number, 3);
+    }
+
+    #[test]
+    fn source_stream() -> Result<(), MuxError> {
+        let mut client = Mux::new();
+        let mut server = Mux::new();
+
+        let (id, open) = client.open(StreamKind::Source, BodyType::Json, b"{}".to_vec());
+        let MuxEvent::StreamOpened { id: server_id, .. } = server.recv(open)? else {
+            panic!("expected a stream");
+        };
+        server.accept(server_id, StreamKind::Source)?;
+
+        // only the server sends data on a source
+        assert!(matches!(
+            client.send(id, BodyType::Binary, vec![1]),
+            Err(MuxError::WrongDirection(_))
+        ));
+        for body in [vec![1], vec![2]] {
+            let data = server.send(server_id, BodyType::Binary, body.clone())?;
+            assert_eq!(
+                client.recv(data)?,
+                MuxEvent::StreamData {
+                    id,
+                    body_type: BodyType::Binary,
+                    body,
+                }
+            );
+        }
+
+        let end = server.end(server_id, None)?;
+        assert!(matches!(client.recv(end)?, MuxEvent::StreamEnded { .. }));
+        assert!(client.is_open(id));
+        let end = client.end(id, None)?;
+        assert!(!client.is_open(id));
+        assert!(matches!(server.recv(end)?, MuxEvent::StreamEnded { .. }));
+        assert!(!server.is_open(server_id));
+        Ok(())
+    }
+}
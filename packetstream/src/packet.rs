@@ -0,0 +1,213 @@
+use crate::PacketStreamError;
+
+/// The length of a packet header: flags, body length, and request number
+pub const HEADER_LENGTH: usize = 9;
+
+/// The default longest body a [`PacketDecoder`] accepts, so a peer can't make us buffer 4 GiB
+pub const MAX_BODY_LENGTH: u32 = 16 * 1024 * 1024;
+
+const FLAG_STREAM: u8 = 0b1000;
+const FLAG_END_OR_ERROR: u8 = 0b0100;
+const FLAG_BODY_TYPE: u8 = 0b0011;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BodyType {
+    Binary,
+    Utf8,
+    Json,
+}
+
+impl BodyType {
+    fn to_flags(self) -> u8 {
+        match self {
+            BodyType::Binary => 0,
+            BodyType::Utf8 => 1,
+            BodyType::Json => 2,
+        }
+    }
+
+    fn from_flags(flags: u8) -> Result<Self, PacketStreamError> {
+        match flags & FLAG_BODY_TYPE {
+            0 => Ok(BodyType::Binary),
+            1 => Ok(BodyType::Utf8),
+            2 => Ok(BodyType::Json),
+            _ => Err(PacketStreamError::InvalidBodyType { flags }),
+        }
+    }
+}
+
+/// A packet: one message of a request, response, or stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub stream: bool,
+    pub end_or_error: bool,
+    pub body_type: BodyType,
+    /// Positive for requests, negative for responses to a request of the same number
+    pub request_number: i32,
+    pub body: Vec<u8>,
+}
+
+impl Packet {
+    pub fn encode_header(&self) -> [u8; HEADER_LENGTH] {
+        let mut flags = self.body_type.to_flags();
+        if self.stream {
+            flags |= FLAG_STREAM;
+        }
+        if self.end_or_error {
+            flags |= FLAG_END_OR_ERROR;
+        }
+        let mut header = [0; HEADER_LENGTH];
+        header[0] = flags;
+        header[1..5].copy_from_slice(&(self.body.len() as u32).to_be_bytes());
+        header[5..].copy_from_slice(&self.request_number.to_be_bytes());
+        header
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        [self.encode_header().as_slice(), &self.body].concat()
+    }
+}
+
+/// A decoded packet header
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PacketHeader {
+    pub stream: bool,
+    pub end_or_error: bool,
+    pub body_type: BodyType,
+    pub body_length: u32,
+    pub request_number: i32,
+}
+
+impl PacketHeader {
+    /// Decode a header, returning `None` if it is the goodbye, which is all zeros.
+    pub fn decode(header: &[u8; HEADER_LENGTH]) -> Result<Option<Self>, PacketStreamError> {
+        if header.iter().all(|byte| *byte == 0) {
+            return Ok(None);
+        }
+        let flags = header[0];
+        Ok(Some(Self {
+            stream: flags & FLAG_STREAM != 0,
+            end_or_error: flags & FLAG_END_OR_ERROR != 0,
+            body_type: BodyType::from_flags(flags)?,
+            body_length: u32::from_be_bytes(header[1..5].try_into().unwrap()),
+            request_number: i32::from_be_bytes(header[5..].try_into().unwrap()),
+        }))
+    }
+}
+
+/// Decodes packets from bytes as they arrive, in whatever chunks they arrive in.
+#[derive(Debug)]
+pub struct PacketDecoder {
+    buffer: Vec<u8>,
+    max_body_length: u32,
+}
+
+impl Default for PacketDecoder {
+    fn default() -> Self {
+        Self::with_max_body_length(MAX_BODY_LENGTH)
+    }
+}
+
+/// A decoded packet, or the goodbye which ends a packet stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decoded {
+    Packet(Packet),
+    Goodbye,
+}
+
+impl PacketDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A decoder which rejects packets with a longer body than the max.
+    pub fn with_max_body_length(max_body_length: u32) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_body_length,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Whether there are no buffered bytes of a partial packet.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Decode the next packet, or `None` if more bytes are needed.
+    pub fn decode(&mut self) -> Result<Option<Decoded>, PacketStreamError> {
+        if self.buffer.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+        let header = self.buffer[..HEADER_LENGTH].try_into().unwrap();
+        let Some(header) = PacketHeader::decode(header)? else {
+            self.buffer.drain(..HEADER_LENGTH);
+            return Ok(Some(Decoded::Goodbye));
+        };
+        if header.body_length > self.max_body_length {
+            return Err(PacketStreamError::BodyTooLong {
+                body_length: header.body_length,
+                max_body_length: self.max_body_length,
+            });
+        }
+        let packet_length = HEADER_LENGTH + header.body_length as usize;
+        if self.buffer.len() < packet_length {
+            return Ok(None);
+        }
+        let body = self.buffer[HEADER_LENGTH..packet_length].to_vec();
+        self.buffer.drain(..packet_length);
+        Ok(Some(Decoded::Packet(Packet {
+            stream: header.stream,
+            end_or_error: header.end_or_error,
+            body_type: header.body_type,
+            request_number: header.request_number,
+            body,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_in_chunks() -> Result<(), PacketStreamError> {
+        let packet = Packet {
+            stream: true,
+            end_or_error: false,
+            body_type: BodyType::Json,
+            request_number: -3,
+            body: br#"{"hello":"world"}"#.to_vec(),
+        };
+        let bytes = packet.encode();
+        assert_eq!(
+            &bytes[..HEADER_LENGTH],
+            &[0b1010, 0, 0, 0, 17, 255, 255, 255, 253]
+        );
+
+        let mut decoder = PacketDecoder::new();
+        for chunk in [&bytes[..4], &bytes[4..12]] {
+            decoder.push(chunk);
+            assert_eq!(decoder.decode()?, None);
+        }
+        decoder.push(&bytes[12..]);
+        decoder.push(&[0; HEADER_LENGTH]);
+        assert_eq!(decoder.decode()?, Some(Decoded::Packet(packet)));
+        assert_eq!(decoder.decode()?, Some(Decoded::Goodbye));
+        assert!(decoder.is_empty());
+
+        let mut decoder = PacketDecoder::with_max_body_length(16);
+        decoder.push(&bytes);
+        assert!(matches!(
+            decoder.decode(),
+            Err(PacketStreamError::BodyTooLong {
+                body_length: 17,
+                max_body_length: 16,
+            })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use futures::{ready, AsyncRead, AsyncWrite, Sink, Stream};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{Decoded, Packet, PacketDecoder, PacketStreamError, HEADER_LENGTH};
+
+const READ_CHUNK_LENGTH: usize = 4096;
+
+/// A stream of packets read from an inner reader, which ends at the goodbye.
+pub struct PacketReader<R> {
+    inner: R,
+    decoder: PacketDecoder,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> PacketReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_decoder(inner, PacketDecoder::new())
+    }
+
+    /// Read with a decoder of other limits, like [`PacketDecoder::with_max_body_length`].
+    pub fn with_decoder(inner: R, decoder: PacketDecoder) -> Self {
+        Self {
+            inner,
+            decoder,
+            done: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for PacketReader<R> {
+    type Item = Result<Packet, PacketStreamError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            match this.decoder.decode() {
+                Err(err) => {
+                    // the decoder can't find the next packet after a bad header
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Ok(Some(Decoded::Packet(packet))) => return Poll::Ready(Some(Ok(packet))),
+                Ok(Some(Decoded::Goodbye)) => {
+                    this.done = true;
+                    continue;
+                }
+                Ok(None) => {}
+            }
+
+            let mut chunk = [0; READ_CHUNK_LENGTH];
+            let n = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            if n == 0 {
+                this.done = true;
+                if !this.decoder.is_empty() {
+                    return Poll::Ready(Some(Err(PacketStreamError::Truncated)));
+                }
+                continue;
+            }
+            this.decoder.push(&chunk[..n]);
+        }
+    }
+}
+
+/// A sink of packets written to an inner writer. Closing the sink sends the goodbye.
+pub struct PacketWriter<W> {
+    inner: W,
+    pending: Vec<u8>,
+    written: usize,
+    goodbye: bool,
+}
+
+impl<W: AsyncWrite + Unpin> PacketWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+            written: 0,
+            goodbye: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.pending.len() {
+            let n =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        self.pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Sink<Packet> for PacketWriter<W> {
+    type Error = PacketStreamError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, packet: Packet) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        if this.goodbye {
+            return Err(PacketStreamError::Closed);
+        }
+        this.pending = packet.encode();
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        ready!(Pin::new(&mut this.inner).poll_flush(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        if !this.goodbye {
+            this.pending = vec![0; HEADER_LENGTH];
+            this.goodbye = true;
+            ready!(this.poll_pending(cx))?;
+        }
+        ready!(Pin::new(&mut this.inner).poll_close(cx))?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, io::Cursor, SinkExt, StreamExt};
+
+    use super::*;
+    use crate::BodyType;
+
+    #[test]
+    fn write_then_read_packets() -> Result<(), PacketStreamError> {
+        let packets: Vec<Packet> = (1..=3)
+            .map(|request_number| Packet {
+                stream: false,
+                end_or_error: false,
+                body_type: BodyType::Utf8,
+                request_number,
+                body: format!("hello {}", request_number).into_bytes(),
+            })
+            .collect();
+
+        let mut writer = PacketWriter::new(Cursor::new(Vec::new()));
+        block_on(async {
+            for packet in &packets {
+                writer.send(packet.clone()).await?;
+            }
+            writer.close().await
+        })?;
+        let mut bytes = writer.into_inner().into_inner();
+        // anything after the goodbye is ignored
+        bytes.extend_from_slice(b"trailing");
+
+        let reader = PacketReader::new(Cursor::new(bytes));
+        let read: Vec<Packet> = block_on(reader.map(Result::unwrap).collect());
+        assert_eq!(read, packets);
+        Ok(())
+    }
+
+    #[test]
+    fn end_after_decode_error() -> Result<(), PacketStreamError> {
+        let packet = Packet {
+            stream: false,
+            end_or_error: false,
+            body_type: BodyType::Utf8,
+            request_number: 1,
+            body: b"longer than the max body length".to_vec(),
+        };
+        let mut writer = PacketWriter::new(Cursor::new(Vec::new()));
+        block_on(writer.send(packet))?;
+        let bytes = writer.into_inner().into_inner();
+
+        let mut reader =
+            PacketReader::with_decoder(Cursor::new(bytes), PacketDecoder::with_max_body_length(16));
+        assert!(matches!(
+            block_on(reader.next()),
+            Some(Err(PacketStreamError::BodyTooLong { .. }))
+        ));
+        assert!(block_on(reader.next()).is_none());
+        Ok(())
+    }
+}
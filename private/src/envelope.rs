@@ -0,0 +1,114 @@
+use ppppp_bytes::{FromBytes, ToBytes};
+use ppppp_crypto::{BoxError, BoxingKey, CryptoRngCore, SecretBoxKey, UnboxingKey};
+use ppppp_msg::MsgData;
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvelopeError {
+    #[error("envelope has no recipients")]
+    NoRecipients,
+    #[error("envelope is not base58")]
+    Base58,
+    #[error("envelope crypto error: {0}")]
+    Box(#[from] BoxError),
+    #[error("invalid envelope plaintext: {0}")]
+    Json(#[from] JsonError),
+}
+
+/// Msg data encrypted to recipients
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Envelope {
+    /// The data, encrypted with the msg key, as base58
+    #[serde(rename = "box")]
+    ciphertext: String,
+    /// The msg key sealed to each recipient, as base58
+    keys: Vec<String>,
+}
+
+impl Envelope {
+    /// Encrypt data to the recipients, with a new msg key.
+    pub fn seal<R: CryptoRngCore>(
+        rng: &mut R,
+        data: &MsgData,
+        recipients: &[BoxingKey],
+    ) -> Result<Self, EnvelopeError> {
+        if recipients.is_empty() {
+            return Err(EnvelopeError::NoRecipients);
+        }
+        let msg_key = SecretBoxKey::generate(rng);
+
+        let plaintext = serde_json::to_vec(data)?;
+        let ciphertext = msg_key.encrypt(rng, &plaintext)?;
+        let keys = recipients
+            .iter()
+            .map(|recipient| {
+                let sealed = recipient.seal(rng, &msg_key.to_bytes())?;
+                Ok(ppppp_base58::encode(&sealed))
+            })
+            .collect::<Result<_, EnvelopeError>>()?;
+
+        Ok(Self {
+            ciphertext: ppppp_base58::encode(&ciphertext),
+            keys,
+        })
+    }
+
+    /// Decrypt the data with a recipient's key, or `None` if the envelope is not addressed to it.
+    pub fn open(&self, unboxing_key: &UnboxingKey) -> Result<Option<MsgData>, EnvelopeError> {
+        for key in &self.keys {
+            let sealed = ppppp_base58::decode(key).map_err(|_| EnvelopeError::Base58)?;
+            let Ok(msg_key) = unboxing_key.unseal(&sealed) else {
+                continue;
+            };
+            let msg_key: [u8; 32] = msg_key.try_into().map_err(|_| BoxError)?;
+            let msg_key = SecretBoxKey::from_bytes(&msg_key).unwrap();
+
+            let ciphertext =
+                ppppp_base58::decode(&self.ciphertext).map_err(|_| EnvelopeError::Base58)?;
+            let plaintext = msg_key.decrypt(&ciphertext)?;
+            return Ok(Some(serde_json::from_slice(&plaintext)?));
+        }
+        Ok(None)
+    }
+
+    /// The envelope in msg data, if the data is an envelope
+    pub fn from_data(data: &MsgData) -> Option<Self> {
+        serde_json::from_value(data.to_value()).ok()
+    }
+
+    pub fn to_data(&self) -> MsgData {
+        let value = serde_json::to_value(self).expect("envelope serializes to json");
+        MsgData::try_from(value).expect("envelope is an object")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{BoxKeypair, OsRng};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn seal_and_open() -> Result<(), Box<dyn Error>> {
+        let alice = BoxKeypair::generate(&mut OsRng);
+        let bob = BoxKeypair::generate(&mut OsRng);
+        let carol = BoxKeypair::generate(&mut OsRng);
+        let data = MsgData::try_from(json!({ "text": "hi bob" }))?;
+
+        let recipients = [alice.boxing_key().clone(), bob.boxing_key().clone()];
+        let envelope = Envelope::seal(&mut OsRng, &data, &recipients)?;
+        let envelope = Envelope::from_data(&envelope.to_data()).unwrap();
+
+        for recipient in [&alice, &bob] {
+            let opened = envelope.open(recipient.unboxing_key())?.unwrap();
+            assert_eq!(opened.to_value(), data.to_value());
+        }
+        assert!(envelope.open(carol.unboxing_key())?.is_none());
+        assert!(Envelope::from_data(&data).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,14 @@
+//! Private msgs, whose data is encrypted to the accounts they are addressed to.
+//!
+//! The data of a private msg is an [`Envelope`]: the plaintext data encrypted with a key made
+//! for the msg, and that key sealed to the `ExternalEncryption` key of each recipient account.
+//! The envelope is the msg data, so the msg's `data_hash` and `data_size` are of the envelope,
+//! and the msg validates without being decrypted.
+//!
+//! The sealed keys are anonymous, so the envelope doesn't say who the recipients are.
+
+mod envelope;
+mod publish;
+
+pub use crate::envelope::{Envelope, EnvelopeError};
+pub use crate::publish::{publish, read, PrivateError, PrivatePublishOpts};
@@ -0,0 +1,172 @@
+use ppppp_crypto::{CryptoRngCore, SignKeypair, UnboxingKey};
+use ppppp_msg::{Msg, MsgData, MsgDomain, MsgId};
+use ppppp_msg_store::{FeedPublishOpts, MsgStore, MsgStoreError};
+use typed_builder::TypedBuilder;
+
+use crate::{Envelope, EnvelopeError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PrivateError {
+    #[error("store error: {0}")]
+    Store(#[from] MsgStoreError),
+    #[error("envelope error: {0}")]
+    Envelope(#[from] EnvelopeError),
+    #[error("account {account_id} has no encryption key")]
+    NoEncryptionKey { account_id: MsgId },
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct PrivatePublishOpts {
+    #[builder(setter(into))]
+    pub account_id: MsgId,
+    #[builder(setter(into))]
+    pub domain: MsgDomain,
+    /// The plaintext data
+    #[builder(setter(into))]
+    pub data: MsgData,
+    /// The accounts who can read the msg, which should include the author to read it later
+    #[builder(setter(into))]
+    pub recipients: Vec<MsgId>,
+    #[builder(setter(into))]
+    pub sign_keypair: SignKeypair,
+}
+
+/// Publish a private msg to an account's feed for a domain.
+///
+/// The data is encrypted to the `ExternalEncryption` keys of each recipient, from their account
+/// tangles.
+pub fn publish<R: CryptoRngCore>(
+    store: &mut MsgStore,
+    rng: &mut R,
+    opts: PrivatePublishOpts,
+) -> Result<(MsgId, Msg), PrivateError> {
+    let PrivatePublishOpts {
+        account_id,
+        domain,
+        data,
+        recipients,
+        sign_keypair,
+    } = opts;
+
+    let mut boxing_keys = Vec::new();
+    for recipient in recipients {
+        let keys = store.account(&recipient)?.boxing_keys();
+        if keys.is_empty() {
+            return Err(PrivateError::NoEncryptionKey {
+                account_id: recipient,
+            });
+        }
+        boxing_keys.extend(keys);
+    }
+    let envelope = Envelope::seal(rng, &data, &boxing_keys)?;
+
+    Ok(store.publish(
+        FeedPublishOpts::builder()
+            .account_id(account_id)
+            .domain(domain)
+            .data(envelope.to_data())
+            .sign_keypair(sign_keypair)
+            .build(),
+    )?)
+}
+
+/// Read a msg with a recipient's key: the plaintext data, or `None` if the msg is not private or
+/// not addressed to the key.
+pub fn read(msg: &Msg, unboxing_key: &UnboxingKey) -> Result<Option<MsgData>, PrivateError> {
+    let Some(envelope) = Envelope::from_data(msg.data()) else {
+        return Ok(None);
+    };
+    Ok(envelope.open(unboxing_key)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{BoxKeypair, Nonce, OsRng};
+    use ppppp_msg::{AccountKey, AccountPower, MsgAccountAddOpts};
+    use serde_json::json;
+    use std::error::Error;
+
+    use super::*;
+
+    /// Create an account, with an encryption key if given
+    fn account(
+        store: &mut MsgStore,
+        keypair: &SignKeypair,
+        box_keypair: Option<&BoxKeypair>,
+    ) -> Result<MsgId, Box<dyn Error>> {
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        store.add_validated(account, &account_id)?;
+        if let Some(box_keypair) = box_keypair {
+            let add = Msg::create_account_add(
+                MsgAccountAddOpts::builder()
+                    .account_tangle(store.tangle(&account_id)?.unwrap())
+                    .new_key(AccountKey::ExternalEncryption {
+                        algorithm: Default::default(),
+                        bytes: box_keypair.boxing_key().clone(),
+                    })
+                    .powers(vec![AccountPower::ExternalEncryption])
+                    .sign_keypair(keypair.clone())
+                    .build(),
+            )?;
+            store.add_validated(add, &account_id)?;
+        }
+        Ok(account_id)
+    }
+
+    #[test]
+    fn publish_and_read() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut store = MsgStore::open(dir.path().join("log"))?;
+
+        let alice_keypair = SignKeypair::generate(&mut OsRng);
+        let alice_box = BoxKeypair::generate(&mut OsRng);
+        let alice = account(&mut store, &alice_keypair, Some(&alice_box))?;
+        let bob_box = BoxKeypair::generate(&mut OsRng);
+        let bob = account(
+            &mut store,
+            &SignKeypair::generate(&mut OsRng),
+            Some(&bob_box),
+        )?;
+        let carol = account(&mut store, &SignKeypair::generate(&mut OsRng), None)?;
+
+        let data = MsgData::try_from(json!({ "text": "hi bob" }))?;
+        let opts = PrivatePublishOpts::builder()
+            .account_id(alice)
+            .domain(MsgDomain::try_from("dms".to_string())?)
+            .data(data.clone())
+            .sign_keypair(alice_keypair.clone());
+
+        let (msg_id, _) = publish(
+            &mut store,
+            &mut OsRng,
+            opts.clone().recipients(vec![alice, bob]).build(),
+        )?;
+        // the stored msg validated, with its data hash of the envelope
+        let msg = store.get(&msg_id)?.unwrap();
+        assert!(Envelope::from_data(msg.data()).is_some());
+        assert_eq!(msg.metadata().data_hash(), &Some(msg.data().to_hash().0));
+
+        for unboxing_key in [alice_box.unboxing_key(), bob_box.unboxing_key()] {
+            let read = read(&msg, unboxing_key)?.unwrap();
+            assert_eq!(read.to_value(), data.to_value());
+        }
+        let stranger = BoxKeypair::generate(&mut OsRng);
+        assert!(read(&msg, stranger.unboxing_key())?.is_none());
+
+        let result = publish(
+            &mut store,
+            &mut OsRng,
+            opts.recipients(vec![alice, carol]).build(),
+        );
+        assert!(matches!(
+            result,
+            Err(PrivateError::NoEncryptionKey { account_id }) if account_id == carol
+        ));
+        Ok(())
+    }
+}
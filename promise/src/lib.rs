@@ -0,0 +1,15 @@
+//! Promises, one-time tokens which authorize others to gain something.
+//!
+//! - [staltz/ppppp-promise](https://github.com/staltz/ppppp-promise)
+//!
+//! A peer creates a promise and shares its token, for example in an invite. Whoever has the token
+//! can redeem it, once, for what was promised:
+//!
+//! - `follow`: the account follows the redeemer
+//! - `account-add`: the redeemer's key is added to the account
+
+mod store;
+mod token;
+
+pub use crate::store::{AccountAddRedeemOpts, Promise, PromiseError, PromiseStore};
+pub use crate::token::{Token, TOKEN_LENGTH};
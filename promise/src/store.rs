@@ -0,0 +1,318 @@
+use ppppp_bytes::DeserializeBytesError;
+use ppppp_crypto::{CryptoRngCore, SignKeypair};
+use ppppp_msg::{
+    AccountConsent, AccountKey, AccountPower, Msg, MsgAccountAddError, MsgAccountAddOpts, MsgId,
+    Tangle,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use subtle::ConstantTimeEq;
+use tempfile::NamedTempFile;
+use typed_builder::TypedBuilder;
+
+use crate::Token;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PromiseError {
+    #[error("io error on {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid promises file {path}: {source}")]
+    Json {
+        path: PathBuf,
+        #[source]
+        source: JsonError,
+    },
+    #[error("invalid token in promises file {path}: {source}")]
+    Token {
+        path: PathBuf,
+        #[source]
+        source: DeserializeBytesError,
+    },
+    #[error("no promise for token")]
+    NotFound,
+    #[error("promise is not a {kind} promise")]
+    Kind { kind: &'static str },
+    #[error("promise is for account {account_id}, not the account tangle {tangle_id}")]
+    AccountMismatch { account_id: MsgId, tangle_id: MsgId },
+    #[error("account add error: {0}")]
+    AccountAdd(#[from] MsgAccountAddError),
+}
+
+/// What a promise authorizes
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Promise {
+    /// The account follows the redeemer
+    Follow { account: MsgId },
+    /// The redeemer's key is added to the account, with the powers
+    AccountAdd {
+        account: MsgId,
+        #[serde(default)]
+        powers: Vec<AccountPower>,
+    },
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct AccountAddRedeemOpts {
+    pub token: Token,
+    /// The tangle of the promised account
+    #[builder(setter(into))]
+    pub account_tangle: Tangle,
+    /// The redeemer's key
+    #[builder(setter(into))]
+    pub key: AccountKey,
+    /// The consent of the redeemer's key, required if the key is a signature key
+    #[builder(default, setter(strip_option))]
+    pub consent: Option<AccountConsent>,
+    /// The keypair of an existing key of the account with the "add" power
+    #[builder(setter(into))]
+    pub sign_keypair: SignKeypair,
+}
+
+/// A store of promises, in a JSON file of each token's promise.
+///
+/// The file is rewritten in full on every change, through a temporary file, so it is always
+/// whole.
+#[derive(Debug)]
+pub struct PromiseStore {
+    path: PathBuf,
+    promises: Vec<(Token, Promise)>,
+}
+
+impl PromiseStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PromiseError> {
+        let path = path.as_ref().to_path_buf();
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    path,
+                    promises: Vec::new(),
+                })
+            }
+            Err(source) => return Err(PromiseError::Io { path, source }),
+        };
+        let promises: BTreeMap<String, Promise> = match serde_json::from_slice(&content) {
+            Ok(promises) => promises,
+            Err(source) => return Err(PromiseError::Json { path, source }),
+        };
+        let promises = promises
+            .into_iter()
+            .map(|(token, promise)| Ok((token.parse()?, promise)))
+            .collect::<Result<_, DeserializeBytesError>>()
+            .map_err(|source| PromiseError::Token {
+                path: path.clone(),
+                source,
+            })?;
+        Ok(Self { path, promises })
+    }
+
+    fn save(&self) -> Result<(), PromiseError> {
+        let io_err = |source| PromiseError::Io {
+            path: self.path.clone(),
+            source,
+        };
+        let promises: BTreeMap<String, &Promise> = self
+            .promises
+            .iter()
+            .map(|(token, promise)| (token.to_string(), promise))
+            .collect();
+        let content = serde_json::to_vec_pretty(&promises).expect("promises serialize to json");
+
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir).map_err(io_err)?;
+        let file = NamedTempFile::new_in(dir).map_err(io_err)?;
+        fs::write(file.path(), content).map_err(io_err)?;
+        file.persist(&self.path).map_err(|err| io_err(err.error))?;
+        Ok(())
+    }
+
+    /// The index of a token's promise, comparing with every token in constant time.
+    fn find(&self, token: &Token) -> Option<usize> {
+        let mut found = None;
+        for (index, (candidate, _)) in self.promises.iter().enumerate() {
+            if bool::from(candidate.ct_eq(token)) {
+                found = Some(index);
+            }
+        }
+        found
+    }
+
+    /// Create a promise, returning the token which redeems it.
+    pub fn create<R: CryptoRngCore>(
+        &mut self,
+        rng: &mut R,
+        promise: Promise,
+    ) -> Result<Token, PromiseError> {
+        let token = Token::generate(rng);
+        self.promises.push((token.clone(), promise));
+        self.save()?;
+        Ok(token)
+    }
+
+    pub fn get(&self, token: &Token) -> Option<&Promise> {
+        self.find(token).map(|index| &self.promises[index].1)
+    }
+
+    /// Revoke a promise, returning whether there was one.
+    pub fn revoke(&mut self, token: &Token) -> Result<bool, PromiseError> {
+        let Some(index) = self.find(token) else {
+            return Ok(false);
+        };
+        self.promises.remove(index);
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Redeem a follow promise, returning the account which promised to follow the redeemer.
+    pub fn redeem_follow(&mut self, token: &Token) -> Result<MsgId, PromiseError> {
+        let index = self.find(token).ok_or(PromiseError::NotFound)?;
+        let Promise::Follow { account } = self.promises[index].1 else {
+            return Err(PromiseError::Kind { kind: "follow" });
+        };
+        self.promises.remove(index);
+        self.save()?;
+        Ok(account)
+    }
+
+    /// Redeem an account add promise, returning the account msg which adds the redeemer's key.
+    ///
+    /// The promise is only used up once the msg is created, so a redeemer who sends an invalid
+    /// consent can try again.
+    pub fn redeem_account_add(&mut self, opts: AccountAddRedeemOpts) -> Result<Msg, PromiseError> {
+        let AccountAddRedeemOpts {
+            token,
+            account_tangle,
+            key,
+            consent,
+            sign_keypair,
+        } = opts;
+
+        let index = self.find(&token).ok_or(PromiseError::NotFound)?;
+        let Promise::AccountAdd { account, powers } = &self.promises[index].1 else {
+            return Err(PromiseError::Kind {
+                kind: "account-add",
+            });
+        };
+        let tangle_id = *account_tangle.get_id();
+        if account != &tangle_id {
+            return Err(PromiseError::AccountMismatch {
+                account_id: *account,
+                tangle_id,
+            });
+        }
+        let msg = Msg::create_account_add(MsgAccountAddOpts {
+            account_tangle,
+            new_key: key,
+            powers: powers.clone(),
+            consent_keypair: None,
+            consent,
+            sign_keypair,
+        })?;
+
+        self.promises.remove(index);
+        self.save()?;
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng};
+    use ppppp_msg::{validate, Account};
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn create_revoke_and_redeem() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("promises.json");
+        let mut store = PromiseStore::open(&path)?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let root = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account = root.id()?;
+        let mut account_tangle = Tangle::new(account);
+        account_tangle.add(&account, &root);
+
+        let follow = store.create(&mut OsRng, Promise::Follow { account })?;
+        let revoked = store.create(&mut OsRng, Promise::Follow { account })?;
+        let add = store.create(
+            &mut OsRng,
+            Promise::AccountAdd {
+                account,
+                powers: vec![AccountPower::Add],
+            },
+        )?;
+        assert_ne!(follow, revoked);
+        assert_eq!(format!("{:?}", follow), "Token(..)");
+
+        // promises persist, by token
+        let mut store = PromiseStore::open(&path)?;
+        assert_eq!(store.get(&follow), Some(&Promise::Follow { account }));
+        assert!(store.revoke(&revoked)?);
+        assert!(!store.revoke(&revoked)?);
+        assert!(matches!(
+            store.redeem_follow(&add),
+            Err(PromiseError::Kind { kind: "follow" })
+        ));
+        assert_eq!(store.redeem_follow(&follow)?, account);
+        assert!(matches!(
+            store.redeem_follow(&follow),
+            Err(PromiseError::NotFound)
+        ));
+
+        let device = SignKeypair::generate(&mut OsRng);
+        let key = AccountKey::ShsAndExternalSignature {
+            algorithm: Default::default(),
+            bytes: device.verifying_key().clone(),
+        };
+        let opts = AccountAddRedeemOpts::builder()
+            .token(add.clone())
+            .account_tangle(account_tangle.clone())
+            .key(key.clone())
+            .sign_keypair(keypair);
+        let stranger = SignKeypair::generate(&mut OsRng);
+        assert!(matches!(
+            store.redeem_account_add(
+                opts.clone()
+                    .consent(AccountConsent::create(&account, stranger.signing_key()))
+                    .build()
+            ),
+            Err(PromiseError::AccountAdd(
+                MsgAccountAddError::ConsentDoesNotVerify(_)
+            ))
+        ));
+        let msg = store.redeem_account_add(
+            opts.consent(AccountConsent::create(&account, device.signing_key()))
+                .build(),
+        )?;
+        assert!(store.get(&add).is_none());
+        assert!(PromiseStore::open(&path)?.get(&add).is_none());
+
+        let msg_id = msg.id()?;
+        let verifying_keys = Account::from_tangle(&account_tangle, |_| None)?.verifying_keys();
+        validate(&msg, &msg_id, &account_tangle, &verifying_keys, &account)?;
+        account_tangle.add(&msg_id, &msg);
+        let account = Account::from_tangle(&account_tangle, |_| Some(msg.clone()))?;
+        assert_eq!(
+            account.get_powers(&key),
+            Some(&[AccountPower::Add].into_iter().collect())
+        );
+        Ok(())
+    }
+}
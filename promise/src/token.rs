@@ -0,0 +1,58 @@
+use ppppp_bytes::{impl_as_bytes_outputs, impl_from_bytes_inputs, AsBytes, FromBytes};
+use ppppp_crypto::CryptoRngCore;
+use std::convert::Infallible;
+use subtle::ConstantTimeEq;
+
+pub const TOKEN_LENGTH: usize = 32;
+
+/// The secret which redeems a promise.
+///
+/// Tokens are compared in constant time, so comparing doesn't leak how much of a guess is right,
+/// and are left out of `Debug`, so logging doesn't leak them.
+#[derive(Clone)]
+pub struct Token([u8; TOKEN_LENGTH]);
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Token").finish_non_exhaustive()
+    }
+}
+
+impl Token {
+    pub fn generate<R: CryptoRngCore>(rng: &mut R) -> Self {
+        let mut bytes = [0; TOKEN_LENGTH];
+        rng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+}
+
+impl FromBytes<TOKEN_LENGTH> for Token {
+    type Error = Infallible;
+
+    fn from_bytes(bytes: &[u8; TOKEN_LENGTH]) -> Result<Self, Self::Error> {
+        Ok(Self(*bytes))
+    }
+}
+
+impl AsBytes<TOKEN_LENGTH> for Token {
+    fn as_bytes(&self) -> &[u8; TOKEN_LENGTH] {
+        &self.0
+    }
+}
+
+impl_from_bytes_inputs!(Token, TOKEN_LENGTH);
+impl_as_bytes_outputs!(Token, TOKEN_LENGTH);
+
+impl ConstantTimeEq for Token {
+    fn ct_eq(&self, other: &Self) -> subtle::Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Token {}
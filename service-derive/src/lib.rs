@@ -0,0 +1,324 @@
+//! The `#[service]` attribute of [ppppp-service](https://docs.rs/ppppp-service), which derives a
+//! client and a server from a service trait.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, parse_quote, spanned::Spanned, FnArg, ItemTrait,
+    LitStr, Pat, ReturnType, TraitItem, TraitItemFn, Type,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum MethodKind {
+    Async,
+    Source,
+    Sink,
+    Duplex,
+}
+
+struct Method {
+    item: TraitItemFn,
+    kind: MethodKind,
+    /// The name of the input stream param, for sinks and duplexes
+    input: Option<Ident>,
+    params: Vec<(Ident, Type)>,
+}
+
+#[derive(Default)]
+struct ServiceArgs {
+    name: Option<LitStr>,
+}
+
+impl ServiceArgs {
+    fn parse(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("name") {
+            self.name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported service argument, expected `name`"))
+        }
+    }
+}
+
+fn parse_method(mut item: TraitItemFn) -> syn::Result<Method> {
+    let mut kind = None;
+    let mut error = None;
+    item.attrs.retain(|attr| {
+        let marker = if attr.path().is_ident("source") {
+            MethodKind::Source
+        } else if attr.path().is_ident("sink") {
+            MethodKind::Sink
+        } else if attr.path().is_ident("duplex") {
+            MethodKind::Duplex
+        } else {
+            return true;
+        };
+        if kind.replace(marker).is_some() {
+            error = Some(syn::Error::new(attr.span(), "a method has only one kind"));
+        }
+        false
+    });
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    let is_async = item.sig.asyncness.is_some();
+    let kind = match kind {
+        None if is_async => MethodKind::Async,
+        Some(MethodKind::Sink) if is_async => MethodKind::Sink,
+        Some(kind @ (MethodKind::Source | MethodKind::Duplex)) if !is_async => kind,
+        _ => return Err(syn::Error::new(
+            item.sig.span(),
+            "service methods are async, async and #[sink], or not async and #[source] or #[duplex]",
+        )),
+    };
+
+    let mut inputs = item.sig.inputs.iter();
+    match inputs.next() {
+        Some(FnArg::Receiver(receiver))
+            if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+        _ => {
+            return Err(syn::Error::new(
+                item.sig.span(),
+                "service methods take `&self`",
+            ))
+        }
+    }
+    let mut params = Vec::new();
+    for input in inputs {
+        let FnArg::Typed(typed) = input else {
+            unreachable!()
+        };
+        let Pat::Ident(pat) = typed.pat.as_ref() else {
+            return Err(syn::Error::new(
+                typed.pat.span(),
+                "service method params are plain names",
+            ));
+        };
+        params.push((pat.ident.clone(), typed.ty.as_ref().clone()));
+    }
+    let input = match kind {
+        MethodKind::Sink | MethodKind::Duplex => {
+            if params.is_empty() {
+                return Err(syn::Error::new(
+                    item.sig.span(),
+                    "sinks and duplexes take an input stream after `&self`",
+                ));
+            }
+            Some(params.remove(0).0)
+        }
+        MethodKind::Async | MethodKind::Source => None,
+    };
+
+    Ok(Method {
+        item,
+        kind,
+        input,
+        params,
+    })
+}
+
+/// The method in the service trait, where an async fn returns a future which is `Send`.
+fn trait_method(method: &Method) -> TraitItemFn {
+    let mut item = method.item.clone();
+    if item.sig.asyncness.take().is_some() {
+        let output = match &item.sig.output {
+            ReturnType::Default => quote!(()),
+            ReturnType::Type(_, ty) => quote!(#ty),
+        };
+        item.sig.output = parse_quote! {
+            -> impl ::core::future::Future<Output = #output> + ::core::marker::Send
+        };
+    }
+    item
+}
+
+fn client_method(method: &Method, name: &TokenStream2) -> TokenStream2 {
+    let private = quote!(::ppppp_service::__private);
+    let sig = &method.item.sig;
+    let attrs = &method.item.attrs;
+    let params = method.params.iter().map(|(param, _)| param);
+    let args = quote! {
+        #private::args(::std::vec![#(#private::to_arg(&#params)),*])
+    };
+    let body = match (method.kind, &method.input) {
+        (MethodKind::Async, _) => quote! {
+            #private::call(&self.transport, #name, #args).await
+        },
+        (MethodKind::Source, _) => quote! {
+            #private::source(&self.transport, #name, #args)
+        },
+        (MethodKind::Sink, Some(input)) => quote! {
+            #private::sink(&self.transport, #name, #args, #input).await
+        },
+        (MethodKind::Duplex, Some(input)) => quote! {
+            #private::duplex(&self.transport, #name, #args, #input)
+        },
+        _ => unreachable!(),
+    };
+    quote! {
+        #(#attrs)*
+        #sig {
+            #body
+        }
+    }
+}
+
+fn register_method(method: &Method, name: &TokenStream2) -> TokenStream2 {
+    let private = quote!(::ppppp_service::__private);
+    let ident = &method.item.sig.ident;
+    let params: Vec<_> = method.params.iter().map(|(param, _)| param).collect();
+    let types = method.params.iter().map(|(_, ty)| ty);
+    let parse_args = quote! {
+        let mut args = args.into_iter();
+        #(let #params = #private::from_arg::<#types>(&mut args)?;)*
+    };
+    match method.kind {
+        MethodKind::Async => quote! {
+            registry.add_async(#name, move |args| {
+                let service = service.clone();
+                async move {
+                    #parse_args
+                    let output = service.#ident(#(#params),*).await?;
+                    #private::to_body(&output)
+                }
+            });
+        },
+        MethodKind::Source => quote! {
+            registry.add_source(#name, move |args| {
+                #private::rpc_stream(|| {
+                    #parse_args
+                    #private::RpcResult::Ok(service.#ident(#(#params),*))
+                })
+            });
+        },
+        MethodKind::Sink => quote! {
+            registry.add_sink(#name, move |args, input| {
+                let service = service.clone();
+                async move {
+                    #parse_args
+                    #private::RpcResult::Ok(service.#ident(#private::service_stream(input), #(#params),*).await?)
+                }
+            });
+        },
+        MethodKind::Duplex => quote! {
+            registry.add_duplex(#name, move |args, input| {
+                #private::rpc_stream(|| {
+                    #parse_args
+                    #private::RpcResult::Ok(service.#ident(#private::service_stream(input), #(#params),*))
+                })
+            });
+        },
+    }
+}
+
+fn expand(args: ServiceArgs, mut item: ItemTrait) -> syn::Result<TokenStream2> {
+    let mut methods = Vec::new();
+    for trait_item in &item.items {
+        match trait_item {
+            TraitItem::Fn(method) => methods.push(parse_method(method.clone())?),
+            other => {
+                return Err(syn::Error::new(
+                    other.span(),
+                    "service traits only have methods",
+                ))
+            }
+        }
+    }
+
+    item.items = methods
+        .iter()
+        .map(|method| TraitItem::Fn(trait_method(method)))
+        .collect();
+    item.supertraits.push(parse_quote!(::core::marker::Send));
+    item.supertraits.push(parse_quote!(::core::marker::Sync));
+    item.supertraits.push(parse_quote!('static));
+
+    let vis = &item.vis;
+    let trait_ident = &item.ident;
+    let client_ident = format_ident!("{}Client", trait_ident);
+    let server_ident = format_ident!("{}Server", trait_ident);
+    let names: Vec<TokenStream2> = methods
+        .iter()
+        .map(|method| {
+            let method_name = LitStr::new(&method.item.sig.ident.to_string(), Span::call_site());
+            match &args.name {
+                Some(name) => quote!(&[#name, #method_name]),
+                None => quote!(&[#method_name]),
+            }
+        })
+        .collect();
+    let client_methods = methods
+        .iter()
+        .zip(&names)
+        .map(|(method, name)| client_method(method, name));
+    let register_methods = methods
+        .iter()
+        .zip(&names)
+        .map(|(method, name)| register_method(method, name));
+
+    let client_doc = format!("Calls a [`{}`] over a transport.", trait_ident);
+    let server_doc = format!("Registers a [`{}`] to answer calls.", trait_ident);
+
+    Ok(quote! {
+        #item
+
+        #[doc = #client_doc]
+        #[derive(Clone, Debug)]
+        #vis struct #client_ident<T> {
+            transport: T,
+        }
+
+        impl<T: ::ppppp_service::Transport> #client_ident<T> {
+            pub fn new(transport: T) -> Self {
+                Self { transport }
+            }
+        }
+
+        impl<T: ::ppppp_service::Transport> #trait_ident for #client_ident<T> {
+            #(#client_methods)*
+        }
+
+        #[doc = #server_doc]
+        #vis struct #server_ident<S> {
+            service: ::std::sync::Arc<S>,
+        }
+
+        impl<S: #trait_ident> #server_ident<S> {
+            pub fn new(service: ::std::sync::Arc<S>) -> Self {
+                Self { service }
+            }
+        }
+
+        impl<S: #trait_ident> ::ppppp_service::__private::Service for #server_ident<S> {
+            fn register(&self, registry: &mut ::ppppp_service::__private::Registry) {
+                #({
+                    let service = self.service.clone();
+                    #register_methods
+                })*
+            }
+        }
+    })
+}
+
+/// Derive a client and a server from a service trait.
+///
+/// Each method is one of:
+///
+/// - `async fn`, called once with a response
+/// - `#[source] fn`, returning a stream
+/// - `#[sink] async fn`, taking a stream after `&self`
+/// - `#[duplex] fn`, taking a stream after `&self` and returning a stream
+///
+/// `#[service(name = "math")]` puts the methods in a group, so `add` is called as `math.add`.
+#[proc_macro_attribute]
+pub fn service(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = ServiceArgs::default();
+    let parser = syn::meta::parser(|meta| args.parse(meta));
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as ItemTrait);
+    expand(args, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
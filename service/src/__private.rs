@@ -0,0 +1,116 @@
+//! Helpers for the code derived by `#[service]`.
+
+use futures::{future, stream, StreamExt};
+use ppppp_muxrpc::{Body, RpcError, RpcStream};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{ServiceError, ServiceStream, Transport};
+
+pub use ppppp_muxrpc::{Registry, RpcResult, Service};
+
+pub fn to_arg<T: Serialize>(value: &T) -> Result<Value, ServiceError> {
+    Ok(serde_json::to_value(value)?)
+}
+
+pub fn args(args: Vec<Result<Value, ServiceError>>) -> Result<Vec<Value>, ServiceError> {
+    args.into_iter().collect()
+}
+
+/// Take the next arg, where a missing arg is null, so optional args can be left out.
+pub fn from_arg<T: DeserializeOwned>(args: &mut impl Iterator<Item = Value>) -> RpcResult<T> {
+    let arg = args.next().unwrap_or(Value::Null);
+    serde_json::from_value(arg).map_err(|err| RpcError::new(format!("invalid arg: {}", err)))
+}
+
+pub fn to_body<T: Serialize>(value: &T) -> RpcResult<Body> {
+    serde_json::to_value(value)
+        .map(Body::Json)
+        .map_err(|err| RpcError::new(err.to_string()))
+}
+
+fn error_stream<T: Send + 'static>(err: ServiceError) -> ServiceStream<T> {
+    stream::once(future::ready(Err(err))).boxed()
+}
+
+/// Encode a stream to send, which ends at the first error, since a caller only ends streams well.
+fn encode_stream<T: Serialize + Send + 'static>(
+    input: ServiceStream<T>,
+) -> stream::BoxStream<'static, Body> {
+    input
+        .scan((), |(), item| {
+            let body = item.ok().and_then(|item| to_body(&item).ok());
+            future::ready(body)
+        })
+        .boxed()
+}
+
+fn decode_stream<T: DeserializeOwned + Send + 'static>(
+    output: ServiceStream<Body>,
+) -> ServiceStream<T> {
+    output.map(|item| Ok(item?.into_json()?)).boxed()
+}
+
+pub async fn call<T: DeserializeOwned>(
+    transport: &impl Transport,
+    name: &[&str],
+    args: Result<Vec<Value>, ServiceError>,
+) -> Result<T, ServiceError> {
+    let body = transport.call(name, args?).await?;
+    Ok(body.into_json()?)
+}
+
+pub fn source<T: DeserializeOwned + Send + 'static>(
+    transport: &impl Transport,
+    name: &[&str],
+    args: Result<Vec<Value>, ServiceError>,
+) -> ServiceStream<T> {
+    match args {
+        Ok(args) => decode_stream(transport.source(name, args)),
+        Err(err) => error_stream(err),
+    }
+}
+
+pub async fn sink<T: Serialize + Send + 'static>(
+    transport: &impl Transport,
+    name: &[&str],
+    args: Result<Vec<Value>, ServiceError>,
+    input: ServiceStream<T>,
+) -> Result<(), ServiceError> {
+    transport.sink(name, args?, encode_stream(input)).await
+}
+
+pub fn duplex<In, Out>(
+    transport: &impl Transport,
+    name: &[&str],
+    args: Result<Vec<Value>, ServiceError>,
+    input: ServiceStream<In>,
+) -> ServiceStream<Out>
+where
+    In: Serialize + Send + 'static,
+    Out: DeserializeOwned + Send + 'static,
+{
+    match args {
+        Ok(args) => decode_stream(transport.duplex(name, args, encode_stream(input))),
+        Err(err) => error_stream(err),
+    }
+}
+
+/// The stream a handler was called with, as a service stream.
+pub fn service_stream<T: DeserializeOwned + Send + 'static>(input: RpcStream) -> ServiceStream<T> {
+    input.map(|item| Ok(item?.into_json()?)).boxed()
+}
+
+/// The stream a service method returned, or the error from calling it, as a handler stream.
+pub fn rpc_stream<T, F>(call: F) -> RpcStream
+where
+    T: Serialize + Send + 'static,
+    F: FnOnce() -> RpcResult<ServiceStream<T>>,
+{
+    match call() {
+        Ok(output) => output
+            .map(|item| to_body(&item.map_err(RpcError::from)?))
+            .boxed(),
+        Err(err) => stream::once(future::ready(Err(err))).boxed(),
+    }
+}
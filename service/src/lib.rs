@@ -0,0 +1,59 @@
+//! Services, whose methods are described once, as a trait, then called in process or over muxrpc.
+//!
+//! ```ignore
+//! #[ppppp_service::service(name = "math")]
+//! pub trait Math {
+//!     async fn add(&self, a: i64, b: i64) -> Result<i64, ServiceError>;
+//!     #[source]
+//!     fn count(&self, n: u64) -> ServiceStream<u64>;
+//! }
+//! ```
+//!
+//! Besides the trait, this derives a `MathServer`, which registers an implementation of the trait
+//! with a muxrpc [`Registry`](ppppp_muxrpc::Registry), and a `MathClient`, which implements the
+//! trait by calling a [`Transport`]: either [`InMemory`] in the same process, or a muxrpc
+//! [`RpcClient`](ppppp_muxrpc::RpcClient) over any byte stream.
+
+use futures::stream::BoxStream;
+use ppppp_muxrpc::{MuxRpcError, RpcError};
+
+// so the derived code can refer to this crate in its own tests
+extern crate self as ppppp_service;
+
+#[doc(hidden)]
+pub mod __private;
+mod transport;
+
+pub use crate::transport::{InMemory, Transport};
+pub use ppppp_service_derive::service;
+
+/// A stream of items, or an error which ends the stream
+pub type ServiceStream<T> = BoxStream<'static, Result<T, ServiceError>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServiceError {
+    #[error("{0}")]
+    Rpc(#[from] RpcError),
+    #[error("transport error: {0}")]
+    Transport(MuxRpcError),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl From<MuxRpcError> for ServiceError {
+    fn from(err: MuxRpcError) -> Self {
+        match err {
+            MuxRpcError::Remote(err) => ServiceError::Rpc(err),
+            err => ServiceError::Transport(err),
+        }
+    }
+}
+
+impl From<ServiceError> for RpcError {
+    fn from(err: ServiceError) -> Self {
+        match err {
+            ServiceError::Rpc(err) => err,
+            err => RpcError::new(err.to_string()),
+        }
+    }
+}
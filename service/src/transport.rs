@@ -0,0 +1,247 @@
+use futures::{
+    future::{self, BoxFuture},
+    stream::{self, BoxStream},
+    FutureExt, StreamExt, TryFutureExt, TryStreamExt,
+};
+use ppppp_muxrpc::{Body, Registry, RpcClient};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::{ServiceError, ServiceStream};
+
+/// How a service client calls methods.
+pub trait Transport: Clone + Send + Sync + 'static {
+    fn call(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+    ) -> BoxFuture<'static, Result<Body, ServiceError>>;
+
+    fn source(&self, name: &[&str], args: Vec<Value>) -> ServiceStream<Body>;
+
+    fn sink(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+        input: BoxStream<'static, Body>,
+    ) -> BoxFuture<'static, Result<(), ServiceError>>;
+
+    fn duplex(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+        input: BoxStream<'static, Body>,
+    ) -> ServiceStream<Body>;
+}
+
+/// Calls the methods in a registry in the same process, without a connection.
+#[derive(Clone)]
+pub struct InMemory {
+    registry: Arc<Registry>,
+}
+
+impl InMemory {
+    pub fn new(registry: Arc<Registry>) -> Self {
+        Self { registry }
+    }
+}
+
+impl Transport for InMemory {
+    fn call(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+    ) -> BoxFuture<'static, Result<Body, ServiceError>> {
+        self.registry.call(name, args).err_into().boxed()
+    }
+
+    fn source(&self, name: &[&str], args: Vec<Value>) -> ServiceStream<Body> {
+        self.registry.source(name, args).err_into().boxed()
+    }
+
+    fn sink(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+        input: BoxStream<'static, Body>,
+    ) -> BoxFuture<'static, Result<(), ServiceError>> {
+        let input = input.map(Ok).boxed();
+        self.registry.sink(name, args, input).err_into().boxed()
+    }
+
+    fn duplex(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+        input: BoxStream<'static, Body>,
+    ) -> ServiceStream<Body> {
+        let input = input.map(Ok).boxed();
+        self.registry.duplex(name, args, input).err_into().boxed()
+    }
+}
+
+/// Calls the methods of a remote peer over muxrpc.
+impl Transport for RpcClient {
+    fn call(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+    ) -> BoxFuture<'static, Result<Body, ServiceError>> {
+        let client = self.clone();
+        let name: Vec<String> = name.iter().map(|part| part.to_string()).collect();
+        async move { Ok(client.call(&name, args).await?) }.boxed()
+    }
+
+    fn source(&self, name: &[&str], args: Vec<Value>) -> ServiceStream<Body> {
+        match RpcClient::source(self, name, args) {
+            Ok(output) => output.err_into().boxed(),
+            Err(err) => stream::once(future::ready(Err(err.into()))).boxed(),
+        }
+    }
+
+    fn sink(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+        input: BoxStream<'static, Body>,
+    ) -> BoxFuture<'static, Result<(), ServiceError>> {
+        let (sender, done) = match RpcClient::sink(self, name, args) {
+            Ok(sink) => sink,
+            Err(err) => return future::ready(Err(err.into())).boxed(),
+        };
+        async move {
+            // the remote may end the sink before the input is done, which stops the forwarding
+            let forward = input.map(Ok).forward(sender);
+            let done = done.try_for_each(|_| future::ready(Ok(())));
+            let (_, done) = future::join(forward, done).await;
+            Ok(done?)
+        }
+        .boxed()
+    }
+
+    fn duplex(
+        &self,
+        name: &[&str],
+        args: Vec<Value>,
+        input: BoxStream<'static, Body>,
+    ) -> ServiceStream<Body> {
+        let (sender, output) = match RpcClient::duplex(self, name, args) {
+            Ok(duplex) => duplex,
+            Err(err) => return stream::once(future::ready(Err(err.into()))).boxed(),
+        };
+        // forward the input while the output is read
+        let forward =
+            stream::once(input.map(Ok).forward(sender)).filter_map(|_| future::ready(None));
+        stream::select(forward, output.err_into()).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, future::join3, stream, StreamExt, TryStreamExt};
+    use futures_ringbuf::Endpoint;
+    use ppppp_muxrpc::{connect, RpcError};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::service;
+
+    #[service(name = "math")]
+    trait Math {
+        async fn add(&self, a: i64, b: i64) -> Result<i64, ServiceError>;
+
+        async fn divide(&self, a: i64, b: i64) -> Result<i64, ServiceError>;
+
+        #[source]
+        fn count(&self, n: u64) -> ServiceStream<u64>;
+
+        #[sink]
+        async fn remember(&self, numbers: ServiceStream<i64>) -> Result<(), ServiceError>;
+
+        async fn remembered(&self) -> Result<Vec<i64>, ServiceError>;
+
+        #[duplex]
+        fn multiply(&self, numbers: ServiceStream<i64>, by: i64) -> ServiceStream<i64>;
+    }
+
+    #[derive(Default)]
+    struct Calculator {
+        remembered: Mutex<Vec<i64>>,
+    }
+
+    impl Math for Calculator {
+        async fn add(&self, a: i64, b: i64) -> Result<i64, ServiceError> {
+            Ok(a + b)
+        }
+
+        async fn divide(&self, a: i64, b: i64) -> Result<i64, ServiceError> {
+            a.checked_div(b)
+                .ok_or_else(|| RpcError::new("divide by zero").into())
+        }
+
+        fn count(&self, n: u64) -> ServiceStream<u64> {
+            stream::iter((0..n).map(Ok)).boxed()
+        }
+
+        async fn remember(&self, numbers: ServiceStream<i64>) -> Result<(), ServiceError> {
+            let numbers: Vec<i64> = numbers.try_collect().await?;
+            self.remembered.lock().unwrap().extend(numbers);
+            Ok(())
+        }
+
+        async fn remembered(&self) -> Result<Vec<i64>, ServiceError> {
+            Ok(self.remembered.lock().unwrap().clone())
+        }
+
+        fn multiply(&self, numbers: ServiceStream<i64>, by: i64) -> ServiceStream<i64> {
+            numbers.map_ok(move |n| n * by).boxed()
+        }
+    }
+
+    fn numbers() -> ServiceStream<i64> {
+        stream::iter([1, 2, 3].map(Ok)).boxed()
+    }
+
+    async fn use_math<M: Math>(math: &M) -> Result<(), ServiceError> {
+        assert_eq!(math.add(1, 2).await?, 3);
+        match math.divide(1, 0).await {
+            Err(ServiceError::Rpc(err)) => assert_eq!(err.message, "divide by zero"),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        let counted: Vec<u64> = math.count(3).try_collect().await?;
+        assert_eq!(counted, vec![0, 1, 2]);
+        math.remember(numbers()).await?;
+        assert_eq!(math.remembered().await?, vec![1, 2, 3]);
+        let multiplied: Vec<i64> = math.multiply(numbers(), 10).try_collect().await?;
+        assert_eq!(multiplied, vec![10, 20, 30]);
+        Ok(())
+    }
+
+    fn registry() -> Arc<Registry> {
+        let mut registry = Registry::new();
+        registry.register(&MathServer::new(Arc::new(Calculator::default())));
+        Arc::new(registry)
+    }
+
+    #[test]
+    fn call_directly_and_in_memory() -> Result<(), ServiceError> {
+        block_on(use_math(&Calculator::default()))?;
+        block_on(use_math(&MathClient::new(InMemory::new(registry()))))
+    }
+
+    #[test]
+    fn call_remotely() -> Result<(), ServiceError> {
+        let (client_stream, server_stream) = Endpoint::pair(256, 256);
+        let (client, client_connection) = connect(client_stream, Arc::new(Registry::new()));
+        let (_, server_connection) = connect(server_stream, registry());
+        let math = MathClient::new(client.clone());
+        let (client_result, server_result, result) =
+            block_on(join3(client_connection, server_connection, async {
+                use_math(&math).await?;
+                Ok::<_, ServiceError>(client.close()?)
+            }));
+        client_result.unwrap();
+        server_result.unwrap();
+        result
+    }
+}
@@ -0,0 +1,24 @@
+//! Sets of strings, as a CRDT in a feed of msgs.
+//!
+//! - [staltz/ppppp-set](https://github.com/staltz/ppppp-set/blob/master/protospec.md)
+//!
+//! Each change to an account's set is a msg in the account's feed for the domain
+//! `set_v1__<subdomain>`, which adds or deletes items. The set is the msgs applied in the
+//! topological order of the feed tangle, so peers with the same msgs agree on the set.
+//!
+//! The "item roots" of an item are the msgs which last added or deleted it. The msgs before
+//! them can be pruned, see [`Tangle::prunable`](ppppp_msg::Tangle::prunable).
+
+use ppppp_msg::{MsgDomain, MsgDomainDeserializeError};
+
+mod set;
+
+pub use crate::set::{Set, SetError, SetMsgData, SetUpdateOpts};
+
+/// The prefix of the domain of every set feed
+pub const DOMAIN_PREFIX: &str = "set_v1__";
+
+/// The domain of the feed for a set with this subdomain
+pub fn domain(subdomain: &str) -> Result<MsgDomain, MsgDomainDeserializeError> {
+    MsgDomain::try_from(format!("{}{}", DOMAIN_PREFIX, subdomain))
+}
@@ -0,0 +1,363 @@
+// https://github.com/staltz/ppppp-set/blob/master/protospec.md
+
+use ppppp_crypto::SignKeypair;
+use ppppp_msg::{AccountId, Msg, MsgCreateOpts, MsgData, MsgDomain, MsgError, MsgId, Tangle};
+use serde::{Deserialize, Serialize};
+use serde_json::Error as JsonError;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use typed_builder::TypedBuilder;
+
+use crate::DOMAIN_PREFIX;
+
+/// The data of a set msg
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SetMsgData {
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub del: Vec<String>,
+    /// The item roots of the added or deleted items, which this msg replaces
+    #[serde(default)]
+    pub supersedes: Vec<MsgId>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SetError {
+    #[error("msg error: {0}")]
+    Msg(#[from] MsgError),
+    #[error("tangle {root_msg_id} is not a set feed")]
+    NotASetTangle { root_msg_id: MsgId },
+    #[error("invalid set msg data in {msg_id}: {source}")]
+    Data {
+        msg_id: MsgId,
+        #[source]
+        source: JsonError,
+    },
+}
+
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct SetUpdateOpts {
+    /// The item to add or delete
+    #[builder(setter(into))]
+    pub item: String,
+    #[builder(default, setter(into))]
+    pub account_tips: Option<Vec<MsgId>>,
+    #[builder(setter(into))]
+    pub sign_keypair: SignKeypair,
+}
+
+/// The set of an account for a subdomain, and the item roots of each item.
+#[derive(Clone, Debug)]
+pub struct Set {
+    account_id: MsgId,
+    domain: MsgDomain,
+    tangle: Tangle,
+    items: BTreeSet<String>,
+    item_roots: BTreeMap<String, HashSet<MsgId>>,
+}
+
+impl Set {
+    /// Read a set from its feed tangle.
+    ///
+    /// Msgs which are missing or erased are skipped, as they were pruned.
+    pub fn from_tangle<GetMsg>(tangle: &Tangle, mut get_msg: GetMsg) -> Result<Self, SetError>
+    where
+        GetMsg: FnMut(&MsgId) -> Option<Msg>,
+    {
+        let root_msg_id = *tangle.get_id();
+        let moot = tangle
+            .get_moot_details()
+            .filter(|moot| moot.domain.0.starts_with(DOMAIN_PREFIX))
+            .ok_or(SetError::NotASetTangle { root_msg_id })?;
+        let AccountId::Tangle(account_id) = moot.account_id else {
+            return Err(SetError::NotASetTangle { root_msg_id });
+        };
+
+        let mut set = Self {
+            account_id,
+            domain: moot.domain,
+            tangle: tangle.clone(),
+            items: BTreeSet::new(),
+            item_roots: BTreeMap::new(),
+        };
+        for msg_id in tangle.topo_sort() {
+            if msg_id == root_msg_id {
+                continue;
+            }
+            if let Some(msg) = get_msg(&msg_id) {
+                if !msg.data().is_null() {
+                    set.apply(&msg_id, &msg)?;
+                }
+            }
+        }
+        Ok(set)
+    }
+
+    /// Apply a set msg, after the msgs it comes after.
+    ///
+    /// Of concurrent adds and deletes of an item, the last in topological order wins, whatever
+    /// order they are applied in.
+    pub fn apply(&mut self, msg_id: &MsgId, msg: &Msg) -> Result<(), SetError> {
+        let data: SetMsgData =
+            serde_json::from_value(msg.data().to_value()).map_err(|source| SetError::Data {
+                msg_id: *msg_id,
+                source,
+            })?;
+        if !self.tangle.has(msg_id) {
+            self.tangle.add(msg_id, msg);
+        }
+
+        for item in data.add {
+            let roots = self.item_roots.entry(item.clone()).or_default();
+            if self.tangle.replace_roots(roots, msg_id, &data.supersedes) {
+                self.items.insert(item);
+            }
+        }
+        for item in data.del {
+            let roots = self.item_roots.entry(item.clone()).or_default();
+            if self.tangle.replace_roots(roots, msg_id, &data.supersedes) {
+                self.items.remove(&item);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has(&self, item: &str) -> bool {
+        self.items.contains(item)
+    }
+
+    /// The items, in order
+    pub fn items(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The msgs which last added or deleted the item
+    pub fn item_roots(&self, item: &str) -> Vec<MsgId> {
+        let mut roots: Vec<MsgId> = self
+            .item_roots
+            .get(item)
+            .map(|roots| roots.iter().copied().collect())
+            .unwrap_or_default();
+        roots.sort();
+        roots
+    }
+
+    /// The item roots which no other item root comes after, the earliest msgs needed to know the
+    /// set.
+    pub fn min_item_roots(&self) -> Vec<MsgId> {
+        self.tangle.min_roots(self.item_roots.values().flatten())
+    }
+
+    /// The depth in the feed tangle of the earliest msg needed to know the set
+    pub fn min_required_depth(&self) -> u64 {
+        self.tangle
+            .min_required_depth(self.item_roots.values().flatten())
+    }
+
+    /// The msgs in the feed which come before every item root, which can be pruned.
+    pub fn prunable(&self) -> Vec<MsgId> {
+        self.tangle.prunable(self.item_roots.values().flatten())
+    }
+
+    /// Create a msg which adds an item, superseding its item roots.
+    ///
+    /// Returns `None` if the item is already in the set. The msg is not applied: add it to the
+    /// store, then [`Set::apply`] it.
+    pub fn create_add(&self, opts: SetUpdateOpts) -> Result<Option<Msg>, SetError> {
+        if self.has(&opts.item) {
+            return Ok(None);
+        }
+        let data = SetMsgData {
+            add: vec![opts.item.clone()],
+            del: Vec::new(),
+            supersedes: self.item_roots(&opts.item),
+        };
+        self.create(data, opts).map(Some)
+    }
+
+    /// Create a msg which deletes an item, superseding its item roots.
+    ///
+    /// Returns `None` if the item is not in the set. The msg is not applied: add it to the
+    /// store, then [`Set::apply`] it.
+    pub fn create_del(&self, opts: SetUpdateOpts) -> Result<Option<Msg>, SetError> {
+        if !self.has(&opts.item) {
+            return Ok(None);
+        }
+        let data = SetMsgData {
+            add: Vec::new(),
+            del: vec![opts.item.clone()],
+            supersedes: self.item_roots(&opts.item),
+        };
+        self.create(data, opts).map(Some)
+    }
+
+    fn create(&self, data: SetMsgData, opts: SetUpdateOpts) -> Result<Msg, SetError> {
+        let SetUpdateOpts {
+            account_tips,
+            sign_keypair,
+            ..
+        } = opts;
+        let data = serde_json::to_value(data).expect("set msg data serializes to json");
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(data).expect("set msg data is an object"))
+                .domain(self.domain.clone())
+                .sign_keypair(sign_keypair)
+                .account_id(AccountId::Tangle(self.account_id))
+                .account_tips(account_tips)
+                .tangles(HashMap::from([(
+                    *self.tangle.get_id(),
+                    self.tangle.clone(),
+                )]))
+                .build(),
+        )?;
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng};
+    use ppppp_msg_store::MsgStore;
+    use std::error::Error;
+
+    use super::*;
+
+    /// Add or delete an item, storing and applying the msg, returning whether the set changed.
+    fn update(
+        store: &mut MsgStore,
+        set: &mut Set,
+        item: &str,
+        add: bool,
+        keypair: &SignKeypair,
+    ) -> Result<bool, Box<dyn Error>> {
+        let opts = SetUpdateOpts::builder()
+            .item(item)
+            .sign_keypair(keypair.clone())
+            .build();
+        let msg = if add {
+            set.create_add(opts)?
+        } else {
+            set.create_del(opts)?
+        };
+        let Some(msg) = msg else {
+            return Ok(false);
+        };
+        let msg_id = store.add_validated(msg.clone(), set.tangle.get_id())?;
+        set.apply(&msg_id, &msg)?;
+        Ok(true)
+    }
+
+    #[test]
+    fn add_del_and_prune() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut store = MsgStore::open(dir.path().join("log"))?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        store.add_validated(account, &account_id)?;
+        let moot = Msg::create_moot(
+            AccountId::Tangle(account_id),
+            crate::domain("follows")?,
+            keypair.clone(),
+        )?;
+        let moot_id = moot.id()?;
+        store.add_validated(moot, &moot_id)?;
+
+        let tangle = store.tangle(&moot_id)?.unwrap();
+        let mut set = Set::from_tangle(&tangle, |_| None)?;
+        assert!(set.is_empty());
+        assert!(update(&mut store, &mut set, "alice", true, &keypair)?);
+        assert!(update(&mut store, &mut set, "bob", true, &keypair)?);
+        assert!(!update(&mut store, &mut set, "alice", true, &keypair)?);
+        let [add_alice] = set.item_roots("alice")[..] else {
+            panic!("alice has one item root");
+        };
+        let [add_bob] = set.item_roots("bob")[..] else {
+            panic!("bob has one item root");
+        };
+        assert!(update(&mut store, &mut set, "alice", false, &keypair)?);
+        assert!(!update(&mut store, &mut set, "carol", false, &keypair)?);
+
+        let [del_alice] = set.item_roots("alice")[..] else {
+            panic!("alice has one item root");
+        };
+        let del_msg = store.get(&del_alice)?.unwrap();
+        let del_data: SetMsgData = serde_json::from_value(del_msg.data().to_value())?;
+        assert_eq!(del_data.supersedes, vec![add_alice]);
+
+        assert_eq!(set.items().collect::<Vec<_>>(), vec!["bob"]);
+        assert_eq!(set.min_item_roots(), vec![add_bob]);
+        assert_eq!(set.prunable(), vec![add_alice]);
+
+        // the set is the same without the pruned msgs
+        store.del(&add_alice)?;
+        let tangle = store.tangle(&moot_id)?.unwrap();
+        let set = Set::from_tangle(&tangle, |msg_id| store.get(msg_id).ok().flatten())?;
+        assert_eq!(set.items().collect::<Vec<_>>(), vec!["bob"]);
+        assert_eq!(set.item_roots("alice"), vec![del_alice]);
+        assert!(set.prunable().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_add_after_del() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = account.id()?;
+        let moot = Msg::create_moot(
+            AccountId::Tangle(account_id),
+            crate::domain("follows")?,
+            keypair.clone(),
+        )?;
+        let moot_id = moot.id()?;
+        let mut tangle = Tangle::new(moot_id);
+        tangle.add(&moot_id, &moot);
+        let mut set = Set::from_tangle(&tangle, |_| None)?;
+
+        let opts = |account_tips: Option<Vec<MsgId>>| {
+            SetUpdateOpts::builder()
+                .item("alice")
+                .account_tips(account_tips)
+                .sign_keypair(keypair.clone())
+                .build()
+        };
+        // another device adds the item at the same time, but its msg is applied last
+        let concurrent = set.create_add(opts(Some(vec![account_id])))?.unwrap();
+        let concurrent_id = concurrent.id()?;
+        let add = set.create_add(opts(None))?.unwrap();
+        let add_id = add.id()?;
+        set.apply(&add_id, &add)?;
+        let del = set.create_del(opts(None))?.unwrap();
+        let del_id = del.id()?;
+        set.apply(&del_id, &del)?;
+        set.apply(&concurrent_id, &concurrent)?;
+        assert!(!set.has("alice"));
+
+        let msgs = HashMap::from([(add_id, add), (del_id, del), (concurrent_id, concurrent)]);
+        let read = Set::from_tangle(&set.tangle, |msg_id| msgs.get(msg_id).cloned())?;
+        assert_eq!(
+            read.items().collect::<Vec<_>>(),
+            set.items().collect::<Vec<_>>()
+        );
+        assert_eq!(read.item_roots("alice"), set.item_roots("alice"));
+        Ok(())
+    }
+}
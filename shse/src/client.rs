@@ -0,0 +1,168 @@
+use ppppp_bytes::{AsBytes, FromBytes, ToBytes};
+use ppppp_crypto::{SignKeypair, Signature, VerifyingKey};
+
+use crate::{
+    crypto::{
+        auth, auth_verify, dh, ephemeral_secret, hash, long_term_secret, secretbox_open,
+        secretbox_seal,
+    },
+    EphemeralKeypair, Extra, HandshakeError, HandshakeOutcome, NetworkKey, CLIENT_AUTH_LENGTH,
+    HELLO_LENGTH, SERVER_ACCEPT_LENGTH,
+};
+
+/// The client side of a handshake, before the server has said hello.
+#[derive(Debug)]
+pub struct Client {
+    network_key: NetworkKey,
+    keypair: SignKeypair,
+    server_key: VerifyingKey,
+    extra: Extra,
+    ephemeral: EphemeralKeypair,
+}
+
+impl Client {
+    pub fn new(
+        network_key: NetworkKey,
+        keypair: SignKeypair,
+        server_key: VerifyingKey,
+        extra: Extra,
+        ephemeral: EphemeralKeypair,
+    ) -> Self {
+        Self {
+            network_key,
+            keypair,
+            server_key,
+            extra,
+            ephemeral,
+        }
+    }
+
+    /// The client hello: our ephemeral public key, authenticated with the network key.
+    pub fn hello(&self) -> [u8; HELLO_LENGTH] {
+        let ephemeral_public = self.ephemeral.public_bytes();
+        let mut hello = [0; HELLO_LENGTH];
+        hello[..32].copy_from_slice(&auth(&self.network_key, &ephemeral_public));
+        hello[32..].copy_from_slice(&ephemeral_public);
+        hello
+    }
+
+    pub fn recv_server_hello(
+        self,
+        hello: &[u8; HELLO_LENGTH],
+    ) -> Result<ClientAuth, HandshakeError> {
+        let (tag, server_ephemeral) = hello.split_at(32);
+        if !auth_verify(&self.network_key, tag, server_ephemeral) {
+            return Err(HandshakeError::ServerHelloInvalid);
+        }
+        let server_ephemeral: [u8; 32] = server_ephemeral.try_into().unwrap();
+
+        let secret = ephemeral_secret(&self.ephemeral);
+        let shared_a = dh(secret, &server_ephemeral)?;
+        let shared_b = dh(secret, self.server_key.to_boxing_key().as_bytes())?;
+
+        let signature = self.keypair.signing_key().sign(
+            &[
+                self.network_key.as_bytes().as_slice(),
+                self.server_key.as_bytes(),
+                &hash(&[&shared_a]),
+                &self.extra,
+            ]
+            .concat(),
+        );
+        let key = hash(&[self.network_key.as_bytes(), &shared_a, &shared_b]);
+        let plaintext = [
+            signature.to_bytes().as_slice(),
+            self.keypair.verifying_key().as_bytes(),
+            &self.extra,
+        ]
+        .concat();
+        let auth = secretbox_seal(&key, &plaintext).try_into().unwrap();
+
+        Ok(ClientAuth {
+            client: self,
+            server_ephemeral,
+            shared_a,
+            shared_b,
+            signature,
+            auth,
+        })
+    }
+}
+
+/// The client side of a handshake, after the server has said hello.
+pub struct ClientAuth {
+    client: Client,
+    server_ephemeral: [u8; 32],
+    shared_a: [u8; 32],
+    shared_b: [u8; 32],
+    signature: Signature,
+    auth: [u8; CLIENT_AUTH_LENGTH],
+}
+
+impl std::fmt::Debug for ClientAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientAuth")
+            .field("server_key", &self.client.server_key)
+            .field("extra", &self.client.extra)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClientAuth {
+    /// The client auth: our signature, verifying key, and extra data, encrypted for the server.
+    pub fn auth(&self) -> [u8; CLIENT_AUTH_LENGTH] {
+        self.auth
+    }
+
+    pub fn recv_server_accept(
+        self,
+        accept: &[u8; SERVER_ACCEPT_LENGTH],
+    ) -> Result<HandshakeOutcome, HandshakeError> {
+        let Self {
+            client,
+            server_ephemeral,
+            shared_a,
+            shared_b,
+            signature,
+            ..
+        } = self;
+
+        let shared_c = dh(
+            &long_term_secret(client.keypair.signing_key()),
+            &server_ephemeral,
+        )?;
+        let key = hash(&[
+            client.network_key.as_bytes(),
+            &shared_a,
+            &shared_b,
+            &shared_c,
+        ]);
+        let plaintext = secretbox_open(&key, accept).ok_or(HandshakeError::ServerAcceptDecrypt)?;
+        let server_signature = Signature::from_bytes(&plaintext.try_into().unwrap()).unwrap();
+
+        client
+            .server_key
+            .verify(
+                &[
+                    client.network_key.as_bytes().as_slice(),
+                    &signature.to_bytes(),
+                    client.keypair.verifying_key().as_bytes(),
+                    &hash(&[&shared_a]),
+                    &client.extra,
+                ]
+                .concat(),
+                &server_signature,
+            )
+            .map_err(HandshakeError::ServerAcceptSignature)?;
+
+        Ok(HandshakeOutcome::new(
+            &client.network_key,
+            &key,
+            client.keypair.verifying_key(),
+            &client.ephemeral.public_bytes(),
+            client.server_key,
+            &server_ephemeral,
+            client.extra,
+        ))
+    }
+}
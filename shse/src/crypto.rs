@@ -0,0 +1,96 @@
+use crypto_secretbox::{aead::Aead, KeyInit, Nonce as SecretBoxNonce, XSalsa20Poly1305};
+use hmac::{Hmac, Mac};
+use ppppp_bytes::{AsBytes, ToBytes};
+use ppppp_crypto::{CryptoRngCore, SigningKey, UnboxingKey};
+use sha2::{Digest, Sha256, Sha512};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{HandshakeError, NetworkKey};
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// An ephemeral x25519 keypair, used for a single handshake
+#[derive(Clone)]
+pub struct EphemeralKeypair {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl EphemeralKeypair {
+    pub fn generate<R: CryptoRngCore + ?Sized>(rng: &mut R) -> Self {
+        let mut bytes = [0; 32];
+        rng.fill_bytes(&mut bytes);
+        Self::from_secret_bytes(bytes)
+    }
+
+    /// Create from known secret bytes, for deterministic tests.
+    pub fn from_secret_bytes(bytes: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(bytes);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    pub fn public_bytes(&self) -> [u8; 32] {
+        self.public.to_bytes()
+    }
+}
+
+impl std::fmt::Debug for EphemeralKeypair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EphemeralKeypair")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+/// HMAC-SHA-512-256, as libsodium's `crypto_auth`
+pub(crate) fn auth(network_key: &NetworkKey, message: &[u8]) -> [u8; 32] {
+    let mut mac = <HmacSha512 as Mac>::new_from_slice(network_key.as_bytes()).unwrap();
+    mac.update(message);
+    mac.finalize().into_bytes()[..32].try_into().unwrap()
+}
+
+pub(crate) fn auth_verify(network_key: &NetworkKey, tag: &[u8], message: &[u8]) -> bool {
+    let mut mac = <HmacSha512 as Mac>::new_from_slice(network_key.as_bytes()).unwrap();
+    mac.update(message);
+    mac.verify_truncated_left(tag).is_ok()
+}
+
+pub(crate) fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Diffie-Hellman between two x25519 keys, rejecting low order public keys.
+pub(crate) fn dh(secret: &StaticSecret, public: &[u8; 32]) -> Result<[u8; 32], HandshakeError> {
+    let shared = secret.diffie_hellman(&PublicKey::from(*public));
+    if !shared.was_contributory() {
+        return Err(HandshakeError::SharedSecretNotContributory);
+    }
+    Ok(shared.to_bytes())
+}
+
+pub(crate) fn ephemeral_secret(keypair: &EphemeralKeypair) -> &StaticSecret {
+    &keypair.secret
+}
+
+pub(crate) fn long_term_secret(signing_key: &SigningKey) -> StaticSecret {
+    let unboxing_key: UnboxingKey = signing_key.to_unboxing_key();
+    StaticSecret::from(unboxing_key.to_bytes())
+}
+
+/// Encrypt with a key used only once, so the nonce is zero.
+pub(crate) fn secretbox_seal(key: &[u8; 32], plaintext: &[u8]) -> Vec<u8> {
+    XSalsa20Poly1305::new(key.into())
+        .encrypt(&SecretBoxNonce::default(), plaintext)
+        .unwrap()
+}
+
+pub(crate) fn secretbox_open(key: &[u8; 32], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    XSalsa20Poly1305::new(key.into())
+        .decrypt(&SecretBoxNonce::default(), ciphertext)
+        .ok()
+}
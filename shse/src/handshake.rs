@@ -0,0 +1,348 @@
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use ppppp_bytes::AsBytes;
+use ppppp_crypto::{CryptoRngCore, SignKeypair, SignatureError, VerifyingKey};
+
+use crate::{
+    crypto::{auth, hash},
+    Client, EphemeralKeypair, Extra, NetworkKey, Server, CLIENT_AUTH_LENGTH, HELLO_LENGTH,
+    SERVER_ACCEPT_LENGTH,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HandshakeError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("client hello was not authenticated with our network key")]
+    ClientHelloInvalid,
+    #[error("server hello was not authenticated with our network key")]
+    ServerHelloInvalid,
+    #[error("key exchange with a low order public key")]
+    SharedSecretNotContributory,
+    #[error("failed to decrypt client auth")]
+    ClientAuthDecrypt,
+    #[error("client auth has an invalid verifying key: {0}")]
+    ClientAuthVerifyingKey(#[source] SignatureError),
+    #[error("client auth signature does not verify: {0}")]
+    ClientAuthSignature(#[source] SignatureError),
+    #[error("client was rejected by the server")]
+    ClientRejected,
+    #[error("failed to decrypt server accept")]
+    ServerAcceptDecrypt,
+    #[error("server accept signature does not verify: {0}")]
+    ServerAcceptSignature(#[source] SignatureError),
+}
+
+/// The result of a successful handshake: keys and nonces for a box stream, and who we talked to.
+#[derive(Clone)]
+pub struct HandshakeOutcome {
+    pub encrypt_key: [u8; 32],
+    pub encrypt_nonce: [u8; 24],
+    pub decrypt_key: [u8; 32],
+    pub decrypt_nonce: [u8; 24],
+    pub remote_key: VerifyingKey,
+    pub extra: Extra,
+}
+
+impl std::fmt::Debug for HandshakeOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HandshakeOutcome")
+            .field("remote_key", &self.remote_key)
+            .field("extra", &self.extra)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HandshakeOutcome {
+    pub(crate) fn new(
+        network_key: &NetworkKey,
+        key: &[u8; 32],
+        local_key: &VerifyingKey,
+        local_ephemeral: &[u8; 32],
+        remote_key: VerifyingKey,
+        remote_ephemeral: &[u8; 32],
+        extra: Extra,
+    ) -> Self {
+        let shared = hash(&[key]);
+        Self {
+            encrypt_key: hash(&[&shared, remote_key.as_bytes()]),
+            encrypt_nonce: auth(network_key, remote_ephemeral)[..24]
+                .try_into()
+                .unwrap(),
+            decrypt_key: hash(&[&shared, local_key.as_bytes()]),
+            decrypt_nonce: auth(network_key, local_ephemeral)[..24].try_into().unwrap(),
+            remote_key,
+            extra,
+        }
+    }
+}
+
+/// Do the client side of a handshake over a stream.
+pub async fn client_handshake<S, R>(
+    stream: &mut S,
+    rng: &mut R,
+    network_key: NetworkKey,
+    keypair: SignKeypair,
+    server_key: VerifyingKey,
+    extra: Extra,
+) -> Result<HandshakeOutcome, HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: CryptoRngCore + ?Sized,
+{
+    let client = Client::new(
+        network_key,
+        keypair,
+        server_key,
+        extra,
+        EphemeralKeypair::generate(rng),
+    );
+    stream.write_all(&client.hello()).await?;
+    stream.flush().await?;
+
+    let mut hello = [0; HELLO_LENGTH];
+    stream.read_exact(&mut hello).await?;
+    let client = client.recv_server_hello(&hello)?;
+    stream.write_all(&client.auth()).await?;
+    stream.flush().await?;
+
+    let mut accept = [0; SERVER_ACCEPT_LENGTH];
+    stream.read_exact(&mut accept).await?;
+    client.recv_server_accept(&accept)
+}
+
+/// Do the server side of a handshake over a stream.
+///
+/// Once the client has authenticated, `authorize` decides whether to accept the client, given its
+/// verifying key and extra data.
+pub async fn server_handshake<S, R, A>(
+    stream: &mut S,
+    rng: &mut R,
+    network_key: NetworkKey,
+    keypair: SignKeypair,
+    authorize: A,
+) -> Result<HandshakeOutcome, HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: CryptoRngCore + ?Sized,
+    A: FnOnce(&VerifyingKey, &Extra) -> bool,
+{
+    let server = Server::new(network_key, keypair, EphemeralKeypair::generate(rng));
+
+    let mut hello = [0; HELLO_LENGTH];
+    stream.read_exact(&mut hello).await?;
+    let server = server.recv_client_hello(&hello)?;
+    stream.write_all(&server.hello()).await?;
+    stream.flush().await?;
+
+    let mut auth = [0; CLIENT_AUTH_LENGTH];
+    stream.read_exact(&mut auth).await?;
+    let server = server.recv_client_auth(&auth)?;
+    if !authorize(server.client_key(), server.extra()) {
+        return Err(HandshakeError::ClientRejected);
+    }
+
+    let (accept, outcome) = server.accept();
+    stream.write_all(&accept).await?;
+    stream.flush().await?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, future::join};
+    use futures_ringbuf::Endpoint;
+    use ppppp_bytes::FromBytes;
+    use ppppp_crypto::{OsRng, SigningKey};
+
+    use super::*;
+    use crate::NO_EXTRA;
+
+    fn keypair(seed: u8) -> SignKeypair {
+        SignKeypair::from_signing_key(SigningKey::from_bytes(&[seed; 32]).unwrap())
+    }
+
+    #[test]
+    fn state_machines_agree() -> Result<(), HandshakeError> {
+        let network_key = NetworkKey::from_bytes(&[1; 32]).unwrap();
+        let client_keypair = keypair(2);
+        let server_keypair = keypair(3);
+        let extra = [4; 32];
+
+        let client = Client::new(
+            network_key.clone(),
+            client_keypair.clone(),
+            server_keypair.verifying_key().clone(),
+            extra,
+            EphemeralKeypair::from_secret_bytes([5; 32]),
+        );
+        let server = Server::new(
+            network_key,
+            server_keypair.clone(),
+            EphemeralKeypair::from_secret_bytes([6; 32]),
+        );
+
+        let server = server.recv_client_hello(&client.hello())?;
+        let client = client.recv_server_hello(&server.hello())?;
+        let server = server.recv_client_auth(&client.auth())?;
+        assert_eq!(server.client_key(), client_keypair.verifying_key());
+        assert_eq!(server.extra(), &extra);
+        let (accept, server_outcome) = server.accept();
+        let client_outcome = client.recv_server_accept(&accept)?;
+
+        assert_eq!(client_outcome.encrypt_key, server_outcome.decrypt_key);
+        assert_eq!(client_outcome.encrypt_nonce, server_outcome.decrypt_nonce);
+        assert_eq!(client_outcome.decrypt_key, server_outcome.encrypt_key);
+        assert_eq!(client_outcome.decrypt_nonce, server_outcome.encrypt_nonce);
+        assert_eq!(&client_outcome.remote_key, server_keypair.verifying_key());
+        assert_eq!(&server_outcome.remote_key, client_keypair.verifying_key());
+        assert_eq!(server_outcome.extra, extra);
+        assert!(!format!("{:?}", client_outcome).contains("key: ["));
+        Ok(())
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// The hellos and box stream keys and nonces of a fixed handshake, pinned so the key schedule
+    /// doesn't change by accident. The hellos and box keys were checked against node's `crypto`,
+    /// but these are not vectors published with secret-handshake-ext, which are still missing.
+    #[test]
+    fn regression_answers() -> Result<(), HandshakeError> {
+        let network_key = NetworkKey::from_bytes(&[1; 32]).unwrap();
+        let client = Client::new(
+            network_key.clone(),
+            keypair(2),
+            keypair(3).verifying_key().clone(),
+            [4; 32],
+            EphemeralKeypair::from_secret_bytes([5; 32]),
+        );
+        let server = Server::new(
+            network_key,
+            keypair(3),
+            EphemeralKeypair::from_secret_bytes([6; 32]),
+        );
+
+        let client_hello = client.hello();
+        let server = server.recv_client_hello(&client_hello)?;
+        let server_hello = server.hello();
+        let client = client.recv_server_hello(&server_hello)?;
+        let client_auth = client.auth();
+        let server = server.recv_client_auth(&client_auth)?;
+        let (server_accept, _) = server.accept();
+        let outcome = client.recv_server_accept(&server_accept)?;
+
+        assert_eq!(
+            hex(&client_hello),
+            "e4e3d970a288b53ff3325cfadecce576a16660228cdc23845b24b423bc404c1f\
+             50a61409b1ddd0325e9b16b700e719e9772c07000b1bd7786e907c653d20495d"
+        );
+        assert_eq!(
+            hex(&server_hello),
+            "7e2bd2010dea33f69c8b39b241f749ecb86829e3a5e017dbe4b5f686e39ad846\
+             f5b2d6e60f9477e310c2982daaa6c9136c108a1777c5947e448fa37d68174557"
+        );
+        assert_eq!(
+            hex(&client_auth),
+            "e6999d0f1cd446df31575cef1cde6cd1f6218390312bcea19ce022f762fed895\
+             e156427994d1aaf5b5221f07852b9ab255a1ea75b4cc73b81c3e9e434fb58bf5\
+             2964136cd8ab2f3ebc7b7a5cdbbf689b885e7dc25718942a1cc8750c7ddfe3d4\
+             e3cc6daeb5e3bc46c5fba6198b6beef81fc0e26163a9d9405c50c63536c54644\
+             99222f5449abb1c44ed960631028d480"
+        );
+        assert_eq!(
+            hex(&server_accept),
+            "8e69c4fb35998020a9be08995474f70c5ecae1b4f2ae5e61c55aa9a8ca650333\
+             03b75ba2e940ead7ebd366fce29de7a4eb3693e2ec318ca6ca105474508e6b78\
+             db0b9d814c134393edbb0a260c5e1646"
+        );
+        assert_eq!(
+            hex(&outcome.encrypt_key),
+            "26397e2380b5c0a27b882469b1c03eeeb07f1d2a224445e95ee2ba28c09d4614"
+        );
+        assert_eq!(
+            hex(&outcome.encrypt_nonce),
+            "7e2bd2010dea33f69c8b39b241f749ecb86829e3a5e017db"
+        );
+        assert_eq!(
+            hex(&outcome.decrypt_key),
+            "8b72ab8d156202d363181579556dcf9f47fc6baf055c877afe0045253159fa81"
+        );
+        assert_eq!(
+            hex(&outcome.decrypt_nonce),
+            "e4e3d970a288b53ff3325cfadecce576a16660228cdc2384"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_over_stream() {
+        let network_key = NetworkKey::generate(&mut OsRng);
+        let client_keypair = SignKeypair::generate(&mut OsRng);
+        let server_keypair = SignKeypair::generate(&mut OsRng);
+        let (mut client_stream, mut server_stream) = Endpoint::pair(1024, 1024);
+
+        let (client_outcome, server_outcome) = block_on(join(
+            client_handshake(
+                &mut client_stream,
+                &mut OsRng,
+                network_key.clone(),
+                client_keypair.clone(),
+                server_keypair.verifying_key().clone(),
+                NO_EXTRA,
+            ),
+            server_handshake(
+                &mut server_stream,
+                &mut OsRng,
+                network_key,
+                server_keypair,
+                |client_key, _| client_key == client_keypair.verifying_key(),
+            ),
+        ));
+        let (client_outcome, server_outcome) = (client_outcome.unwrap(), server_outcome.unwrap());
+        assert_eq!(client_outcome.encrypt_key, server_outcome.decrypt_key);
+        assert_eq!(client_outcome.decrypt_key, server_outcome.encrypt_key);
+    }
+
+    #[test]
+    fn handshake_failures() {
+        let network_key = NetworkKey::from_bytes(&[1; 32]).unwrap();
+        let client_keypair = keypair(2);
+        let server_keypair = keypair(3);
+        let client = |network_key: NetworkKey, server_key: &VerifyingKey| {
+            Client::new(
+                network_key,
+                client_keypair.clone(),
+                server_key.clone(),
+                NO_EXTRA,
+                EphemeralKeypair::generate(&mut OsRng),
+            )
+        };
+        let server = || {
+            Server::new(
+                network_key.clone(),
+                server_keypair.clone(),
+                EphemeralKeypair::generate(&mut OsRng),
+            )
+        };
+
+        // a client of another network
+        let other_network = client(
+            NetworkKey::from_bytes(&[9; 32]).unwrap(),
+            server_keypair.verifying_key(),
+        );
+        assert!(matches!(
+            server().recv_client_hello(&other_network.hello()),
+            Err(HandshakeError::ClientHelloInvalid)
+        ));
+
+        // a client which expects to talk to someone else
+        let wrong_server = client(network_key.clone(), keypair(4).verifying_key());
+        let server = server().recv_client_hello(&wrong_server.hello()).unwrap();
+        let wrong_server = wrong_server.recv_server_hello(&server.hello()).unwrap();
+        assert!(matches!(
+            server.recv_client_auth(&wrong_server.auth()),
+            Err(HandshakeError::ClientAuthDecrypt)
+        ));
+    }
+}
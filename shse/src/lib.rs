@@ -0,0 +1,32 @@
+//! Secret handshake, extended with extra data from the client.
+//!
+//! - [staltz/secret-handshake-ext](https://github.com/staltz/secret-handshake-ext)
+//! - [Scuttlebutt Protocol Guide: Handshake](https://ssbc.github.io/scuttlebutt-protocol-guide/#handshake)
+//!
+//! [`Client`] and [`Server`] are state machines which don't do any io, so they can be driven over
+//! any transport. [`client_handshake`] and [`server_handshake`] drive them over an async stream.
+
+mod client;
+mod crypto;
+mod handshake;
+mod server;
+
+pub use crate::client::{Client, ClientAuth};
+pub use crate::crypto::EphemeralKeypair;
+pub use crate::handshake::{client_handshake, server_handshake, HandshakeError, HandshakeOutcome};
+pub use crate::server::{Server, ServerAccept, ServerHello};
+pub use ppppp_caps::NetworkKey;
+
+/// The length of the extra data the client sends to the server
+pub const EXTRA_LENGTH: usize = 32;
+/// The extra data of a client with nothing extra to say
+pub const NO_EXTRA: Extra = [0; EXTRA_LENGTH];
+
+pub type Extra = [u8; EXTRA_LENGTH];
+
+/// The length of a client or server hello: an hmac and an ephemeral public key
+pub const HELLO_LENGTH: usize = 64;
+/// The length of a client auth: a box of a signature, a verifying key, and the extra data
+pub const CLIENT_AUTH_LENGTH: usize = 16 + 64 + 32 + EXTRA_LENGTH;
+/// The length of a server accept: a box of a signature
+pub const SERVER_ACCEPT_LENGTH: usize = 16 + 64;
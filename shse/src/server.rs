@@ -0,0 +1,205 @@
+use ppppp_bytes::{AsBytes, FromBytes, ToBytes};
+use ppppp_crypto::{SignKeypair, Signature, VerifyingKey};
+
+use crate::{
+    crypto::{
+        auth, auth_verify, dh, ephemeral_secret, hash, long_term_secret, secretbox_open,
+        secretbox_seal,
+    },
+    EphemeralKeypair, Extra, HandshakeError, HandshakeOutcome, NetworkKey, CLIENT_AUTH_LENGTH,
+    EXTRA_LENGTH, HELLO_LENGTH, SERVER_ACCEPT_LENGTH,
+};
+
+/// The server side of a handshake, before the client has said hello.
+#[derive(Debug)]
+pub struct Server {
+    network_key: NetworkKey,
+    keypair: SignKeypair,
+    ephemeral: EphemeralKeypair,
+}
+
+impl Server {
+    pub fn new(network_key: NetworkKey, keypair: SignKeypair, ephemeral: EphemeralKeypair) -> Self {
+        Self {
+            network_key,
+            keypair,
+            ephemeral,
+        }
+    }
+
+    pub fn recv_client_hello(
+        self,
+        hello: &[u8; HELLO_LENGTH],
+    ) -> Result<ServerHello, HandshakeError> {
+        let (tag, client_ephemeral) = hello.split_at(32);
+        if !auth_verify(&self.network_key, tag, client_ephemeral) {
+            return Err(HandshakeError::ClientHelloInvalid);
+        }
+        let client_ephemeral: [u8; 32] = client_ephemeral.try_into().unwrap();
+
+        let shared_a = dh(ephemeral_secret(&self.ephemeral), &client_ephemeral)?;
+        let shared_b = dh(
+            &long_term_secret(self.keypair.signing_key()),
+            &client_ephemeral,
+        )?;
+
+        Ok(ServerHello {
+            server: self,
+            client_ephemeral,
+            shared_a,
+            shared_b,
+        })
+    }
+}
+
+/// The server side of a handshake, after the client has said hello.
+pub struct ServerHello {
+    server: Server,
+    client_ephemeral: [u8; 32],
+    shared_a: [u8; 32],
+    shared_b: [u8; 32],
+}
+
+impl std::fmt::Debug for ServerHello {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerHello").finish_non_exhaustive()
+    }
+}
+
+impl ServerHello {
+    /// The server hello: our ephemeral public key, authenticated with the network key.
+    pub fn hello(&self) -> [u8; HELLO_LENGTH] {
+        let ephemeral_public = self.server.ephemeral.public_bytes();
+        let mut hello = [0; HELLO_LENGTH];
+        hello[..32].copy_from_slice(&auth(&self.server.network_key, &ephemeral_public));
+        hello[32..].copy_from_slice(&ephemeral_public);
+        hello
+    }
+
+    pub fn recv_client_auth(
+        self,
+        client_auth: &[u8; CLIENT_AUTH_LENGTH],
+    ) -> Result<ServerAccept, HandshakeError> {
+        let Self {
+            server,
+            client_ephemeral,
+            shared_a,
+            shared_b,
+        } = self;
+
+        let key = hash(&[server.network_key.as_bytes(), &shared_a, &shared_b]);
+        let plaintext =
+            secretbox_open(&key, client_auth).ok_or(HandshakeError::ClientAuthDecrypt)?;
+        let (client_signature, rest) = plaintext.split_at(64);
+        let (client_key, extra) = rest.split_at(32);
+        let client_signature = Signature::from_bytes(client_signature.try_into().unwrap()).unwrap();
+        let client_key = VerifyingKey::from_bytes(client_key.try_into().unwrap())
+            .map_err(HandshakeError::ClientAuthVerifyingKey)?;
+        let extra: Extra = extra.try_into().unwrap();
+
+        client_key
+            .verify(
+                &[
+                    server.network_key.as_bytes().as_slice(),
+                    server.keypair.verifying_key().as_bytes(),
+                    &hash(&[&shared_a]),
+                    &extra,
+                ]
+                .concat(),
+                &client_signature,
+            )
+            .map_err(HandshakeError::ClientAuthSignature)?;
+
+        let shared_c = dh(
+            ephemeral_secret(&server.ephemeral),
+            client_key.to_boxing_key().as_bytes(),
+        )?;
+
+        let key = hash(&[
+            server.network_key.as_bytes(),
+            &shared_a,
+            &shared_b,
+            &shared_c,
+        ]);
+
+        Ok(ServerAccept {
+            server,
+            client_ephemeral,
+            client_key,
+            client_signature,
+            extra,
+            shared_a,
+            key,
+        })
+    }
+}
+
+/// The server side of a handshake, after the client has authenticated.
+///
+/// Check the client's verifying key and extra data before accepting.
+pub struct ServerAccept {
+    server: Server,
+    client_ephemeral: [u8; 32],
+    client_key: VerifyingKey,
+    client_signature: Signature,
+    extra: Extra,
+    shared_a: [u8; 32],
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for ServerAccept {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerAccept")
+            .field("client_key", &self.client_key)
+            .field("extra", &self.extra)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServerAccept {
+    pub fn client_key(&self) -> &VerifyingKey {
+        &self.client_key
+    }
+
+    pub fn extra(&self) -> &[u8; EXTRA_LENGTH] {
+        &self.extra
+    }
+
+    /// The server accept: our signature of the client's auth, encrypted for the client.
+    pub fn accept(self) -> ([u8; SERVER_ACCEPT_LENGTH], HandshakeOutcome) {
+        let Self {
+            server,
+            client_ephemeral,
+            client_key,
+            client_signature,
+            extra,
+            shared_a,
+            key,
+        } = self;
+
+        let signature = server.keypair.signing_key().sign(
+            &[
+                server.network_key.as_bytes().as_slice(),
+                &client_signature.to_bytes(),
+                client_key.as_bytes(),
+                &hash(&[&shared_a]),
+                &extra,
+            ]
+            .concat(),
+        );
+        let accept = secretbox_seal(&key, &signature.to_bytes())
+            .try_into()
+            .unwrap();
+
+        let outcome = HandshakeOutcome::new(
+            &server.network_key,
+            &key,
+            server.keypair.verifying_key(),
+            &server.ephemeral.public_bytes(),
+            client_key,
+            &client_ephemeral,
+            extra,
+        );
+        (accept, outcome)
+    }
+}
@@ -0,0 +1,88 @@
+use ppppp_bytes::AsBytes;
+use ppppp_crypto::Hasher;
+use ppppp_msg::MsgId;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+const BITS_PER_ITEM: usize = 10;
+const MIN_BITS: usize = 64;
+const HASHES: u8 = 7;
+
+/// A bloom filter of msg ids.
+///
+/// Each sync round salts the hashes with the round number, so a msg id which is a false positive
+/// in one round is unlikely to be a false positive in the next.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bloom {
+    round: u8,
+    hashes: u8,
+    bits: Vec<u8>,
+}
+
+impl Bloom {
+    pub fn new(round: u8, capacity: usize) -> Self {
+        let num_bits = (capacity * BITS_PER_ITEM).max(MIN_BITS);
+        Self {
+            round,
+            hashes: HASHES,
+            bits: vec![0; num_bits.div_ceil(8)],
+        }
+    }
+
+    pub fn round(&self) -> u8 {
+        self.round
+    }
+
+    pub fn insert(&mut self, msg_id: &MsgId) {
+        for index in self.indexes(msg_id) {
+            self.bits[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    pub fn contains(&self, msg_id: &MsgId) -> bool {
+        if self.bits.is_empty() {
+            return false;
+        }
+        self.indexes(msg_id)
+            .all(|index| self.bits[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    fn indexes(&self, msg_id: &MsgId) -> impl Iterator<Item = usize> {
+        let mut hasher = Hasher::new();
+        hasher.write_all(&[self.round]).unwrap();
+        hasher.write_all(msg_id.as_bytes()).unwrap();
+        let hash = hasher.finalize();
+        let hash = hash.as_bytes();
+
+        // double hashing: the i-th index is h1 + i * h2
+        let h1 = u64::from_le_bytes(hash[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(hash[8..16].try_into().unwrap());
+        let num_bits = (self.bits.len() * 8) as u64;
+        (0..u64::from(self.hashes))
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_bytes::FromBytes;
+
+    use super::*;
+
+    #[test]
+    fn bloom_contains_inserted() {
+        let msg_ids: Vec<MsgId> = (0..100_u8)
+            .map(|i| MsgId::from_bytes(&[i; 16]).unwrap())
+            .collect();
+        let mut bloom = Bloom::new(0, 50);
+        for msg_id in &msg_ids[..50] {
+            bloom.insert(msg_id);
+        }
+        assert!(msg_ids[..50].iter().all(|msg_id| bloom.contains(msg_id)));
+        let false_positives = msg_ids[50..]
+            .iter()
+            .filter(|msg_id| bloom.contains(msg_id))
+            .count();
+        assert!(false_positives < 5);
+    }
+}
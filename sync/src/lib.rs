@@ -0,0 +1,7 @@
+mod bloom;
+mod range;
+mod sync;
+
+pub use crate::bloom::Bloom;
+pub use crate::range::{Goal, Range};
+pub use crate::sync::{sync, SyncError, SyncMsg, BLOOM_ROUNDS};
@@ -0,0 +1,78 @@
+use ppppp_msg::Tangle;
+use serde::{Deserialize, Serialize};
+
+/// An inclusive range of tangle depths.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Range {
+    pub const EMPTY: Range = Range { min: 1, max: 0 };
+
+    pub fn new(min: u64, max: u64) -> Self {
+        Self { min, max }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    pub fn contains(&self, depth: u64) -> bool {
+        self.min <= depth && depth <= self.max
+    }
+
+    /// The range of depths we have of a tangle, or empty if we don't have its root.
+    pub fn have(tangle: Option<&Tangle>) -> Self {
+        match tangle {
+            Some(tangle) if tangle.get_root().is_ok() => Range::new(0, tangle.get_max_depth()),
+            _ => Range::EMPTY,
+        }
+    }
+}
+
+/// How much of a tangle we want to replicate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Goal {
+    /// Every msg in the tangle.
+    All,
+    /// Only the newest msgs, up to this many depths below the tip.
+    Newest(u64),
+}
+
+impl Goal {
+    /// The range of depths we want, given what the remote has.
+    ///
+    /// We always want the whole range up to the remote's newest msg, even if we have msgs as new,
+    /// because the remote might have concurrent msgs we don't.
+    pub fn want_range(&self, remote_have: Range) -> Range {
+        if remote_have.is_empty() {
+            return Range::EMPTY;
+        }
+        match *self {
+            Goal::All => Range::new(0, remote_have.max),
+            Goal::Newest(0) => Range::EMPTY,
+            Goal::Newest(count) => {
+                let max = remote_have.max;
+                let min = max.saturating_sub(count - 1).max(remote_have.min);
+                Range::new(min, max)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn want_ranges() {
+        let remote = Range::new(0, 10);
+        assert_eq!(Goal::All.want_range(remote), Range::new(0, 10));
+        assert_eq!(Goal::Newest(4).want_range(remote), Range::new(7, 10));
+        assert_eq!(Goal::Newest(20).want_range(remote), Range::new(0, 10));
+        assert!(Goal::All.want_range(Range::EMPTY).is_empty());
+        assert!(Goal::Newest(0).want_range(remote).is_empty());
+    }
+}
@@ -0,0 +1,431 @@
+use futures::{future::try_join, Sink, SinkExt, Stream, StreamExt};
+use ppppp_msg::{Msg, MsgId, Tangle};
+use ppppp_msg_store::{MsgStore, MsgStoreError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::{Bloom, Goal, Range};
+
+/// How many bloom filters are exchanged per tangle, each salted differently.
+pub const BLOOM_ROUNDS: u8 = 3;
+
+/// A msg of the sync protocol, sent between peers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SyncMsg {
+    HaveRange { id: MsgId, range: Range },
+    WantRange { id: MsgId, range: Range },
+    Bloom { id: MsgId, bloom: Bloom },
+    Msgs { id: MsgId, msgs: Vec<Msg> },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError<E> {
+    #[error("transport error: {0}")]
+    Transport(#[source] E),
+    #[error("transport closed before sync finished")]
+    Closed,
+    #[error("unexpected sync msg, expected {expected} for tangle {id}")]
+    Unexpected { expected: &'static str, id: MsgId },
+    #[error("store error: {0}")]
+    Store(#[from] MsgStoreError),
+}
+
+/// Sync tangles with a remote peer, returning the ids of the msgs we received.
+///
+/// Msgs from the remote which don't validate are skipped, rather than failing the sync.
+///
+/// Both peers must list the same tangles in the same order, though each may have its own goal.
+/// Tangles are synced one after another, so an account tangle listed before the feeds of that
+/// account is synced first, and the feed msgs can be validated against its keys.
+///
+/// For each tangle, the peers:
+///
+/// 1. exchange the range of depths they have,
+/// 2. exchange the range of depths they want,
+/// 3. exchange [`BLOOM_ROUNDS`] bloom filters of the msgs they have in the range they want,
+/// 4. send each other the msgs in the wanted range which are missing from any of the blooms.
+pub async fn sync<T, E>(
+    store: &mut MsgStore,
+    transport: T,
+    goals: &[(MsgId, Goal)],
+) -> Result<Vec<MsgId>, SyncError<E>>
+where
+    T: Sink<SyncMsg, Error = E> + Stream<Item = Result<SyncMsg, E>>,
+{
+    let (mut sink, mut stream) = transport.split();
+    let mut received = Vec::new();
+
+    for (id, goal) in goals {
+        let id = *id;
+        let tangle = store
+            .tangle(&id)?
+            .filter(|tangle| tangle.get_root().is_ok());
+
+        let local_have = Range::have(tangle.as_ref());
+        let remote_have = exchange(
+            &mut sink,
+            &mut stream,
+            SyncMsg::HaveRange {
+                id,
+                range: local_have,
+            },
+            |msg| match msg {
+                SyncMsg::HaveRange { id: msg_id, range } if msg_id == id => Some(range),
+                _ => None,
+            },
+            "have-range",
+            id,
+        )
+        .await?;
+
+        let local_want = goal.want_range(remote_have);
+        let remote_want = exchange(
+            &mut sink,
+            &mut stream,
+            SyncMsg::WantRange {
+                id,
+                range: local_want,
+            },
+            |msg| match msg {
+                SyncMsg::WantRange { id: msg_id, range } if msg_id == id => Some(range),
+                _ => None,
+            },
+            "want-range",
+            id,
+        )
+        .await?;
+
+        let local_msg_ids = tangle
+            .as_ref()
+            .map(|tangle| msg_ids_in_range(tangle, local_want))
+            .unwrap_or_default();
+        let mut remote_blooms = Vec::new();
+        for round in 0..BLOOM_ROUNDS {
+            let mut bloom = Bloom::new(round, local_msg_ids.len());
+            for msg_id in &local_msg_ids {
+                bloom.insert(msg_id);
+            }
+            let remote_bloom = exchange(
+                &mut sink,
+                &mut stream,
+                SyncMsg::Bloom { id, bloom },
+                |msg| match msg {
+                    SyncMsg::Bloom { id: msg_id, bloom } if msg_id == id => Some(bloom),
+                    _ => None,
+                },
+                "bloom",
+                id,
+            )
+            .await?;
+            remote_blooms.push(remote_bloom);
+        }
+
+        let mut msgs = Vec::new();
+        if let Some(tangle) = &tangle {
+            for msg_id in msgs_to_send(tangle, remote_have, remote_want, &remote_blooms) {
+                if let Some(msg) = store.get(&msg_id)? {
+                    msgs.push(msg);
+                }
+            }
+        }
+        let mut remote_msgs = exchange(
+            &mut sink,
+            &mut stream,
+            SyncMsg::Msgs { id, msgs },
+            |msg| match msg {
+                SyncMsg::Msgs { id: msg_id, msgs } if msg_id == id => Some(msgs),
+                _ => None,
+            },
+            "msgs",
+            id,
+        )
+        .await?;
+
+        // add parents before children, so each msg validates against the tangle so far
+        remote_msgs.sort_by_key(|msg| match msg.metadata().tangles().get(&id) {
+            Some(msg_tangle) => msg_tangle.depth(),
+            None => 0,
+        });
+        for msg in remote_msgs {
+            // an invalid msg is skipped, and so is any msg after it which can't validate without it
+            let Ok(msg_id) = msg.id() else {
+                continue;
+            };
+            if store.has(&msg_id) {
+                continue;
+            }
+            match store.add_validated(msg, &id) {
+                Ok(_) => received.push(msg_id),
+                Err(
+                    MsgStoreError::Validate(_)
+                    | MsgStoreError::Account(_)
+                    | MsgStoreError::MsgNotFound { .. },
+                ) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    Ok(received)
+}
+
+/// Send a msg to the remote while receiving the remote's msg for the same step.
+async fn exchange<Si, St, E, R>(
+    sink: &mut Si,
+    stream: &mut St,
+    msg: SyncMsg,
+    extract: impl FnOnce(SyncMsg) -> Option<R>,
+    expected: &'static str,
+    id: MsgId,
+) -> Result<R, SyncError<E>>
+where
+    Si: Sink<SyncMsg, Error = E> + Unpin,
+    St: Stream<Item = Result<SyncMsg, E>> + Unpin,
+{
+    let send = async { sink.send(msg).await.map_err(SyncError::Transport) };
+    let recv = async {
+        match stream.next().await {
+            None => Err(SyncError::Closed),
+            Some(Err(err)) => Err(SyncError::Transport(err)),
+            Some(Ok(msg)) => extract(msg).ok_or(SyncError::Unexpected { expected, id }),
+        }
+    };
+    let ((), remote) = try_join(send, recv).await?;
+    Ok(remote)
+}
+
+fn msg_ids_in_range(tangle: &Tangle, range: Range) -> Vec<MsgId> {
+    tangle
+        .topo_sort()
+        .into_iter()
+        .filter(|msg_id| range.contains(tangle.get_depth(msg_id).unwrap()))
+        .collect()
+}
+
+/// The msgs the remote wants which are missing from at least one of its blooms, in topological
+/// order.
+///
+/// If the remote only wants a slice of the tangle, the shortest path from the oldest of these to
+/// the root is sent too, so the remote can validate the slice.
+fn msgs_to_send(
+    tangle: &Tangle,
+    remote_have: Range,
+    remote_want: Range,
+    remote_blooms: &[Bloom],
+) -> Vec<MsgId> {
+    let missing: Vec<MsgId> = msg_ids_in_range(tangle, remote_want)
+        .into_iter()
+        .filter(|msg_id| !remote_blooms.iter().all(|bloom| bloom.contains(msg_id)))
+        .collect();
+    let Some(oldest) = missing.first() else {
+        return missing;
+    };
+
+    let mut msg_ids: HashSet<MsgId> = missing.iter().cloned().collect();
+    if remote_want.min > 0 {
+        msg_ids.extend(tangle.shortest_path_to_root(oldest));
+    }
+    if remote_have.is_empty() {
+        msg_ids.insert(*tangle.get_id());
+    }
+    tangle
+        .topo_sort()
+        .into_iter()
+        .filter(|msg_id| msg_ids.contains(msg_id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{
+        channel::mpsc::{unbounded, SendError, UnboundedReceiver, UnboundedSender},
+        executor::block_on,
+        future,
+    };
+    use ppppp_crypto::{Nonce, OsRng, SignKeypair};
+    use ppppp_msg::{AccountId, MsgData, MsgDomain};
+    use ppppp_msg_store::FeedPublishOpts;
+    use serde_json::json;
+    use std::{
+        error::Error,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use super::*;
+
+    struct Duplex {
+        tx: UnboundedSender<SyncMsg>,
+        rx: UnboundedReceiver<SyncMsg>,
+    }
+
+    fn duplex() -> (Duplex, Duplex) {
+        let (a_tx, b_rx) = unbounded();
+        let (b_tx, a_rx) = unbounded();
+        (Duplex { tx: a_tx, rx: a_rx }, Duplex { tx: b_tx, rx: b_rx })
+    }
+
+    impl Sink<SyncMsg> for Duplex {
+        type Error = SendError;
+
+        fn poll_ready(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), SendError>> {
+            Pin::new(&mut self.tx).poll_ready(cx)
+        }
+
+        fn start_send(mut self: Pin<&mut Self>, item: SyncMsg) -> Result<(), SendError> {
+            Pin::new(&mut self.tx).start_send(item)
+        }
+
+        fn poll_flush(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), SendError>> {
+            Pin::new(&mut self.tx).poll_flush(cx)
+        }
+
+        fn poll_close(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), SendError>> {
+            Pin::new(&mut self.tx).poll_close(cx)
+        }
+    }
+
+    impl Stream for Duplex {
+        type Item = Result<SyncMsg, SendError>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.rx.poll_next_unpin(cx).map(|msg| msg.map(Ok))
+        }
+    }
+
+    fn publish(
+        store: &mut MsgStore,
+        account_id: MsgId,
+        domain: &MsgDomain,
+        keypair: &SignKeypair,
+        text: &str,
+    ) -> Result<MsgId, Box<dyn Error>> {
+        let (msg_id, _) = store.publish(
+            FeedPublishOpts::builder()
+                .account_id(account_id)
+                .domain(domain.clone())
+                .data(MsgData::try_from(json!({ "text": text }))?)
+                .sign_keypair(keypair.clone())
+                .build(),
+        )?;
+        Ok(msg_id)
+    }
+
+    #[test]
+    fn sync_account_and_feed() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut alice = MsgStore::open(dir.path().join("alice"))?;
+        let mut bob = MsgStore::open(dir.path().join("bob"))?;
+        let mut carol = MsgStore::open(dir.path().join("carol"))?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = alice.add_validated(account.clone(), &account.id()?)?;
+        bob.add_validated(account, &account_id)?;
+
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let first = publish(&mut alice, account_id, &domain, &keypair, "first")?;
+        let moot_id = Msg::get_moot_id(AccountId::Tangle(account_id), domain.clone())?;
+        bob.add_validated(alice.get(&moot_id)?.unwrap(), &moot_id)?;
+        bob.add_validated(alice.get(&first)?.unwrap(), &moot_id)?;
+        publish(&mut alice, account_id, &domain, &keypair, "second")?;
+        publish(&mut alice, account_id, &domain, &keypair, "third")?;
+        // a concurrent msg, only bob has
+        publish(&mut bob, account_id, &domain, &keypair, "fork")?;
+
+        let goals = [(account_id, Goal::All), (moot_id, Goal::All)];
+        let (a, b) = duplex();
+        let (to_alice, to_bob) = block_on(try_join(
+            sync(&mut alice, a, &goals),
+            sync(&mut bob, b, &goals),
+        ))?;
+        assert_eq!(to_alice.len(), 1);
+        assert_eq!(to_bob.len(), 2);
+        let alice_feed = alice.feed(&account_id, &domain)?.unwrap();
+        let bob_feed = bob.feed(&account_id, &domain)?.unwrap();
+        assert_eq!(alice_feed.size(), 5);
+        assert_eq!(alice_feed.topo_sort(), bob_feed.topo_sort());
+
+        // carol starts with nothing, and only wants the newest msgs
+        let goals = [(account_id, Goal::All), (moot_id, Goal::Newest(1))];
+        let (a, c) = duplex();
+        block_on(try_join(
+            sync(
+                &mut alice,
+                a,
+                &[(account_id, Goal::All), (moot_id, Goal::All)],
+            ),
+            sync(&mut carol, c, &goals),
+        ))?;
+        let carol_feed = carol.feed(&account_id, &domain)?.unwrap();
+        assert!(carol.has(&account_id));
+        assert_eq!(carol_feed.get_max_depth(), alice_feed.get_max_depth());
+        assert!(carol_feed.has(alice_feed.topo_sort().last().unwrap()));
+        assert!(carol_feed.size() < alice_feed.size());
+        Ok(())
+    }
+
+    #[test]
+    fn skip_invalid_msgs() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut alice = MsgStore::open(dir.path().join("alice"))?;
+        let mut bob = MsgStore::open(dir.path().join("bob"))?;
+
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        let account_id = alice.add_validated(account.clone(), &account.id()?)?;
+        bob.add_validated(account, &account_id)?;
+
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let first = publish(&mut alice, account_id, &domain, &keypair, "first")?;
+        let second = publish(&mut alice, account_id, &domain, &keypair, "second")?;
+        let third = publish(&mut alice, account_id, &domain, &keypair, "third")?;
+        let moot_id = Msg::get_moot_id(AccountId::Tangle(account_id), domain)?;
+
+        // alice's second msg is tampered with on the way to bob
+        let tamper = |msg: SyncMsg| {
+            let SyncMsg::Msgs { id, msgs } = msg else {
+                return future::ready(Ok::<_, SendError>(msg));
+            };
+            let msgs = msgs
+                .into_iter()
+                .map(|msg| {
+                    let mut value = serde_json::to_value(&msg).unwrap();
+                    if value["data"]["text"] == "second" {
+                        value["data"]["text"] = json!("forged");
+                    }
+                    serde_json::from_value(value).unwrap()
+                })
+                .collect();
+            future::ready(Ok(SyncMsg::Msgs { id, msgs }))
+        };
+        let goals = [(account_id, Goal::All), (moot_id, Goal::All)];
+        let (a, b) = duplex();
+        let (_, to_bob) = block_on(try_join(
+            sync(&mut alice, a.with(tamper), &goals),
+            sync(&mut bob, b, &goals),
+        ))?;
+        // the third msg also has a lipmaa link to the moot, so it validates without the second
+        assert_eq!(to_bob, vec![moot_id, first, third]);
+        assert!(!bob.has(&second));
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+//! Threads, the discussions in weave tangles.
+//!
+//! - [ssbc/ssb-threads](https://github.com/ssbc/ssb-threads)
+//!
+//! A thread is a root msg and the replies in its weave tangle, which form a tree. A reply is under
+//! the msg in the `replyTo` field of its data. Without one, it is under the latest msg it saw,
+//! from its `prev` in the tangle.
+
+mod thread;
+
+pub use crate::thread::{AuthorSummary, Thread, ThreadError, ThreadTree, REPLY_TO_FIELD};
@@ -0,0 +1,348 @@
+use ppppp_msg::{AccountId, Msg, MsgId, Tangle, TangleMissingRootMessageError, TangleType};
+use std::collections::{BTreeMap, HashMap};
+
+/// The field of a reply's data with the id of the msg it replies to
+pub const REPLY_TO_FIELD: &str = "replyTo";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ThreadError {
+    #[error(transparent)]
+    TangleMissingRootMessage(#[from] TangleMissingRootMessageError),
+    #[error("tangle {root_msg_id} is not a weave tangle")]
+    NotAWeaveTangle { root_msg_id: MsgId },
+}
+
+/// A msg of a thread, and the replies under it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreadTree {
+    pub msg_id: MsgId,
+    pub replies: Vec<ThreadTree>,
+}
+
+/// What an author has posted in a thread
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthorSummary {
+    pub account_id: AccountId,
+    /// The number of msgs, counting the root
+    pub count: usize,
+    pub first_msg_id: MsgId,
+    pub last_msg_id: MsgId,
+}
+
+/// A thread: a root msg and the replies in its weave tangle.
+#[derive(Clone, Debug)]
+pub struct Thread {
+    tangle: Tangle,
+    msgs: HashMap<MsgId, Msg>,
+    /// The msg ids, root first, in topological order
+    msg_ids: Vec<MsgId>,
+}
+
+impl Thread {
+    /// Assemble a thread from its weave tangle.
+    ///
+    /// Replies which are missing are left out, as they were deleted.
+    pub fn from_tangle<GetMsg>(tangle: &Tangle, mut get_msg: GetMsg) -> Result<Self, ThreadError>
+    where
+        GetMsg: FnMut(&MsgId) -> Option<Msg>,
+    {
+        let root_msg_id = *tangle.get_id();
+        if tangle.get_type()? != TangleType::Weave {
+            return Err(ThreadError::NotAWeaveTangle { root_msg_id });
+        }
+
+        let mut msgs = HashMap::from([(root_msg_id, tangle.get_root()?)]);
+        let mut msg_ids = vec![root_msg_id];
+        for msg_id in tangle.topo_sort() {
+            if msg_id == root_msg_id {
+                continue;
+            }
+            if let Some(msg) = get_msg(&msg_id) {
+                msgs.insert(msg_id, msg);
+                msg_ids.push(msg_id);
+            }
+        }
+        Ok(Self {
+            tangle: tangle.clone(),
+            msgs,
+            msg_ids,
+        })
+    }
+
+    pub fn root_msg_id(&self) -> &MsgId {
+        self.tangle.get_id()
+    }
+
+    pub fn root(&self) -> &Msg {
+        &self.msgs[self.tangle.get_id()]
+    }
+
+    pub fn get(&self, msg_id: &MsgId) -> Option<&Msg> {
+        self.msgs.get(msg_id)
+    }
+
+    /// The replies, in topological order, where concurrent replies are ordered by id
+    pub fn replies(&self) -> &[MsgId] {
+        &self.msg_ids[1..]
+    }
+
+    /// The msg a reply is under: the msg in its [`REPLY_TO_FIELD`], if that is in the thread
+    /// before it.
+    ///
+    /// Otherwise, the deepest msg in its `prev`, or the root if none are in the thread.
+    pub fn parent(&self, msg_id: &MsgId) -> Option<MsgId> {
+        let root_msg_id = self.root_msg_id();
+        if msg_id == root_msg_id {
+            return None;
+        }
+        let msg = self.msgs.get(msg_id)?;
+        let reply_to = msg
+            .data()
+            .to_value()
+            .get(REPLY_TO_FIELD)
+            .and_then(|reply_to| reply_to.as_str()?.parse::<MsgId>().ok())
+            // only a msg it comes after, so the tree has no cycles
+            .filter(|reply_to| {
+                self.msgs.contains_key(reply_to) && self.tangle.precedes(reply_to, msg_id)
+            });
+        if let Some(reply_to) = reply_to {
+            return Some(reply_to);
+        }
+
+        let prev_msg_ids = msg.metadata().tangles().get(root_msg_id)?.prev_msg_ids();
+        let parent = prev_msg_ids
+            .iter()
+            .filter(|prev_msg_id| self.msgs.contains_key(prev_msg_id))
+            .max_by_key(|prev_msg_id| {
+                (
+                    self.tangle.get_depth(prev_msg_id),
+                    std::cmp::Reverse(**prev_msg_id),
+                )
+            })
+            .copied()
+            .unwrap_or(*root_msg_id);
+        Some(parent)
+    }
+
+    /// The tree of replies, from the root
+    pub fn tree(&self) -> ThreadTree {
+        let mut children: BTreeMap<MsgId, Vec<MsgId>> = BTreeMap::new();
+        for msg_id in self.replies() {
+            let parent = self.parent(msg_id).unwrap();
+            children.entry(parent).or_default().push(*msg_id);
+        }
+        build_tree(*self.root_msg_id(), &mut children)
+    }
+
+    /// What each author has posted, in order of their first msg
+    pub fn authors(&self) -> Vec<AuthorSummary> {
+        let mut authors: Vec<AuthorSummary> = Vec::new();
+        for msg_id in &self.msg_ids {
+            let account_id = self.msgs[msg_id].metadata().account_id();
+            match authors
+                .iter_mut()
+                .find(|author| &author.account_id == account_id)
+            {
+                Some(author) => {
+                    author.count += 1;
+                    author.last_msg_id = *msg_id;
+                }
+                None => authors.push(AuthorSummary {
+                    account_id: account_id.clone(),
+                    count: 1,
+                    first_msg_id: *msg_id,
+                    last_msg_id: *msg_id,
+                }),
+            }
+        }
+        authors
+    }
+
+    /// The replies which are new since the tips, as they are not the tips or before them.
+    pub fn new_since(&self, tips: &[MsgId]) -> Vec<MsgId> {
+        self.replies()
+            .iter()
+            .filter(|msg_id| {
+                !tips.contains(msg_id) && !tips.iter().any(|tip| self.tangle.precedes(msg_id, tip))
+            })
+            .copied()
+            .collect()
+    }
+}
+
+fn build_tree(msg_id: MsgId, children: &mut BTreeMap<MsgId, Vec<MsgId>>) -> ThreadTree {
+    let replies = children
+        .remove(&msg_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|reply_msg_id| build_tree(reply_msg_id, children))
+        .collect();
+    ThreadTree { msg_id, replies }
+}
+
+#[cfg(test)]
+mod tests {
+    use ppppp_crypto::{Nonce, OsRng, SignKeypair};
+    use ppppp_msg::{MsgCreateOpts, MsgData, MsgDomain};
+    use serde_json::json;
+    use std::{collections::HashMap, error::Error};
+
+    use super::*;
+
+    fn account(keypair: &SignKeypair) -> Result<AccountId, Box<dyn Error>> {
+        let account = Msg::create_account(
+            keypair.clone(),
+            "account".to_string().try_into()?,
+            None::<fn() -> Nonce>,
+        )?;
+        Ok(AccountId::Tangle(account.id()?))
+    }
+
+    fn post(
+        text: &str,
+        account_id: &AccountId,
+        keypair: &SignKeypair,
+        tangle: Option<&Tangle>,
+    ) -> Result<(MsgId, Msg), Box<dyn Error>> {
+        post_data(json!({ "text": text }), account_id, keypair, tangle)
+    }
+
+    fn post_data(
+        data: serde_json::Value,
+        account_id: &AccountId,
+        keypair: &SignKeypair,
+        tangle: Option<&Tangle>,
+    ) -> Result<(MsgId, Msg), Box<dyn Error>> {
+        let domain: MsgDomain = "post".to_string().try_into()?;
+        let msg = Msg::create(
+            MsgCreateOpts::builder()
+                .data(MsgData::try_from(data)?)
+                .domain(domain)
+                .sign_keypair(keypair.clone())
+                .account_id(account_id.clone())
+                .tangles(
+                    tangle
+                        .map(|tangle| HashMap::from([(*tangle.get_id(), tangle.clone())]))
+                        .unwrap_or_default(),
+                )
+                .build(),
+        )?;
+        Ok((msg.id()?, msg))
+    }
+
+    #[test]
+    fn thread_of_replies() -> Result<(), Box<dyn Error>> {
+        let alice_keypair = SignKeypair::generate(&mut OsRng);
+        let bob_keypair = SignKeypair::generate(&mut OsRng);
+        let alice = account(&alice_keypair)?;
+        let bob = account(&bob_keypair)?;
+
+        let (root_id, root) = post("hi", &alice, &alice_keypair, None)?;
+        let mut tangle = Tangle::new(root_id);
+        tangle.add(&root_id, &root);
+        let mut msgs = HashMap::new();
+
+        let (first_id, first) = post("hey", &bob, &bob_keypair, Some(&tangle))?;
+        tangle.add(&first_id, &first);
+        msgs.insert(first_id, first);
+
+        // alice and bob reply at the same time
+        let (alice_id, alice_reply) = post("how are you?", &alice, &alice_keypair, Some(&tangle))?;
+        let (bob_id, bob_reply) = post("what's up?", &bob, &bob_keypair, Some(&tangle))?;
+        tangle.add(&alice_id, &alice_reply);
+        tangle.add(&bob_id, &bob_reply);
+        msgs.insert(alice_id, alice_reply);
+        msgs.insert(bob_id, bob_reply);
+
+        let thread = Thread::from_tangle(&tangle, |msg_id| msgs.get(msg_id).cloned())?;
+        let mut concurrent = vec![alice_id, bob_id];
+        concurrent.sort();
+        assert_eq!(
+            thread.replies(),
+            [vec![first_id], concurrent.clone()].concat()
+        );
+
+        let leaf = |msg_id| ThreadTree {
+            msg_id,
+            replies: Vec::new(),
+        };
+        assert_eq!(
+            thread.tree(),
+            ThreadTree {
+                msg_id: root_id,
+                replies: vec![ThreadTree {
+                    msg_id: first_id,
+                    replies: concurrent.iter().copied().map(leaf).collect(),
+                }],
+            }
+        );
+
+        let authors = thread.authors();
+        assert_eq!(authors.len(), 2);
+        assert_eq!(
+            authors[0],
+            AuthorSummary {
+                account_id: alice,
+                count: 2,
+                first_msg_id: root_id,
+                last_msg_id: alice_id,
+            }
+        );
+        assert_eq!((authors[1].count, authors[1].first_msg_id), (2, first_id));
+
+        assert_eq!(thread.new_since(&[root_id]), thread.replies());
+        assert_eq!(thread.new_since(&[first_id]), concurrent);
+        assert_eq!(thread.new_since(&[alice_id]), vec![bob_id]);
+        assert!(thread.new_since(&[alice_id, bob_id]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn replies_to_the_root_in_sequence() -> Result<(), Box<dyn Error>> {
+        let keypair = SignKeypair::generate(&mut OsRng);
+        let alice = account(&keypair)?;
+
+        let (root_id, root) = post("hi", &alice, &keypair, None)?;
+        let mut tangle = Tangle::new(root_id);
+        tangle.add(&root_id, &root);
+        let mut msgs = HashMap::new();
+
+        // each reply saw the one before, but replies to the root
+        let mut reply_ids = Vec::new();
+        for text in ["one", "two"] {
+            let data = json!({ "text": text, REPLY_TO_FIELD: root_id.to_string() });
+            let (reply_id, reply) = post_data(data, &alice, &keypair, Some(&tangle))?;
+            tangle.add(&reply_id, &reply);
+            msgs.insert(reply_id, reply);
+            reply_ids.push(reply_id);
+        }
+        // without a reply to, a reply is under the latest msg it saw
+        let (last_id, last) = post("three", &alice, &keypair, Some(&tangle))?;
+        tangle.add(&last_id, &last);
+        msgs.insert(last_id, last);
+
+        let thread = Thread::from_tangle(&tangle, |msg_id| msgs.get(msg_id).cloned())?;
+        assert_eq!(thread.parent(&reply_ids[1]), Some(root_id));
+        assert_eq!(thread.parent(&last_id), Some(reply_ids[1]));
+        assert_eq!(
+            thread.tree(),
+            ThreadTree {
+                msg_id: root_id,
+                replies: vec![
+                    ThreadTree {
+                        msg_id: reply_ids[0],
+                        replies: Vec::new(),
+                    },
+                    ThreadTree {
+                        msg_id: reply_ids[1],
+                        replies: vec![ThreadTree {
+                            msg_id: last_id,
+                            replies: Vec::new(),
+                        }],
+                    },
+                ],
+            }
+        );
+        Ok(())
+    }
+}